@@ -0,0 +1,56 @@
+//! Compares scanning a `Collection` with scanning a `SoaCollection`, both
+//! 1M slots of 60-byte values with 70% of the slots occupied.
+//!
+//! Run with `cargo bench --bench layout --features soa-layout`.
+use slotvec::{Collection, SoaCollection};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const SLOTS: usize = 1_000_000;
+const ROUNDS: u32 = 50;
+
+// Big enough that `Option` pads it out, with no niche to hide the tag in
+type Value = [u32; 15];
+
+// Same as in `iter_mut`
+fn fastest(mut pass: impl FnMut()) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let start = Instant::now();
+            pass();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn sum<'a>(values: impl Iterator<Item = &'a Value>) -> u32 {
+    values.fold(0, |sum, value| sum.wrapping_add(value[0]))
+}
+
+fn main() {
+    let mut options = Collection::new();
+    let mut soa = SoaCollection::new();
+    for i in 0..SLOTS {
+        options.add([i as u32; 15]);
+        soa.add([i as u32; 15]);
+    }
+    // Vacate 3 out of every 10 slots
+    for i in 0..SLOTS {
+        if matches!(i % 10, 0 | 3 | 7) {
+            options.take(i);
+            soa.try_take(i);
+        }
+    }
+
+    let with_options = fastest(|| {
+        black_box(sum(options.iter()));
+    });
+    let with_soa = fastest(|| {
+        black_box(sum(soa.iter()));
+    });
+
+    println!("iter, {} slots, {} occupied", SLOTS, options.len());
+    println!("  Option<T> slots: {:?}", with_options);
+    println!("  SoA slots:       {:?}", with_soa);
+}
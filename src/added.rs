@@ -0,0 +1,204 @@
+//! Streaming the values added to a `ConcurrentCollection`.
+//!
+//! With the `futures` feature `stream_added` returns a `Stream` of
+//! `(index, value)` for every add after it was created, so async consumers
+//! can react to a registry growing instead of polling all of it. Every add
+//! queues its index for each live stream and wakes it; the value is cloned
+//! when the stream gets to it. Without the feature, or without streams,
+//! adds skip all of this.
+#[cfg(feature = "futures")]
+use std::collections::VecDeque;
+#[cfg(feature = "futures")]
+use std::pin::Pin;
+#[cfg(feature = "futures")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "futures")]
+use std::sync::{Mutex, PoisonError};
+#[cfg(feature = "futures")]
+use std::task::{Context, Poll, Waker};
+
+#[cfg(feature = "futures")]
+use futures_core::Stream;
+
+#[cfg(feature = "futures")]
+use crate::ConcurrentCollection;
+use crate::Version;
+
+#[cfg(feature = "futures")]
+#[derive(Default)]
+struct Subscriber {
+    added: VecDeque<(usize, Version)>,
+    waker: Option<Waker>,
+}
+
+#[cfg(feature = "futures")]
+#[derive(Default)]
+pub(crate) struct Subscribers {
+    // Indexed by subscriber id, `None` once that stream is dropped
+    queues: Mutex<Vec<Option<Subscriber>>>,
+    // Lets adds skip the lock while nobody is streaming
+    live: AtomicUsize,
+}
+
+#[cfg(not(feature = "futures"))]
+#[derive(Default)]
+pub(crate) struct Subscribers;
+
+#[cfg(feature = "futures")]
+impl Subscribers {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    // Call once the value at `index` is visible at `version`
+    pub(crate) fn added(&self, index: usize, version: Version) {
+        if self.live.load(Ordering::Acquire) == 0 {
+            return;
+        }
+        let mut wakers = Vec::new();
+        for subscriber in self.lock().iter_mut().flatten() {
+            subscriber.added.push_back((index, version));
+            wakers.extend(subscriber.waker.take());
+        }
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    fn subscribe(&self) -> usize {
+        let mut queues = self.lock();
+        // An add racing with this may or may not be streamed, one that
+        // starts after it returns always is
+        self.live.fetch_add(1, Ordering::Release);
+        match queues.iter().position(Option::is_none) {
+            Some(id) => {
+                queues[id] = Some(Subscriber::default());
+                id
+            }
+            None => {
+                queues.push(Some(Subscriber::default()));
+                queues.len() - 1
+            }
+        }
+    }
+
+    fn unsubscribe(&self, id: usize) {
+        self.lock()[id] = None;
+        self.live.fetch_sub(1, Ordering::Release);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Option<Subscriber>>> {
+        self.queues.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(not(feature = "futures"))]
+impl Subscribers {
+    pub(crate) fn new() -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    pub(crate) fn added(&self, _index: usize, _version: Version) {}
+}
+
+/// Returned by `ConcurrentCollection::stream_added`.
+#[cfg(feature = "futures")]
+#[must_use = "streams do nothing unless polled"]
+pub struct StreamAdded<'a, T> {
+    collection: &'a ConcurrentCollection<T>,
+    id: usize,
+}
+
+#[cfg(feature = "futures")]
+impl<T: Clone> Stream for StreamAdded<'_, T> {
+    type Item = (usize, T);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<(usize, T)>> {
+        let subscribers = &self.collection.subscribers;
+        loop {
+            let mut queues = subscribers.lock();
+            let subscriber = queues[self.id].as_mut().unwrap();
+            let Some((index, version)) = subscriber.added.pop_front() else {
+                subscriber.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            };
+            drop(queues);
+
+            // A value that was already taken again is skipped, its slot
+            // being filled again queues a new entry
+            if let Some(item) = self.collection.clone_if_version(index, version) {
+                return Poll::Ready(Some((index, item)));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> Drop for StreamAdded<'_, T> {
+    fn drop(&mut self) {
+        self.collection.subscribers.unsubscribe(self.id);
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> ConcurrentCollection<T> {
+    /// A stream of `(index, value)` for every value added from now on, in
+    /// the order the adds finished. Values that are taken before the stream
+    /// gets to them are skipped. It never ends, drop it to stop listening.
+    pub fn stream_added(&self) -> StreamAdded<'_, T> {
+        StreamAdded {
+            collection: self,
+            id: self.subscribers.subscribe(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "futures"))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // Same as in `waiters`, without pulling in an executor
+    fn block_on_next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match Pin::new(&mut *stream).poll_next(&mut cx) {
+                Poll::Ready(item) => return item,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn streams_adds_made_after_it_was_created() {
+        let coll = ConcurrentCollection::new();
+        coll.add("before");
+        let mut added = coll.stream_added();
+
+        let taken = coll.add("taken");
+        coll.take(taken);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(std::time::Duration::from_millis(10));
+                coll.add("b");
+            });
+            assert_eq!(block_on_next(&mut added), Some((1, "b")));
+        });
+
+        drop(added);
+        assert_eq!(coll.subscribers.live.load(Ordering::Relaxed), 0);
+        coll.add("unseen");
+    }
+}
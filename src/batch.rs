@@ -0,0 +1,261 @@
+//! All-or-nothing application of several operations.
+//!
+//! `apply_batch` checks every operation against the state the collection
+//! would be in by the time it runs before changing anything, so a batch
+//! that fails halfway through validation leaves the collection untouched.
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlotOp<T> {
+    /// Adds the value like `add` does.
+    Add(T),
+    /// Stores the value in a slot that has to be vacant.
+    Fill { index: usize, value: T },
+    /// Swaps the value in a slot that has to be occupied.
+    Replace { index: usize, value: T },
+    /// Takes the value out of a slot that has to be occupied.
+    Take(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SlotOpResult<T> {
+    Added(usize),
+    Filled,
+    Replaced(T),
+    Taken(T),
+}
+
+/// Why a batch was rejected. `op` is the position of the offending operation
+/// within the batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchError {
+    OutOfRange { op: usize, index: usize },
+    Vacant { op: usize, index: usize },
+    Occupied { op: usize, index: usize },
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchError::OutOfRange { op, index } => {
+                write!(f, "operation {}: index {} is out of range", op, index)
+            }
+            BatchError::Vacant { op, index } => {
+                write!(f, "operation {}: slot {} is vacant", op, index)
+            }
+            BatchError::Occupied { op, index } => {
+                write!(f, "operation {}: slot {} is occupied", op, index)
+            }
+        }
+    }
+}
+
+impl Error for BatchError {}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Applies `ops` in order, or none of them if any would fail.
+    ///
+    /// Automatic compaction is held off until the whole batch has been
+    /// applied, so the indices in the results refer to the slots the
+    /// operations touched. The remap callback reports any moves after that.
+    pub fn apply_batch(
+        &mut self,
+        ops: impl IntoIterator<Item = SlotOp<T>>,
+    ) -> Result<Vec<SlotOpResult<T>>, BatchError> {
+        let ops: Vec<_> = ops.into_iter().collect();
+        self.validate_batch(&ops)?;
+
+        let auto_compact = self.auto_compact.take();
+        let results = ops
+            .into_iter()
+            .map(|op| match op {
+                SlotOp::Add(value) => SlotOpResult::Added(self.add(value)),
+                SlotOp::Fill { index, value } => {
+                    self.insert_at(index, value);
+                    SlotOpResult::Filled
+                }
+                // We know the slots are occupied
+                SlotOp::Replace { index, value } => {
+                    SlotOpResult::Replaced(self.insert_at(index, value).unwrap())
+                }
+                SlotOp::Take(index) => SlotOpResult::Taken(self.try_take(index).unwrap()),
+            })
+            .collect();
+
+        self.auto_compact = auto_compact;
+        self.auto_compact();
+        self.debug_assert_consistent();
+        Ok(results)
+    }
+
+    fn validate_batch(&mut self, ops: &[SlotOp<T>]) -> Result<(), BatchError> {
+        // The simulation below predicts `add` from the free list, and `add`
+        // resyncs when it finds a stale entry there, so resync up front
+        let stale = self
+            .free
+            .iter()
+            .any(|index| self.inner.get(index).is_none_or(Option::is_some));
+        if stale {
+            self.resync();
+        }
+
+        // Slots whose occupancy the batch has changed so far
+        let mut changed: BTreeMap<usize, bool> = BTreeMap::new();
+        // The vacancies `add` would reuse, which leaves out retired slots
+        // and reserved blocks
+        let mut reusable: BTreeSet<usize> = self.free.iter().collect();
+        let mut slots = self.inner.len();
+        let is_occupied = |changed: &BTreeMap<usize, bool>, index: usize| {
+            changed
+                .get(&index)
+                .copied()
+                .unwrap_or_else(|| self.inner.get(index).is_some_and(Option::is_some))
+        };
+
+        for (op, slot_op) in ops.iter().enumerate() {
+            let index = match slot_op {
+                SlotOp::Add(_) => {
+                    let index = match reusable.pop_first() {
+                        Some(index) => index,
+                        None => {
+                            // Same as the growth path of `add`
+                            while self.generations.is_retired(slots) {
+                                slots += 1;
+                            }
+                            slots += 1;
+                            slots - 1
+                        }
+                    };
+                    changed.insert(index, true);
+                    continue;
+                }
+                SlotOp::Fill { index, .. }
+                | SlotOp::Replace { index, .. }
+                | SlotOp::Take(index) => *index,
+            };
+
+            if index >= slots {
+                return Err(BatchError::OutOfRange { op, index });
+            }
+
+            let occupied = is_occupied(&changed, index);
+            match slot_op {
+                SlotOp::Fill { .. } if occupied => {
+                    return Err(BatchError::Occupied { op, index });
+                }
+                SlotOp::Fill { .. } => {
+                    reusable.remove(&index);
+                    changed.insert(index, true);
+                }
+                SlotOp::Replace { .. } | SlotOp::Take(_) if !occupied => {
+                    return Err(BatchError::Vacant { op, index });
+                }
+                SlotOp::Take(_) => {
+                    if self.is_reusable(index) && !self.generations.retires_on_bump(index) {
+                        reusable.insert(index);
+                    }
+                    changed.insert(index, false);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_ops_see_the_effects_of_earlier_ones() {
+        let mut coll = Collection::new();
+        coll.add('a');
+        coll.add('b');
+
+        let err = coll
+            .apply_batch(vec![
+                SlotOp::Take(0),
+                SlotOp::Add('c'),
+                SlotOp::Add('d'),
+                SlotOp::Fill {
+                    index: 0,
+                    value: 'x',
+                },
+            ])
+            .unwrap_err();
+        // The first add reused slot 0
+        assert_eq!(err, BatchError::Occupied { op: 3, index: 0 });
+
+        let results = coll
+            .apply_batch(vec![
+                SlotOp::Take(0),
+                SlotOp::Add('c'),
+                SlotOp::Add('d'),
+                SlotOp::Replace {
+                    index: 2,
+                    value: 'e',
+                },
+            ])
+            .unwrap();
+        assert_eq!(
+            results,
+            vec![
+                SlotOpResult::Taken('a'),
+                SlotOpResult::Added(0),
+                SlotOpResult::Added(2),
+                SlotOpResult::Replaced('d'),
+            ]
+        );
+        assert_eq!(coll.to_owned_values(), vec!['c', 'b', 'e']);
+    }
+
+    #[test]
+    fn rejected_batch_changes_nothing() {
+        let mut coll = Collection::new();
+        coll.add(1);
+        coll.add(2);
+        coll.take(1);
+
+        let err = coll
+            .apply_batch(vec![SlotOp::Take(0), SlotOp::Add(3), SlotOp::Take(5)])
+            .unwrap_err();
+        assert_eq!(err, BatchError::OutOfRange { op: 2, index: 5 });
+        assert_eq!(err.to_string(), "operation 2: index 5 is out of range");
+
+        let err = coll.apply_batch(vec![SlotOp::Take(1)]).unwrap_err();
+        assert_eq!(err, BatchError::Vacant { op: 0, index: 1 });
+
+        assert_eq!(coll.to_owned_values(), vec![1]);
+        assert_eq!(coll.add(4), 1);
+    }
+
+    #[test]
+    fn reserved_slots_are_vacant_but_not_reused() {
+        let mut coll = Collection::new();
+        coll.add(1);
+        let block = coll.reserve_block(2);
+
+        let err = coll
+            .apply_batch(vec![SlotOp::Add(5), SlotOp::Take(1)])
+            .unwrap_err();
+        assert_eq!(err, BatchError::Vacant { op: 1, index: 1 });
+        assert_eq!(coll.len(), 1);
+
+        let results = coll
+            .apply_batch(vec![SlotOp::Add(5), SlotOp::Take(3)])
+            .unwrap();
+        assert_eq!(
+            results,
+            vec![SlotOpResult::Added(3), SlotOpResult::Taken(5)]
+        );
+        assert_eq!(coll.add_in_block(&block, 2), Ok(1));
+    }
+}
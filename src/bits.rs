@@ -0,0 +1,286 @@
+//! Occupancy as bit masks.
+//!
+//! Bit `i` is set when slot `i` is occupied. Handy for embedded hot loops
+//! where a `trailing_zeros` loop beats checking every slot. The collection
+//! keeps these bits up to date on every fill and vacate, so walking the
+//! occupied slots costs a word check per 64 slots rather than one per slot.
+//! After `IndexMut` they may be stale until the next `resync`, and the
+//! slots are scanned instead.
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+#[derive(Debug, Default)]
+pub(crate) struct OccupancyBitmap {
+    words: Vec<u64>,
+}
+
+impl OccupancyBitmap {
+    pub(crate) const fn new() -> Self {
+        Self { words: Vec::new() }
+    }
+
+    pub(crate) fn filled(&mut self, index: usize) {
+        let word = index / 64;
+        if self.words.len() <= word {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (index % 64);
+    }
+
+    pub(crate) fn vacated(&mut self, index: usize) {
+        if let Some(word) = self.words.get_mut(index / 64) {
+            *word &= !(1 << (index % 64));
+        }
+    }
+
+    pub(crate) fn moved(&mut self, old: usize, new: usize) {
+        self.vacated(old);
+        self.filled(new);
+    }
+
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        let (at_a, at_b) = (self.is_set(a), self.is_set(b));
+        self.set(a, at_b);
+        self.set(b, at_a);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.words.clear();
+    }
+
+    pub(crate) fn rebuild<T>(&mut self, slots: &[Option<T>]) {
+        self.words = slots.chunks(64).map(word_of).collect();
+    }
+
+    pub(crate) fn check<T>(&self, slots: &[Option<T>]) -> Result<(), String> {
+        let wrong = (0..slots.len().max(self.words.len() * 64))
+            .find(|&index| slots.get(index).is_some_and(Option::is_some) != self.is_set(index));
+        match wrong {
+            Some(index) => Err(format!("{} has the wrong occupancy bit", index)),
+            None => Ok(()),
+        }
+    }
+
+    fn is_set(&self, index: usize) -> bool {
+        self.words
+            .get(index / 64)
+            .is_some_and(|word| word & 1 << (index % 64) != 0)
+    }
+
+    fn set(&mut self, index: usize, occupied: bool) {
+        if occupied {
+            self.filled(index);
+        } else {
+            self.vacated(index);
+        }
+    }
+}
+
+// Occupancy of up to 64 slots, the first in the lowest bit
+fn word_of<T>(chunk: &[Option<T>]) -> u64 {
+    chunk
+        .iter()
+        .enumerate()
+        .filter(|(_, slot)| slot.is_some())
+        .fold(0, |word, (i, _)| word | 1 << i)
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Occupancy of every slot packed into a `u128`, or `None` when the
+    /// collection has more than 128 slots.
+    pub fn occupied_mask(&self) -> Option<u128> {
+        if self.inner.len() > 128 {
+            return None;
+        }
+
+        let low = self.occupancy_word(0) as u128;
+        let high = self.occupancy_word(1) as u128;
+        Some(low | high << 64)
+    }
+
+    /// Indices of the occupied slots, one 64-slot word at a time.
+    pub fn occupied_bits(&self) -> OccupiedBits<'_, T> {
+        OccupiedBits {
+            slots: &self.inner,
+            words: self.fresh_bitmap(),
+            next: 0,
+            base: 0,
+            word: 0,
+        }
+    }
+
+    /// Indices of the occupied slots in ascending order, allocated once at
+    /// the right size. This is `occupied_bits` collected.
+    pub fn occupied_indices(&self) -> Vec<usize> {
+        let mut indices = Vec::with_capacity(self.counted_occupied());
+        indices.extend(self.occupied_bits());
+        indices
+    }
+
+    /// The lowest occupied index at or after `from`. Pass the last result
+    /// plus one to step through the occupied slots.
+    pub fn next_occupied_index(&self, from: usize) -> Option<usize> {
+        if from >= self.inner.len() {
+            return None;
+        }
+        let mut word = from / 64;
+        let mut bits = self.occupancy_word(word) & !0 << (from % 64);
+        while bits == 0 {
+            word += 1;
+            if word * 64 >= self.inner.len() {
+                return None;
+            }
+            bits = self.occupancy_word(word);
+        }
+        Some(word * 64 + bits.trailing_zeros() as usize)
+    }
+
+    /// The highest occupied index at or before `from`, which may be past the
+    /// last slot.
+    pub fn prev_occupied_index(&self, from: usize) -> Option<usize> {
+        let from = from.min(self.inner.len().checked_sub(1)?);
+        let mut word = from / 64;
+        let mut bits = self.occupancy_word(word) & !0 >> (63 - from % 64);
+        while bits == 0 {
+            word = word.checked_sub(1)?;
+            bits = self.occupancy_word(word);
+        }
+        Some(word * 64 + 63 - bits.leading_zeros() as usize)
+    }
+
+    // The bitmap, unless `IndexMut` may have changed slots behind its back
+    fn fresh_bitmap(&self) -> Option<&[u64]> {
+        (!self.touched_through_index_mut).then_some(&self.bitmap.words[..])
+    }
+
+    // Occupancy of slots `64 * word` up to the next 64
+    fn occupancy_word(&self, word: usize) -> u64 {
+        match self.fresh_bitmap() {
+            Some(words) => words.get(word).copied().unwrap_or(0),
+            None => self
+                .inner
+                .get(word * 64..)
+                .map_or(0, |rest| word_of(&rest[..rest.len().min(64)])),
+        }
+    }
+}
+
+pub struct OccupiedBits<'a, T> {
+    slots: &'a [Option<T>],
+    // `None` when the slots have to be scanned instead
+    words: Option<&'a [u64]>,
+    // The next word to load
+    next: usize,
+    base: usize,
+    word: u64,
+}
+
+impl<'a, T> Iterator for OccupiedBits<'a, T> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.word == 0 {
+            let start = self.next * 64;
+            if start >= self.slots.len() {
+                return None;
+            }
+
+            self.word = match self.words {
+                Some(words) => words.get(self.next).copied().unwrap_or(0),
+                None => word_of(&self.slots[start..self.slots.len().min(start + 64)]),
+            };
+            self.base = start;
+            self.next += 1;
+        }
+
+        let bit = self.word.trailing_zeros() as usize;
+        self.word &= self.word - 1;
+        Some(self.base + bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_follow_occupancy() {
+        let mut coll = Collection::new();
+        for i in 0..130 {
+            coll.add(i);
+        }
+        for i in (0..130).filter(|i| i % 3 != 0) {
+            coll.take(i);
+        }
+
+        let expected: Vec<_> = (0..130).step_by(3).collect();
+        assert_eq!(coll.occupied_bits().collect::<Vec<_>>(), expected);
+        assert_eq!(coll.occupied_indices(), expected);
+        assert_eq!(coll.occupied_indices().capacity(), expected.len());
+        assert_eq!(coll.occupied_mask(), None);
+
+        let mut small = Collection::new();
+        small.add('a');
+        small.add('b');
+        small.add('c');
+        small.take(1);
+        assert_eq!(small.occupied_mask(), Some(0b101));
+        assert_eq!(Collection::<u8>::new().occupied_bits().next(), None);
+    }
+
+    #[test]
+    fn steps_to_the_nearest_occupied_slot() {
+        let mut coll = Collection::new();
+        for i in 0..6 {
+            coll.add(i);
+        }
+        for i in [0, 2, 3, 5] {
+            coll.take(i);
+        }
+
+        assert_eq!(coll.next_occupied_index(0), Some(1));
+        assert_eq!(coll.next_occupied_index(2), Some(4));
+        assert_eq!(coll.next_occupied_index(5), None);
+        assert_eq!(coll.next_occupied_index(99), None);
+        assert_eq!(coll.prev_occupied_index(3), Some(1));
+        assert_eq!(coll.prev_occupied_index(99), Some(4));
+        assert_eq!(coll.prev_occupied_index(0), None);
+    }
+
+    #[test]
+    fn steps_across_words() {
+        let mut coll = Collection::new();
+        for i in 0..200 {
+            coll.add(i);
+        }
+        for i in (0..200).filter(|&i| i != 3 && i != 130) {
+            coll.take(i);
+        }
+
+        assert_eq!(coll.next_occupied_index(4), Some(130));
+        assert_eq!(coll.next_occupied_index(131), None);
+        assert_eq!(coll.prev_occupied_index(129), Some(3));
+        assert_eq!(coll.prev_occupied_index(500), Some(130));
+        assert_eq!(coll.occupied_indices(), vec![3, 130]);
+        assert_eq!(coll.occupied_mask(), None);
+    }
+
+    #[cfg(not(feature = "strict"))]
+    #[test]
+    fn scans_the_slots_after_index_mut() {
+        let mut coll = Collection::new();
+        for i in 0..70 {
+            coll.add(i);
+        }
+        coll.take(69);
+        coll[69] = Some(0);
+        coll[1] = None;
+
+        assert_eq!(coll.next_occupied_index(68), Some(68));
+        assert_eq!(coll.next_occupied_index(1), Some(2));
+        assert_eq!(coll.prev_occupied_index(1), Some(0));
+        assert_eq!(coll.occupied_bits().count(), 69);
+        assert_eq!(coll.occupied_indices().last(), Some(&69));
+    }
+}
@@ -0,0 +1,147 @@
+//! Ranges of indices reserved for one user of a shared collection.
+//!
+//! `reserve_block` appends a run of vacant slots that `add` never fills, so
+//! a subsystem adding through its `BlockHandle` always lands in the same
+//! contiguous range no matter what the others do. Compaction doesn't move
+//! values into or out of a block, it stops at the last one instead.
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+// Reserved ranges, end by start
+#[derive(Debug, Default)]
+pub(crate) struct Blocks(BTreeMap<usize, usize>);
+
+impl Blocks {
+    pub(crate) const fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    pub(crate) fn contains(&self, index: usize) -> bool {
+        self.0
+            .range(..=index)
+            .next_back()
+            .is_some_and(|(_, &end)| index < end)
+    }
+
+    // Drops or shortens the blocks reaching past `len`
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.0.split_off(&len);
+        if let Some(end) = self.0.values_mut().next_back() {
+            *end = (*end).min(len);
+        }
+    }
+}
+
+/// A block of indices handed out by `reserve_block`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BlockHandle {
+    start: usize,
+    len: usize,
+}
+
+impl BlockHandle {
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.start + self.len
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Reserves `len` vacant slots past the end for adds through the
+    /// returned handle only. `clear` and `truncate` drop the blocks they
+    /// remove the slots of.
+    pub fn reserve_block(&mut self, len: usize) -> BlockHandle {
+        self.before_growth(len);
+        let start = self.inner.len();
+        self.inner.resize_with(start + len, || None);
+        self.blocks.0.insert(start, start + len);
+        self.debug_assert_consistent();
+        BlockHandle { start, len }
+    }
+
+    /// Adds `item` at the lowest vacant index in the block, or hands it back
+    /// if the block is full or was dropped. Finding the index takes
+    /// O(block length).
+    pub fn add_in_block(&mut self, block: &BlockHandle, item: T) -> Result<usize, T> {
+        let end = match self.blocks.0.get(&block.start) {
+            Some(&end) => end,
+            None => return Err(item),
+        };
+        let vacant = (block.start..end)
+            .find(|&index| self.inner[index].is_none() && !self.generations.is_retired(index));
+        let index = match vacant {
+            Some(index) => index,
+            None => return Err(item),
+        };
+
+        self.inner[index] = Some(item);
+        self.occupied += 1;
+        self.note_filled(index);
+        self.debug_assert_consistent();
+        Ok(index)
+    }
+
+    /// Hands the block's slots back to `add`. Its values stay where they
+    /// are, as ordinary values.
+    pub fn release_block(&mut self, block: BlockHandle) {
+        let end = match self.blocks.0.remove(&block.start) {
+            Some(end) => end,
+            None => return,
+        };
+        for index in block.start..end {
+            if self.inner[index].is_none() && self.is_reusable(index) {
+                self.free.push_back(index);
+            }
+        }
+        self.debug_assert_consistent();
+    }
+
+    // Whether a vacancy at `index` belongs on the free list
+    pub(crate) fn is_reusable(&self, index: usize) -> bool {
+        !self.generations.is_retired(index) && !self.is_fixed(index)
+    }
+
+    // Slots in a block or out on lease stay where they are, vacant or not
+    pub(crate) fn is_fixed(&self, index: usize) -> bool {
+        self.blocks.contains(index) || self.leases.contains(index)
+    }
+
+    // Compaction can't move or trim the last slot if it's fixed
+    pub(crate) fn last_is_fixed(&self) -> bool {
+        self.inner
+            .len()
+            .checked_sub(1)
+            .is_some_and(|last| self.is_fixed(last))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_only_filled_through_their_handle() {
+        let mut coll = Collection::new();
+        coll.add("host");
+        let plugin = coll.reserve_block(2);
+        assert_eq!(plugin.range(), 1..3);
+
+        assert_eq!(coll.add("host"), 3);
+        assert_eq!(coll.add_in_block(&plugin, "a"), Ok(1));
+        assert_eq!(coll.add_in_block(&plugin, "b"), Ok(2));
+        assert_eq!(coll.add_in_block(&plugin, "c"), Err("c"));
+
+        coll.take(1);
+        coll.take(3);
+        coll.compact(|_, _| ());
+        assert_eq!(coll.add("host"), 3);
+        assert_eq!(coll.add_in_block(&plugin, "c"), Ok(1));
+
+        coll.take(1);
+        coll.release_block(plugin);
+        assert_eq!(coll.add("host"), 1);
+    }
+}
@@ -0,0 +1,255 @@
+//! Collections over storage they don't own.
+//!
+//! A `StaticCollection` keeps its slots in a borrowed slice, e.g. a `static`
+//! or a stack buffer, and runs the same slot reuse, keys, groups and stats
+//! as `Collection` on top of it: it is a `Collection` whose allocator hands
+//! out that slice and nothing else. It can't grow past the slice it was
+//! given. The bookkeeping next to the slots still lives on the global heap,
+//! same as for `Collection::new_in`.
+use std::alloc::Layout;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Deref;
+use std::ptr::NonNull;
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use allocator_api2::alloc::{AllocError, Allocator};
+
+use crate::{Collection, GroupId, Key, Slots};
+
+/// The allocator of a `StaticCollection`'s slots. It hands out the borrowed
+/// slice once and refuses everything else, so the slots never move or grow.
+pub struct BorrowedSlots<'a> {
+    buffer: NonNull<u8>,
+    size: usize,
+    handed_out: AtomicBool,
+    borrow: PhantomData<&'a mut [u8]>,
+}
+
+// SAFETY: the buffer is only an address until it's handed out, and the flag
+// makes sure that happens at most once at a time
+unsafe impl Send for BorrowedSlots<'_> {}
+unsafe impl Sync for BorrowedSlots<'_> {}
+
+impl BorrowedSlots<'_> {
+    fn empty() -> Self {
+        Self {
+            buffer: NonNull::dangling(),
+            size: 0,
+            handed_out: AtomicBool::new(true),
+            borrow: PhantomData,
+        }
+    }
+}
+
+// SAFETY: the buffer stays valid for `'a`, which every collection using it
+// is bound by, and it's never handed out twice without being freed between
+unsafe impl Allocator for BorrowedSlots<'_> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let fits = layout.size() <= self.size
+            && (self.buffer.as_ptr() as usize).is_multiple_of(layout.align());
+        if !fits || self.handed_out.swap(true, Ordering::Acquire) {
+            return Err(AllocError);
+        }
+        Ok(NonNull::slice_from_raw_parts(self.buffer, self.size))
+    }
+
+    unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {
+        self.handed_out.store(false, Ordering::Release);
+    }
+}
+
+pub struct StaticCollection<'a, T, Tag = ()> {
+    collection: Collection<T, Tag, BorrowedSlots<'a>>,
+    // The length of the borrowed slice, 0 once it was given back
+    capacity: usize,
+    // Values are written back into the slice, so `T` must stay invariant
+    slots: PhantomData<&'a mut [Option<T>]>,
+}
+
+impl<'a, T> StaticCollection<'a, T> {
+    /// Uses `slots` as storage. Slots that are already `Some` count as
+    /// occupied.
+    pub fn new(slots: &'a mut [Option<T>]) -> Self {
+        Self::new_tagged(slots)
+    }
+}
+
+impl<'a, T, Tag> StaticCollection<'a, T, Tag> {
+    /// Same as `new`, for collections with a tag.
+    pub fn new_tagged(slots: &'a mut [Option<T>]) -> Self {
+        let capacity = slots.len();
+        let alloc = BorrowedSlots {
+            buffer: NonNull::from(&mut *slots).cast(),
+            size: mem::size_of_val(slots),
+            handed_out: AtomicBool::new(false),
+            borrow: PhantomData,
+        };
+        let mut inner = Slots::with_capacity_in(capacity, alloc);
+        // SAFETY: the buffer `inner` got is the slice itself, where every
+        // slot already holds a valid value. The slice isn't touched again
+        // until `release` hands it back.
+        unsafe { inner.set_len(capacity) };
+
+        Self {
+            collection: Collection::from_slots_in(inner),
+            capacity,
+            slots: PhantomData,
+        }
+    }
+
+    /// Stores `item` in the lowest vacant slot, or hands it back if there is
+    /// none.
+    pub fn add(&mut self, item: T) -> Result<usize, T> {
+        if self.is_full() {
+            return Err(item);
+        }
+        Ok(self.collection.add(item))
+    }
+
+    /// Like `add`, returning a key that goes stale once the value is taken.
+    pub fn add_key(&mut self, item: T) -> Result<Key<Tag>, T> {
+        if self.is_full() {
+            return Err(item);
+        }
+        Ok(self.collection.add_key(item))
+    }
+
+    pub fn take(&mut self, index: usize) -> Option<T> {
+        self.collection.try_take(index)
+    }
+
+    pub fn take_key(&mut self, key: Key<Tag>) -> Option<T> {
+        self.collection.take_key(key)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.collection.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.collection.get_mut(index)
+    }
+
+    pub fn get_key_mut(&mut self, key: Key<Tag>) -> Option<&mut T> {
+        self.collection.get_key_mut(key)
+    }
+
+    pub fn add_to_group(&mut self, index: usize, group: GroupId) {
+        self.collection.add_to_group(index, group);
+    }
+
+    pub fn remove_from_group(&mut self, index: usize) -> Option<GroupId> {
+        self.collection.remove_from_group(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.collection.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collection.is_empty()
+    }
+
+    /// Whether `add` has no slot left to fill.
+    pub fn is_full(&self) -> bool {
+        self.collection.vacant_len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.collection.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.collection.iter_mut()
+    }
+
+    /// Gives the storage back.
+    pub fn into_slots(mut self) -> &'a mut [Option<T>] {
+        let (slots, capacity) = self.release();
+        // SAFETY: `release` left every slot valid, and the slice was
+        // borrowed for `'a`
+        unsafe { slice::from_raw_parts_mut(slots, capacity) }
+    }
+
+    // Takes the slots out of the collection without dropping the values,
+    // leaving every slot of the slice valid
+    fn release(&mut self) -> (*mut Option<T>, usize) {
+        let empty = Slots::new_in(BorrowedSlots::empty());
+        let inner = mem::replace(&mut self.collection.inner, empty);
+        let (slots, len, _) = inner.into_raw_parts();
+        let capacity = mem::take(&mut self.capacity);
+        for index in len..capacity {
+            // SAFETY: within the slice, and the slots past `len` hold nothing
+            // that needs dropping
+            unsafe { slots.add(index).write(None) };
+        }
+        (slots, capacity)
+    }
+}
+
+impl<'a, T, Tag> Deref for StaticCollection<'a, T, Tag> {
+    type Target = Collection<T, Tag, BorrowedSlots<'a>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.collection
+    }
+}
+
+impl<T, Tag> Drop for StaticCollection<'_, T, Tag> {
+    fn drop(&mut self) {
+        // The values belong to the slice again once the borrow ends
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_borrowed_slots_without_growing() {
+        let mut storage = [None, Some('x'), None];
+        let mut coll = StaticCollection::new(&mut storage);
+        assert_eq!(coll.len(), 1);
+
+        assert_eq!(coll.add('a'), Ok(0));
+        assert_eq!(coll.add('b'), Ok(2));
+        assert!(coll.is_full());
+        assert_eq!(coll.add('c'), Err('c'));
+
+        assert_eq!(coll.take(1), Some('x'));
+        assert_eq!(coll.add('d'), Ok(1));
+        assert_eq!(coll.iter().collect::<String>(), "adb");
+
+        coll.take(0);
+        coll.into_slots();
+        assert_eq!(storage, [None, Some('d'), Some('b')]);
+    }
+
+    #[test]
+    fn keys_and_groups_work_on_borrowed_slots() {
+        let mut storage = [None, None];
+        let mut coll = StaticCollection::new(&mut storage);
+        let key = coll.add_key(String::from("a")).unwrap();
+        assert_eq!(coll.get_key(key).map(String::as_str), Some("a"));
+
+        let group: GroupId = 7;
+        coll.add_to_group(key.index(), group);
+        assert_eq!(coll.iter_group(group).count(), 1);
+
+        assert_eq!(coll.take_key(key).as_deref(), Some("a"));
+        let reused = coll.add_key(String::from("b")).unwrap();
+        assert_eq!(reused.index(), key.index());
+        assert_eq!(coll.get_key(key), None);
+        assert_eq!(coll.iter_group(group).count(), 0);
+
+        drop(coll);
+        assert_eq!(storage, [Some(String::from("b")), None]);
+    }
+}
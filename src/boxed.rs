@@ -0,0 +1,126 @@
+//! A collection of unsized values, e.g. trait objects.
+//!
+//! `BoxedCollection<dyn Trait>` stores `Box<dyn Trait>` in its slots but
+//! hands out `&dyn Trait`, so callers never see the boxes past `add`.
+use crate::{Collection, Key};
+
+pub struct BoxedCollection<T: ?Sized, Tag = ()> {
+    collection: Collection<Box<T>, Tag>,
+}
+
+impl<T: ?Sized> BoxedCollection<T> {
+    pub fn new() -> Self {
+        Self::new_tagged()
+    }
+}
+
+impl<T: ?Sized, Tag> BoxedCollection<T, Tag> {
+    pub fn new_tagged() -> Self {
+        Self {
+            collection: Collection::new_tagged(),
+        }
+    }
+
+    pub fn add(&mut self, item: Box<T>) -> usize {
+        self.collection.add(item)
+    }
+
+    pub fn add_key(&mut self, item: Box<T>) -> Key<Tag> {
+        self.collection.add_key(item)
+    }
+
+    pub fn take(&mut self, index: usize) -> Option<Box<T>> {
+        self.collection.try_take(index)
+    }
+
+    pub fn take_key(&mut self, key: Key<Tag>) -> Option<Box<T>> {
+        self.collection.take_key(key)
+    }
+
+    pub fn insert_at(&mut self, index: usize, item: Box<T>) -> Option<Box<T>> {
+        self.collection.insert_at(index, item)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.collection.get(index).map(|item| &**item)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.collection.get_mut(index).map(|item| &mut **item)
+    }
+
+    pub fn get_key(&self, key: Key<Tag>) -> Option<&T> {
+        self.collection.get_key(key).map(|item| &**item)
+    }
+
+    pub fn get_key_mut(&mut self, key: Key<Tag>) -> Option<&mut T> {
+        self.collection.get_key_mut(key).map(|item| &mut **item)
+    }
+
+    pub fn len(&self) -> u32 {
+        self.collection.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collection.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.collection.iter().map(|item| &**item)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.collection.iter_mut().map(|item| &mut **item)
+    }
+
+    /// Iterates `(index, value)` over the occupied slots.
+    pub fn enumerate(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.collection
+            .enumerate()
+            .map(|(index, item)| (index, &**item))
+    }
+
+    /// The boxes themselves, for everything this doesn't wrap.
+    pub fn as_collection(&self) -> &Collection<Box<T>, Tag> {
+        &self.collection
+    }
+
+    pub fn into_inner(self) -> Collection<Box<T>, Tag> {
+        self.collection
+    }
+}
+
+impl<T: ?Sized> Default for BoxedCollection<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized, Tag> From<Collection<Box<T>, Tag>> for BoxedCollection<T, Tag> {
+    fn from(collection: Collection<Box<T>, Tag>) -> Self {
+        Self { collection }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Display;
+
+    #[test]
+    fn stores_and_hands_out_trait_objects() {
+        let mut shapes: BoxedCollection<dyn Display> = BoxedCollection::new();
+        shapes.add(Box::new(1));
+        let word = shapes.add_key(Box::new("two"));
+        shapes.add(Box::new(3.5));
+
+        let shown: Vec<_> = shapes.iter().map(|shape| shape.to_string()).collect();
+        assert_eq!(shown, vec!["1", "two", "3.5"]);
+        assert_eq!(shapes.get_key(word).unwrap().to_string(), "two");
+
+        let mut names: BoxedCollection<str> = BoxedCollection::new();
+        let index = names.add("ferris".into());
+        names.get_mut(index).unwrap().make_ascii_uppercase();
+        assert_eq!(names.get(index), Some("FERRIS"));
+    }
+}
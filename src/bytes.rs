@@ -0,0 +1,50 @@
+//! Bulk filling a collection of bytes.
+//!
+//! Adding a buffer byte by byte does the free list bookkeeping once per
+//! byte. `extend_from_slice` does it once per call, then appends whatever
+//! didn't fit into vacancies.
+use std::io::{self, ErrorKind, Read};
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+impl Collection<u8> {
+    /// Reads `reader` to the end, one byte per slot.
+    pub fn from_reader(mut reader: impl Read) -> io::Result<Self> {
+        let mut coll = Self::new();
+        let mut buf = [0; 8192];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => return Ok(coll),
+                Ok(read) => coll.extend_from_slice(&buf[..read]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<Tag, A: Allocator> Collection<u8, Tag, A> {
+    /// Stores every byte, filling vacancies lowest first like `add` would
+    /// and appending the rest.
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.extend_filling_vacancies(bytes.iter().copied());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_vacancies_then_appends() {
+        let mut coll = Collection::from_reader(&b"slots"[..]).unwrap();
+        coll.take(1);
+        coll.take(3);
+
+        coll.extend_from_slice(b"LT!?");
+        assert_eq!(coll.to_owned_values(), b"sLoTs!?");
+        assert_eq!((coll.len(), coll.vacant_len()), (7, 0));
+    }
+}
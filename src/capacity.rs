@@ -0,0 +1,81 @@
+//! How many values fit before the slot storage has to grow.
+//!
+//! `add` reuses vacant slots first, so the slots it can fill without
+//! reallocating are the vacant ones plus the `Vec`'s spare capacity.
+//! `on_growth` reports every reallocation before it happens, so a real-time
+//! loop can see which bursts cause them and `reserve` ahead of time instead.
+use std::panic::AssertUnwindSafe;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::{wipe, Collection};
+
+pub(crate) type OnGrowth = AssertUnwindSafe<Box<dyn FnMut(usize) + Send + Sync>>;
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Slots allocated, occupied or not.
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    /// Allocated slots past the last one in use.
+    pub fn spare_slots(&self) -> usize {
+        self.inner.capacity() - self.inner.len()
+    }
+
+    /// How many `add` calls can happen before the storage reallocates.
+    pub fn until_growth(&self) -> usize {
+        self.spare_slots() + self.free.len()
+    }
+
+    /// Makes room so the next `additional` calls to `add` don't reallocate.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = additional.saturating_sub(self.free.len());
+        wipe::reserve(&mut self.inner, needed);
+    }
+
+    /// Calls `f(capacity)` right before the storage outgrows `capacity` and
+    /// reallocates.
+    pub fn on_growth(&mut self, f: impl FnMut(usize) + Send + Sync + 'static) {
+        self.on_growth = Some(AssertUnwindSafe(Box::new(f)));
+    }
+
+    // Call before appending `additional` slots
+    pub(crate) fn before_growth(&mut self, additional: usize) {
+        if self.spare_slots() >= additional {
+            return;
+        }
+        if let Some(on_growth) = &mut self.on_growth {
+            on_growth(self.inner.capacity());
+        }
+        wipe::reserve(&mut self.inner, additional);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn growth_is_reported_before_it_happens() {
+        let grown = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&grown);
+
+        let mut coll = Collection::new();
+        coll.on_growth(move |capacity| log.lock().unwrap().push(capacity));
+        coll.reserve(2);
+        let capacity = coll.capacity();
+        for i in 0..capacity {
+            coll.add(i);
+        }
+        coll.take(0);
+        assert_eq!(coll.until_growth(), 1);
+
+        coll.add(0);
+        assert!(grown.lock().unwrap().is_empty());
+        coll.add(1);
+        assert_eq!(*grown.lock().unwrap(), vec![capacity]);
+        assert_eq!(coll.until_growth(), coll.spare_slots());
+    }
+}
@@ -0,0 +1,299 @@
+//! A bounded channel whose messages keep a slot index until received.
+//!
+//! `slot_channel` returns senders and a receiver sharing one
+//! `ConcurrentCollection`. Sending a message stores it in a slot and hands
+//! back a `Ticket` for it, e.g. a job handle, which can cancel the message
+//! as long as it hasn't been received. The receiver takes messages lowest
+//! index first. A ticket remembers the version of its slot, so it never
+//! cancels a later message that reused the slot.
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+use crate::versioned::Version;
+use crate::ConcurrentCollection;
+
+/// A channel holding at most `capacity` messages at a time.
+///
+/// # Panics
+///
+/// Panics if `capacity` is 0.
+pub fn slot_channel<T>(capacity: usize) -> (SlotSender<T>, SlotReceiver<T>) {
+    assert!(capacity > 0, "a slot channel needs room for a message");
+    let shared = Arc::new(Shared {
+        slots: ConcurrentCollection::with_segments(capacity, 1),
+        senders: AtomicUsize::new(1),
+        receiving: AtomicBool::new(true),
+        lock: Mutex::new(()),
+        sent: Condvar::new(),
+        freed: Condvar::new(),
+    });
+    (
+        SlotSender {
+            shared: Arc::clone(&shared),
+        },
+        SlotReceiver { shared },
+    )
+}
+
+struct Shared<T> {
+    slots: ConcurrentCollection<T>,
+    senders: AtomicUsize,
+    receiving: AtomicBool,
+    // Held while checking whether to wait and while notifying, so a
+    // notification can't slip in between the check and the wait
+    lock: Mutex<()>,
+    sent: Condvar,
+    freed: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn notify(&self, condvar: &Condvar) {
+        let _lock = self.lock();
+        condvar.notify_all();
+    }
+}
+
+/// Names a message from when it's sent until it's received or cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ticket {
+    index: usize,
+    version: Version,
+}
+
+impl Ticket {
+    /// The slot the message is stored in, which the receiver gets with it.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    /// Every slot is taken.
+    Full(T),
+    /// The receiver is gone.
+    Disconnected(T),
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "the slot channel is full"),
+            TrySendError::Disconnected(_) => write!(f, "the slot channel's receiver is gone"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> Error for TrySendError<T> {}
+
+pub struct SlotSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> SlotSender<T> {
+    /// Sends `message`, handing it back if the channel is full or the
+    /// receiver is gone.
+    pub fn try_send(&self, message: T) -> Result<Ticket, TrySendError<T>> {
+        let ticket = self.store(message)?;
+        self.shared.notify(&self.shared.sent);
+        Ok(ticket)
+    }
+
+    /// Sends `message`, waiting for a free slot if the channel is full. Only
+    /// hands it back if the receiver is gone.
+    pub fn send(&self, mut message: T) -> Result<Ticket, T> {
+        loop {
+            let lock = self.shared.lock();
+            match self.store(message) {
+                Ok(ticket) => {
+                    self.shared.sent.notify_all();
+                    return Ok(ticket);
+                }
+                Err(TrySendError::Disconnected(returned)) => return Err(returned),
+                Err(TrySendError::Full(returned)) => message = returned,
+            }
+            drop(
+                self.shared
+                    .freed
+                    .wait(lock)
+                    .unwrap_or_else(PoisonError::into_inner),
+            );
+        }
+    }
+
+    /// Takes the message back if it hasn't been received yet.
+    pub fn cancel(&self, ticket: Ticket) -> Option<T> {
+        let message = self
+            .shared
+            .slots
+            .take_if_version(ticket.index, Some(ticket.version))?;
+        self.shared.notify(&self.shared.freed);
+        Some(message)
+    }
+
+    /// Whether the message is still waiting to be received.
+    pub fn is_pending(&self, ticket: Ticket) -> bool {
+        self.shared.slots.version(ticket.index) == Some(ticket.version)
+    }
+
+    // `try_send` without waking the receiver
+    fn store(&self, message: T) -> Result<Ticket, TrySendError<T>> {
+        if !self.shared.receiving.load(Ordering::Acquire) {
+            return Err(TrySendError::Disconnected(message));
+        }
+        let (index, version) = self
+            .shared
+            .slots
+            .try_add_versioned(message)
+            .map_err(TrySendError::Full)?;
+        Ok(Ticket { index, version })
+    }
+}
+
+impl<T> Clone for SlotSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for SlotSender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.notify(&self.shared.sent);
+        }
+    }
+}
+
+pub struct SlotReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> SlotReceiver<T> {
+    /// Takes the waiting message with the lowest index, along with that
+    /// index. Looks through every slot, so it takes O(capacity).
+    pub fn try_recv(&self) -> Option<(usize, T)> {
+        let received = self.take_lowest()?;
+        self.shared.notify(&self.shared.freed);
+        Some(received)
+    }
+
+    /// Like `try_recv`, but waits for a message. Returns `None` once every
+    /// sender is gone and no message is left.
+    pub fn recv(&self) -> Option<(usize, T)> {
+        loop {
+            let lock = self.shared.lock();
+            if let Some(received) = self.take_lowest() {
+                drop(lock);
+                self.shared.freed.notify_all();
+                return Some(received);
+            }
+            if self.shared.senders.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+            drop(
+                self.shared
+                    .sent
+                    .wait(lock)
+                    .unwrap_or_else(PoisonError::into_inner),
+            );
+        }
+    }
+
+    /// Takes every waiting message, in index order.
+    pub fn drain(&self) -> impl Iterator<Item = (usize, T)> + '_ {
+        std::iter::from_fn(move || self.try_recv())
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.slots.is_empty()
+    }
+
+    // `try_recv` without waking the senders
+    fn take_lowest(&self) -> Option<(usize, T)> {
+        let slots = &self.shared.slots;
+        (0..slots.capacity()).find_map(|index| Some((index, slots.take(index)?)))
+    }
+}
+
+impl<T> Drop for SlotReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiving.store(false, Ordering::Release);
+        self.shared.notify(&self.shared.freed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn tickets_cancel_only_their_own_message() {
+        let (jobs, queue) = slot_channel(2);
+        let build = jobs.try_send("build").unwrap();
+        let test = jobs.try_send("test").unwrap();
+        assert_eq!(jobs.try_send("lint"), Err(TrySendError::Full("lint")));
+
+        assert_eq!(jobs.cancel(test), Some("test"));
+        assert_eq!(jobs.cancel(test), None);
+        assert_eq!(queue.try_recv(), Some((build.index(), "build")));
+        assert!(!jobs.is_pending(build));
+
+        // The reused slot doesn't answer to the old ticket
+        let deploy = jobs.try_send("deploy").unwrap();
+        assert!(deploy.index() == build.index() || deploy.index() == test.index());
+        assert_eq!(jobs.cancel(build), None);
+        assert!(jobs.is_pending(deploy));
+        assert_eq!(
+            queue.drain().collect::<Vec<_>>(),
+            vec![(deploy.index(), "deploy")]
+        );
+
+        drop(queue);
+        assert_eq!(
+            jobs.try_send("late"),
+            Err(TrySendError::Disconnected("late"))
+        );
+    }
+
+    #[test]
+    fn blocking_ends_deliver_everything() {
+        let (sender, receiver) = slot_channel(2);
+        let producers: Vec<_> = (0..4)
+            .map(|id| {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for i in 0..50 {
+                        sender.send(id * 100 + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let mut received: Vec<_> = std::iter::from_fn(|| receiver.recv())
+            .map(|(_, message)| message)
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        received.sort_unstable();
+        let expected: Vec<_> = (0..4)
+            .flat_map(|id| (0..50).map(move |i| id * 100 + i))
+            .collect();
+        assert_eq!(received, expected);
+    }
+}
@@ -0,0 +1,464 @@
+//! Compaction: moving values out of the tail into vacancies so trailing slots
+//! can be released.
+//!
+//! Compaction changes indices, so every move is reported to a remap callback
+//! as `(old, new)`. `AutoCompact` runs it a few moves at a time from inside
+//! `add` and `take` once fragmentation crosses a threshold.
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::{wipe, Collection};
+
+type Remap = Box<dyn FnMut(usize, usize) + Send + Sync>;
+
+pub struct AutoCompact {
+    threshold: f64,
+    moves_per_call: usize,
+    // The collection is left consistent if the callback panics, so this
+    // doesn't need to make `Collection` lose its unwind safety
+    remap: AssertUnwindSafe<Remap>,
+}
+
+impl AutoCompact {
+    /// Compacts whenever more than `ratio` (0.0 to 1.0) of the slots are
+    /// vacant, doing at most 4 moves per `add` or `take` call.
+    pub fn when_vacancy_exceeds(ratio: f64) -> Self {
+        Self {
+            threshold: ratio,
+            moves_per_call: 4,
+            remap: AssertUnwindSafe(Box::new(|_, _| ())),
+        }
+    }
+
+    pub fn moves_per_call(mut self, moves: usize) -> Self {
+        self.moves_per_call = moves;
+        self
+    }
+
+    /// Called with `(old, new)` for every value compaction moves.
+    pub fn on_remap(mut self, remap: impl FnMut(usize, usize) + Send + Sync + 'static) -> Self {
+        self.remap = AssertUnwindSafe(Box::new(remap));
+        self
+    }
+}
+
+impl fmt::Debug for AutoCompact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoCompact")
+            .field("threshold", &self.threshold)
+            .field("moves_per_call", &self.moves_per_call)
+            .finish()
+    }
+}
+
+// The most vacant slots one compaction step trims off the end, a longer
+// tail takes several steps
+const TRIM_PER_STEP: usize = 64;
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Moves values into the lowest vacancies until no vacant slot is left,
+    /// calling `remap(old, new)` for every value moved.
+    pub fn compact(&mut self, mut remap: impl FnMut(usize, usize)) {
+        while self.compact_step(&mut remap) {}
+        self.debug_assert_consistent();
+    }
+
+    /// Numbers the occupied slots `0..len` in index order without moving
+    /// anything, e.g. for formats that need contiguous ids. Returns the slot
+    /// index of every dense id, and a lookup from slot index to dense id.
+    pub fn dense_id_map(&self) -> (Rc<[usize]>, impl Fn(usize) -> Option<usize>) {
+        let slots: Rc<[usize]> = self.enumerate().map(|(index, _)| index).collect();
+        // In index order, so the lookup can search the same list
+        let sorted = Rc::clone(&slots);
+        (slots, move |index| sorted.binary_search(&index).ok())
+    }
+
+    pub fn set_auto_compact(&mut self, policy: Option<AutoCompact>) {
+        self.auto_compact = policy;
+    }
+
+    /// The share of slots that are vacant, from 0.0 to 1.0.
+    pub fn vacancy_ratio(&self) -> f64 {
+        if self.inner.is_empty() {
+            return 0.0;
+        }
+
+        self.free.len() as f64 / self.inner.len() as f64
+    }
+
+    pub(crate) fn auto_compact(&mut self) {
+        let mut policy = match self.auto_compact.take() {
+            Some(policy) => policy,
+            None => return,
+        };
+
+        if self.vacancy_ratio() > policy.threshold {
+            for _ in 0..policy.moves_per_call {
+                if !self.compact_step(&mut *policy.remap) {
+                    break;
+                }
+            }
+        }
+
+        self.auto_compact = Some(policy);
+    }
+
+    /// Moves the values matching `pred` into the lowest slots, so the first
+    /// `matching` slots outside reserved blocks hold exactly the matching
+    /// values afterwards. Calls `remap(old, new)` once for every value moved,
+    /// including non-matching ones that had to make room, and always into a
+    /// slot that is vacant at that point, so the moves can be replayed in
+    /// order. Values in reserved blocks stay put and aren't counted. Returns
+    /// `(matching, not_matching)`.
+    ///
+    /// Making room takes a vacant slot above the split. If every slot is
+    /// occupied, one is added at the end.
+    pub fn partition_in_place(
+        &mut self,
+        mut pred: impl FnMut(&T) -> bool,
+        mut remap: impl FnMut(usize, usize),
+    ) -> (usize, usize) {
+        // Block slots are left alone, and so are retired vacancies
+        let movable: Vec<usize> = (0..self.inner.len())
+            .filter(|&index| !self.blocks.contains(index))
+            .filter(|&index| self.inner[index].is_some() || self.is_reusable(index))
+            .collect();
+        let matches: Vec<bool> = movable
+            .iter()
+            .map(|&index| self.inner[index].as_ref().is_some_and(&mut pred))
+            .collect();
+        let matching = matches.iter().filter(|&&m| m).count();
+        let occupied = movable
+            .iter()
+            .filter(|&&index| self.inner[index].is_some())
+            .count();
+
+        // Slots below the split that need a matching value, vacant ones
+        // first since filling those frees a slot above it to make room in
+        let mut holes: Vec<usize> = (0..matching).filter(|&i| !matches[i]).collect();
+        holes.sort_by_key(|&i| self.inner[movable[i]].is_some());
+        let strays = (matching..movable.len()).filter(|&i| matches[i]);
+        let mut spare: Vec<usize> = (matching..movable.len())
+            .map(|i| movable[i])
+            .filter(|&index| self.inner[index].is_none())
+            .rev()
+            .collect();
+
+        for (low, high) in holes.into_iter().zip(strays) {
+            let (low, high) = (movable[low], movable[high]);
+            if self.inner[low].is_some() {
+                let room = match spare.pop() {
+                    Some(room) => room,
+                    None => self.push_vacant(),
+                };
+                self.free.remove(room);
+                self.move_to(low, room);
+                remap(low, room);
+            }
+            self.free.remove(low);
+            self.move_to(high, low);
+            remap(high, low);
+
+            if self.is_reusable(high) {
+                self.free.push_back(high);
+                spare.push(high);
+            }
+        }
+
+        self.debug_assert_consistent();
+        (matching, occupied - matching)
+    }
+
+    // Adds a vacant slot at the end for `partition_in_place` to move into,
+    // without putting it on the free list
+    fn push_vacant(&mut self) -> usize {
+        self.before_growth(1);
+        // Retired slots past the end come back vacant, same as in `add`
+        while self.generations.is_retired(self.inner.len()) {
+            self.inner.push(None);
+        }
+        self.inner.push(None);
+        self.inner.len() - 1
+    }
+
+    /// Takes the value at `index` and moves the last value into its slot,
+    /// like `Vec::swap_remove`. Returns the value along with `(old, new)` for
+    /// the value that moved, if one did.
+    ///
+    /// A collection only ever emptied this way stays dense, so iterating it
+    /// touches no vacant slots, at the price of the moved value's index and
+    /// keys. `take` is the other way around.
+    pub fn swap_remove_fill(&mut self, index: usize) -> Option<(T, Option<(usize, usize)>)> {
+        let slot = self.inner.get_mut(index)?;
+        let item = slot.take()?;
+        wipe::vacated(slot);
+        self.groups.unlink(index);
+        self.generations.bump(index);
+        self.count_vacated();
+        self.stats.vacated(index);
+        self.leaks.vacated(index);
+        self.occupancy.vacated(index);
+        self.pending_removals.vacated(index);
+        self.bitmap.vacated(index);
+        self.structure_changes += 1;
+
+        self.trim_vacant_tail();
+        let moved = if index >= self.inner.len() {
+            None
+        } else if self.is_fixed(index) || self.last_is_fixed() {
+            // Nothing moves into or out of a block or lease, the vacancy stays
+            if self.is_reusable(index) {
+                self.free.push_back(index);
+            }
+            None
+        } else {
+            let old = self.move_last_to(index);
+            self.trim_vacant_tail();
+            Some((old, index))
+        };
+        self.debug_assert_consistent();
+        Some((item, moved))
+    }
+
+    // Moves the last value into the lowest vacancy, or trims part of a long
+    // vacant tail, so the work per step is bounded. Returns `false` once
+    // there's nothing left to do.
+    fn compact_step(&mut self, remap: &mut impl FnMut(usize, usize)) -> bool {
+        if !self.trim_vacant_tail_by(TRIM_PER_STEP) {
+            return true;
+        }
+        if self.last_is_fixed() {
+            return false;
+        }
+
+        let new = match self.free.pop_lowest() {
+            Some(lowest) => lowest,
+            None => return false,
+        };
+
+        let old = self.move_last_to(new);
+        remap(old, new);
+        true
+    }
+
+    // Moves the last value into the vacant slot `new`, which must be off the
+    // free list, and returns where it came from. The tail has to be trimmed.
+    fn move_last_to(&mut self, new: usize) -> usize {
+        // After trimming the last slot is always occupied
+        let old = self.inner.len() - 1;
+        self.inner[new] = self.inner.pop().flatten();
+        wipe::released(&mut self.inner, 1);
+        self.inserted.moved(old, new);
+        self.stats.moved(old, new);
+        self.leaks.moved(old, new);
+        self.occupancy.moved(old, new);
+        self.pending_removals.moved(old, new);
+        self.bitmap.moved(old, new);
+        self.structure_changes += 1;
+
+        // Keys to the moved value go stale, `remap` is how callers follow it
+        self.generations.bump(old);
+        if let Some(group) = self.groups.unlink(old) {
+            self.groups.link(new, group);
+        }
+        old
+    }
+
+    // Moves the value at `old` into the vacant slot `new`, which must be off
+    // the free list. `old` is left vacant and off the free list too.
+    fn move_to(&mut self, old: usize, new: usize) {
+        self.inner[new] = self.inner[old].take();
+        wipe::vacated(&mut self.inner[old]);
+        self.inserted.moved(old, new);
+        self.stats.moved(old, new);
+        self.leaks.moved(old, new);
+        self.occupancy.moved(old, new);
+        self.pending_removals.moved(old, new);
+        self.bitmap.moved(old, new);
+        self.structure_changes += 1;
+
+        self.generations.bump(old);
+        if let Some(group) = self.groups.unlink(old) {
+            self.groups.link(new, group);
+        }
+    }
+
+    fn trim_vacant_tail(&mut self) {
+        self.trim_vacant_tail_by(usize::MAX);
+    }
+
+    // Drops up to `max` vacant slots off the end, stopping at a block or a
+    // lease.
+    // Returns whether the tail is trimmed completely.
+    fn trim_vacant_tail_by(&mut self, max: usize) -> bool {
+        let floor = self.inner.len().saturating_sub(max);
+        let mut len = self.inner.len();
+        while len > floor && self.inner[len - 1].is_none() && !self.is_fixed(len - 1) {
+            len -= 1;
+        }
+
+        let trimmed = self.inner.len() - len;
+        if trimmed > 0 {
+            self.inner.truncate(len);
+            wipe::released(&mut self.inner, trimmed);
+            self.free.truncate(len);
+        }
+        self.inner.last().is_none_or(Option::is_some) || self.last_is_fixed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn compact_fills_vacancies_from_the_tail() {
+        let mut coll = Collection::new();
+        for i in 0..6 {
+            coll.add(i);
+        }
+        coll.take(0);
+        coll.take(2);
+        coll.take(4);
+
+        let mut moves = Vec::new();
+        coll.compact(|old, new| moves.push((old, new)));
+
+        assert_eq!(moves, vec![(5, 0), (3, 2)]);
+        assert_eq!(*coll.inner, [Some(5), Some(1), Some(3)]);
+        assert_eq!(coll.vacancy_ratio(), 0.0);
+        assert_eq!(coll.add(6), 3);
+    }
+
+    #[test]
+    fn dense_ids_skip_vacancies_both_ways() {
+        let mut coll = Collection::new();
+        for node in "abcde".chars() {
+            coll.add(node);
+        }
+        coll.take(1);
+        coll.take(3);
+
+        let (slots, dense) = coll.dense_id_map();
+        assert_eq!(*slots, [0, 2, 4]);
+        assert_eq!(
+            (0..6).map(&dense).collect::<Vec<_>>(),
+            vec![Some(0), None, Some(1), None, Some(2), None]
+        );
+        assert_eq!(coll.max_index(), Some(4));
+    }
+
+    #[test]
+    fn partition_moves_matching_values_to_the_front() {
+        let mut coll = Collection::new();
+        for i in 0..8 {
+            coll.add(i);
+        }
+        coll.take(1);
+        let odd_key = coll.key_for_index(5).unwrap();
+
+        let mut moves = Vec::new();
+        let split = coll.partition_in_place(|x| x % 2 == 1, |old, new| moves.push((old, new)));
+
+        assert_eq!(split, (3, 4));
+        assert_eq!(moves, vec![(3, 1), (0, 3), (5, 0), (2, 5), (7, 2)]);
+        assert_eq!(coll.to_owned_values(), vec![5, 3, 7, 0, 4, 2, 6]);
+        assert_eq!(coll.get_key(odd_key), None);
+
+        // The vacancy ended up where the last stray was
+        assert_eq!(coll.get(7), None);
+        assert_eq!(coll.add(9), 7);
+        assert_eq!(coll.add(9), 8);
+    }
+
+    #[test]
+    fn partition_leaves_blocks_alone() {
+        let mut coll = Collection::new();
+        coll.add(0);
+        let block = coll.reserve_block(2);
+        coll.add_in_block(&block, 3).unwrap();
+        coll.add(5);
+        coll.add(7);
+
+        let mut moves = Vec::new();
+        let split = coll.partition_in_place(|x| x % 2 == 1, |old, new| moves.push((old, new)));
+
+        // Nothing was vacant outside the block, so a slot was added to make
+        // room in
+        assert_eq!(split, (2, 1));
+        assert_eq!(moves, vec![(0, 5), (4, 0)]);
+        assert_eq!(
+            *coll.inner,
+            [Some(7), Some(3), None, Some(5), None, Some(0)]
+        );
+        assert_eq!(coll.add(9), 4);
+    }
+
+    #[test]
+    fn swap_remove_fill_keeps_values_dense() {
+        let mut coll = Collection::new();
+        for c in "abcd".chars() {
+            coll.add(c);
+        }
+        let d = coll.key_for_index(3).unwrap();
+
+        assert_eq!(coll.swap_remove_fill(1), Some(('b', Some((3, 1)))));
+        assert_eq!(*coll.inner, [Some('a'), Some('d'), Some('c')]);
+        assert_eq!(coll.get_key(d), None);
+
+        assert_eq!(coll.swap_remove_fill(2), Some(('c', None)));
+        assert_eq!(coll.swap_remove_fill(2), None);
+        assert_eq!((coll.len(), coll.vacant_len()), (2, 0));
+        assert_eq!(coll.add('e'), 2);
+    }
+
+    #[test]
+    fn auto_compact_spreads_work_over_calls() {
+        let moves = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&moves);
+
+        let mut coll = Collection::new();
+        coll.set_auto_compact(Some(
+            AutoCompact::when_vacancy_exceeds(0.5)
+                .moves_per_call(1)
+                .on_remap(move |old, new| log.lock().unwrap().push((old, new))),
+        ));
+
+        for i in 0..10 {
+            coll.add(i);
+        }
+        for i in 0..5 {
+            coll.take(i);
+        }
+        assert!(moves.lock().unwrap().is_empty());
+
+        // Crossing the threshold moves a single value
+        coll.take(5);
+        assert_eq!(*moves.lock().unwrap(), vec![(9, 0)]);
+        assert_eq!(coll.get(0), Some(&9));
+        assert_eq!(coll.len(), 4);
+    }
+
+    #[test]
+    fn auto_compaction_trims_a_long_tail_in_steps() {
+        let mut coll = Collection::new();
+        for i in 0..1000 {
+            coll.add(i);
+        }
+        for i in 200..1000 {
+            coll.take(i);
+        }
+        coll.set_auto_compact(Some(
+            AutoCompact::when_vacancy_exceeds(0.5).moves_per_call(1),
+        ));
+
+        assert_eq!(coll.add(0), 200);
+        assert_eq!(coll.max_index(), Some(1000 - 1 - 64));
+        coll.compact(|_, _| ());
+        assert_eq!(coll.max_index(), Some(200));
+    }
+}
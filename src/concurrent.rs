@@ -0,0 +1,648 @@
+//! A collection that can be shared between threads.
+//!
+//! Slots live in fixed-size segments that are allocated lazily and never
+//! moved or freed before the collection itself is dropped. Growing only
+//! publishes a new segment pointer, so readers of existing slots are never
+//! blocked by it and references into a slot stay valid while it's locked.
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::ptr;
+use std::sync::PoisonError;
+
+// Under `--cfg loom` or `--cfg shuttle` the synchronization comes from the
+// model checker, so the tests at the bottom can explore interleavings
+#[cfg(loom)]
+use loom::sync::{
+    atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+    Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+#[cfg(all(shuttle, not(loom)))]
+use shuttle::sync::{
+    atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+    Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+#[cfg(not(any(loom, shuttle)))]
+use std::sync::{
+    atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+    Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+
+use crate::added::Subscribers;
+use crate::versioned::Version;
+use crate::waiters::Waiters;
+
+const DEFAULT_SEGMENT_SIZE: usize = 1024;
+const DEFAULT_MAX_SEGMENTS: usize = 4096;
+
+struct Slot<T> {
+    value: RwLock<Option<T>>,
+    // Bumped under the write lock whenever the slot is written
+    version: AtomicU64,
+}
+
+impl<T> Slot<T> {
+    fn new() -> Self {
+        Slot {
+            value: RwLock::new(None),
+            version: AtomicU64::new(0),
+        }
+    }
+}
+
+/// How slots are laid out in memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Align {
+    /// Slots are packed back to back. The default.
+    #[default]
+    Packed,
+    /// Every slot gets its own 128 bytes, so threads writing to neighbouring
+    /// slots don't invalidate each other's caches. Slots smaller than that, as
+    /// most are, take up correspondingly more memory.
+    CacheLine,
+}
+
+// 128 rather than 64 since x86_64 prefetches cache lines in pairs
+#[repr(align(128))]
+struct CachePadded<T>(T);
+
+enum Segment<T> {
+    Packed(Box<[Slot<T>]>),
+    Padded(Box<[CachePadded<Slot<T>>]>),
+}
+
+impl<T> Segment<T> {
+    fn new(size: usize, align: Align) -> Self {
+        match align {
+            Align::Packed => Segment::Packed((0..size).map(|_| Slot::new()).collect()),
+            Align::CacheLine => {
+                Segment::Padded((0..size).map(|_| CachePadded(Slot::new())).collect())
+            }
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<&Slot<T>> {
+        match self {
+            Segment::Packed(slots) => slots.get(index),
+            Segment::Padded(slots) => slots.get(index).map(|slot| &slot.0),
+        }
+    }
+}
+
+pub struct ConcurrentCollection<T> {
+    segment_size: usize,
+    align: Align,
+    // A fixed directory of lazily allocated segments
+    segments: Box<[AtomicPtr<Segment<T>>]>,
+    free: Mutex<Vec<usize>>,
+    // The lowest index no `add` has handed out yet
+    next: AtomicUsize,
+    len: AtomicUsize,
+    pub(crate) waiters: Waiters,
+    pub(crate) subscribers: Subscribers,
+    _owns: PhantomData<T>,
+}
+
+// Safety: the raw segment pointers are owned by the collection and only ever
+// give out access to `T` through the slot locks, so it's as thread safe as a
+// `Vec<RwLock<Option<T>>>` would be.
+unsafe impl<T: Send> Send for ConcurrentCollection<T> {}
+unsafe impl<T: Send + Sync> Sync for ConcurrentCollection<T> {}
+
+impl<T> ConcurrentCollection<T> {
+    pub fn new() -> Self {
+        Self::with_segment_size(DEFAULT_SEGMENT_SIZE)
+    }
+
+    /// Uses segments of `segment_size` slots. Smaller segments waste less
+    /// memory on a sparse collection, larger ones raise `capacity`.
+    pub fn with_segment_size(segment_size: usize) -> Self {
+        Self::with_segments(segment_size, DEFAULT_MAX_SEGMENTS)
+    }
+
+    /// Uses at most `max_segments` segments of `segment_size` slots each.
+    pub fn with_segments(segment_size: usize, max_segments: usize) -> Self {
+        Self::with_layout(segment_size, max_segments, Align::Packed)
+    }
+
+    /// Lays out the slots as `align` says, see `Align` for the trade-off.
+    pub fn with_alignment(align: Align) -> Self {
+        Self::with_layout(DEFAULT_SEGMENT_SIZE, DEFAULT_MAX_SEGMENTS, align)
+    }
+
+    /// `with_segments` and `with_alignment` in one.
+    pub fn with_layout(segment_size: usize, max_segments: usize, align: Align) -> Self {
+        assert!(segment_size > 0, "Segments need at least one slot");
+
+        Self {
+            segment_size,
+            align,
+            segments: (0..max_segments)
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect(),
+            free: Mutex::new(Vec::new()),
+            next: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+            waiters: Waiters::new(),
+            subscribers: Subscribers::new(),
+            _owns: PhantomData,
+        }
+    }
+
+    pub fn segment_size(&self) -> usize {
+        self.segment_size
+    }
+
+    pub fn alignment(&self) -> Align {
+        self.align
+    }
+
+    /// The maximum number of slots the collection can hold.
+    pub fn capacity(&self) -> usize {
+        self.segment_size * self.segments.len()
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the collection is at `capacity`.
+    #[track_caller]
+    pub fn add(&self, item: T) -> usize {
+        match self.try_add(item) {
+            Ok(index) => index,
+            Err(_) => panic!("ConcurrentCollection is at capacity"),
+        }
+    }
+
+    /// Like `add`, but hands the value back if the collection is full.
+    pub fn try_add(&self, item: T) -> Result<usize, T> {
+        self.try_add_versioned(item).map(|(index, _)| index)
+    }
+
+    // `try_add`, along with the version the slot is at once it holds `item`
+    pub(crate) fn try_add_versioned(&self, item: T) -> Result<(usize, Version), T> {
+        let index = match self.pop_free() {
+            Some(index) => index,
+            None => {
+                let index = self.next.fetch_add(1, Ordering::Relaxed);
+                if index >= self.capacity() {
+                    self.next.fetch_sub(1, Ordering::Relaxed);
+                    return Err(item);
+                }
+                index
+            }
+        };
+
+        // Count the value before it's visible, or a racing `take` could
+        // bring `len` below zero
+        self.len.fetch_add(1, Ordering::Relaxed);
+        let slot = self.slot_or_grow(index);
+        let mut value = slot.value.write().unwrap_or_else(PoisonError::into_inner);
+        *value = Some(item);
+        let version = Version(slot.version.fetch_add(1, Ordering::Relaxed) + 1);
+        drop(value);
+
+        self.subscribers.added(index, version);
+        Ok((index, version))
+    }
+
+    pub fn take(&self, index: usize) -> Option<T> {
+        self.take_if_version(index, None)
+    }
+
+    // `take`, but only if the slot is still at `version` when given one.
+    // Checked under the same lock the value is taken with.
+    pub(crate) fn take_if_version(&self, index: usize, version: Option<Version>) -> Option<T> {
+        let slot = self.slot(index)?;
+        let mut value = slot.value.write().unwrap_or_else(PoisonError::into_inner);
+        if version.is_some_and(|Version(version)| slot.version.load(Ordering::Relaxed) != version) {
+            return None;
+        }
+        let item = value.take()?;
+        slot.version.fetch_add(1, Ordering::Relaxed);
+        drop(value);
+
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        self.free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(index);
+        self.waiters.wake_all();
+        Some(item)
+    }
+
+    /// Read access to the value at `index`. Writers to that slot, and only
+    /// that slot, wait until the returned guard is dropped.
+    pub fn get(&self, index: usize) -> Option<SlotRef<'_, T>> {
+        let guard = self
+            .slot(index)?
+            .value
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if guard.is_some() {
+            Some(SlotRef { guard })
+        } else {
+            None
+        }
+    }
+
+    /// Exclusive access to the value at `index`. Only that slot is locked,
+    /// so threads working on different slots don't wait for each other.
+    pub fn lock(&self, index: usize) -> Option<SlotGuard<'_, T>> {
+        let slot = self.slot(index)?;
+        let guard = slot.value.write().unwrap_or_else(PoisonError::into_inner);
+
+        if guard.is_some() {
+            Some(SlotGuard {
+                guard,
+                version: &slot.version,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Locks two slots, returning the guards in the order the indices were
+    /// given. The lower index is always locked first, so two threads locking
+    /// the same pair in opposite order can't deadlock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a == b`.
+    pub fn lock_pair(&self, a: usize, b: usize) -> Option<(SlotGuard<'_, T>, SlotGuard<'_, T>)> {
+        assert_ne!(a, b, "Can't lock a slot twice");
+
+        if a < b {
+            let a = self.lock(a)?;
+            Some((a, self.lock(b)?))
+        } else {
+            let b = self.lock(b)?;
+            Some((self.lock(a)?, b))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates `(index, value)` over the occupied slots, locking one slot at
+    /// a time. Slots added or taken during iteration may or may not be seen.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, SlotRef<'_, T>)> + '_ {
+        let end = self.next.load(Ordering::Relaxed).min(self.capacity());
+        (0..end).filter_map(move |index| Some((index, self.get(index)?)))
+    }
+
+    /// Iterates `(index, value)` over copies of the occupied values, for
+    /// readers that must never wait on a writer. A slot that is being
+    /// written at the time is skipped rather than waited for, as is one whose
+    /// version moved on while it was being copied, so every value seen is
+    /// one the slot held as a whole.
+    pub fn iter_consistent(&self) -> impl Iterator<Item = (usize, T)> + '_
+    where
+        T: Clone,
+    {
+        let end = self.next.load(Ordering::Relaxed).min(self.capacity());
+        (0..end).filter_map(move |index| {
+            let slot = self.slot(index)?;
+            let before = slot.version.load(Ordering::Acquire);
+            let item = slot.value.try_read().ok()?.clone()?;
+            if slot.version.load(Ordering::Acquire) != before {
+                return None;
+            }
+            Some((index, item))
+        })
+    }
+
+    #[cfg(feature = "futures")]
+    pub(crate) fn has_free_slot(&self) -> bool {
+        let free = self.free.lock().unwrap_or_else(PoisonError::into_inner);
+        !free.is_empty() || self.next.load(Ordering::Relaxed) < self.capacity()
+    }
+
+    // A copy of the value at `index` if the slot is still at `version`
+    #[cfg(feature = "futures")]
+    pub(crate) fn clone_if_version(&self, index: usize, Version(version): Version) -> Option<T>
+    where
+        T: Clone,
+    {
+        let slot = self.slot(index)?;
+        let value = slot.value.read().unwrap_or_else(PoisonError::into_inner);
+        if slot.version.load(Ordering::Relaxed) != version {
+            return None;
+        }
+        value.clone()
+    }
+
+    pub(crate) fn version(&self, index: usize) -> Option<Version> {
+        let version = self.slot(index)?.version.load(Ordering::Relaxed);
+        Some(Version(version))
+    }
+
+    fn pop_free(&self) -> Option<usize> {
+        self.free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+    }
+
+    fn slot(&self, index: usize) -> Option<&Slot<T>> {
+        let segment = self.segments.get(index / self.segment_size)?;
+        let segment = segment.load(Ordering::Acquire);
+        if segment.is_null() {
+            return None;
+        }
+
+        // Safety: published segments are never freed or moved before the
+        // collection is dropped
+        let segment = unsafe { &*segment };
+        segment.get(index % self.segment_size)
+    }
+
+    fn slot_or_grow(&self, index: usize) -> &Slot<T> {
+        if let Some(slot) = self.slot(index) {
+            return slot;
+        }
+
+        let entry = &self.segments[index / self.segment_size];
+        let segment = Box::into_raw(Box::new(Segment::new(self.segment_size, self.align)));
+
+        if let Err(_existing) = entry.compare_exchange(
+            ptr::null_mut(),
+            segment,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            // Another thread published the segment first
+            // Safety: ours was never shared
+            drop(unsafe { Box::from_raw(segment) });
+        }
+
+        // Either way the segment exists now
+        self.slot(index).unwrap()
+    }
+}
+
+impl<T> Default for ConcurrentCollection<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for ConcurrentCollection<T> {
+    fn drop(&mut self) {
+        for segment in self.segments.iter() {
+            // Not `get_mut`, which the model checkers' atomics don't have
+            let segment = segment.load(Ordering::Acquire);
+            if !segment.is_null() {
+                // Safety: we have exclusive access, and every non-null
+                // pointer came from `Box::into_raw`
+                drop(unsafe { Box::from_raw(segment) });
+            }
+        }
+    }
+}
+
+/// A locked, occupied slot of a `ConcurrentCollection`.
+pub struct SlotRef<'a, T> {
+    guard: RwLockReadGuard<'a, Option<T>>,
+}
+
+impl<T> Deref for SlotRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // `get` only hands out guards for occupied slots, and nobody can
+        // take the value while we hold the read lock
+        self.guard.as_ref().unwrap()
+    }
+}
+
+/// An exclusively locked, occupied slot of a `ConcurrentCollection`.
+pub struct SlotGuard<'a, T> {
+    guard: RwLockWriteGuard<'a, Option<T>>,
+    version: &'a AtomicU64,
+}
+
+impl<T> SlotGuard<'_, T> {
+    pub(crate) fn version(&self) -> Version {
+        Version(self.version.load(Ordering::Relaxed))
+    }
+}
+
+// Any exclusive access counts as a write, whether or not it wrote
+impl<T> Drop for SlotGuard<'_, T> {
+    fn drop(&mut self) {
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<T> Deref for SlotGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Same reasoning as for `SlotRef`
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for SlotGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+#[cfg(all(test, not(any(loom, shuttle))))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn cache_line_aligned_slots_dont_share_lines() {
+        let coll = ConcurrentCollection::with_layout(4, 2, Align::CacheLine);
+        coll.add(1u8);
+        coll.add(2u8);
+
+        let a = coll.slot(0).unwrap() as *const Slot<u8> as usize;
+        let b = coll.slot(1).unwrap() as *const Slot<u8> as usize;
+        assert_eq!(a % 128, 0);
+        assert_eq!(b - a, 128);
+        assert_eq!(*coll.get(1).unwrap(), 2);
+        assert_eq!(coll.alignment(), Align::CacheLine);
+    }
+
+    #[test]
+    fn grows_segment_by_segment() {
+        let coll = ConcurrentCollection::with_segments(2, 3);
+        assert_eq!(coll.capacity(), 6);
+
+        for i in 0..5 {
+            assert_eq!(coll.add(i), i);
+        }
+        let first = coll.get(0).unwrap();
+        assert_eq!(coll.add(5), 5);
+
+        // Growing didn't move the slot we're holding on to
+        assert_eq!(*first, 0);
+        drop(first);
+
+        assert_eq!(coll.try_add(6), Err(6));
+        assert_eq!(coll.take(3), Some(3));
+        assert_eq!(coll.take(3), None);
+        assert_eq!(coll.try_add(7), Ok(3));
+        assert_eq!(coll.len(), 6);
+    }
+
+    #[test]
+    fn threads_add_and_take_concurrently() {
+        let coll = ConcurrentCollection::with_segment_size(16);
+
+        thread::scope(|s| {
+            for t in 0..4 {
+                let coll = &coll;
+                s.spawn(move || {
+                    for i in 0..100 {
+                        let index = coll.add(t * 1000 + i);
+                        assert_eq!(*coll.get(index).unwrap(), t * 1000 + i);
+                        if i % 2 == 0 {
+                            assert_eq!(coll.take(index), Some(t * 1000 + i));
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(coll.len(), 200);
+        assert_eq!(coll.iter().count(), 200);
+    }
+
+    #[test]
+    fn consistent_iteration_skips_slots_being_written() {
+        let coll = ConcurrentCollection::with_segment_size(4);
+        for name in ["a", "b", "c"] {
+            coll.add(name.to_string());
+        }
+        coll.take(1);
+
+        let mut writing = coll.lock(2).unwrap();
+        writing.push_str(" (half written)");
+        let seen: Vec<_> = coll.iter_consistent().collect();
+        assert_eq!(seen, vec![(0, "a".to_string())]);
+
+        drop(writing);
+        let seen: Vec<_> = coll.iter_consistent().map(|(index, _)| index).collect();
+        assert_eq!(seen, vec![0, 2]);
+    }
+
+    #[test]
+    fn lock_pair_in_either_order_doesnt_deadlock() {
+        let coll = ConcurrentCollection::new();
+        let a = coll.add(0);
+        let b = coll.add(0);
+
+        thread::scope(|s| {
+            for (first, second) in [(a, b), (b, a)] {
+                let coll = &coll;
+                s.spawn(move || {
+                    for _ in 0..1000 {
+                        let (mut x, mut y) = coll.lock_pair(first, second).unwrap();
+                        *x += 1;
+                        *y += 1;
+                    }
+                });
+            }
+        });
+
+        assert_eq!(*coll.get(a).unwrap(), 2000);
+        *coll.lock(b).unwrap() -= 1;
+        assert_eq!(*coll.get(b).unwrap(), 1999);
+
+        coll.take(b);
+        assert!(coll.lock(b).is_none());
+        assert!(coll.lock_pair(a, b).is_none());
+    }
+}
+
+// RUSTFLAGS="--cfg loom" cargo test --release --lib concurrent::loom_tests
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    #[test]
+    fn racing_adds_get_distinct_slots() {
+        loom::model(|| {
+            let coll = Arc::new(ConcurrentCollection::with_segments(1, 2));
+            let other = Arc::clone(&coll);
+            let handle = thread::spawn(move || other.add('a'));
+            let b = coll.add('b');
+            let a = handle.join().unwrap();
+
+            assert_ne!(a, b);
+            assert_eq!(coll.len(), 2);
+            assert_eq!(coll.get(a).as_deref(), Some(&'a'));
+            assert_eq!(coll.get(b).as_deref(), Some(&'b'));
+        });
+    }
+
+    #[test]
+    fn take_races_with_get_and_reuse() {
+        loom::model(|| {
+            let coll = Arc::new(ConcurrentCollection::with_segments(1, 2));
+            let index = coll.add(1);
+
+            let other = Arc::clone(&coll);
+            let handle = thread::spawn(move || other.take(index));
+            // Either the value or the vacancy, never anything torn
+            let seen = coll.get(index).map(|value| *value);
+            assert!(seen.is_none() || seen == Some(1));
+
+            assert_eq!(handle.join().unwrap(), Some(1));
+            assert_eq!(coll.add(2), index);
+            assert_eq!(coll.len(), 1);
+        });
+    }
+}
+
+// RUSTFLAGS="--cfg shuttle" cargo test --release --lib concurrent::shuttle_tests
+#[cfg(all(test, shuttle, not(loom)))]
+mod shuttle_tests {
+    use super::*;
+    use shuttle::sync::Arc;
+    use shuttle::thread;
+
+    #[test]
+    fn random_schedules_keep_len_and_slots_consistent() {
+        shuttle::check_random(
+            || {
+                let coll = Arc::new(ConcurrentCollection::with_segments(2, 6));
+                let handles: Vec<_> = (0..4)
+                    .map(|t| {
+                        let coll = Arc::clone(&coll);
+                        thread::spawn(move || {
+                            let mut mine = Vec::new();
+                            for i in 0..3 {
+                                mine.push(coll.add(t * 10 + i));
+                            }
+                            let taken = coll.take(mine[1]);
+                            assert_eq!(taken, Some(t * 10 + 1));
+                            for &index in &[mine[0], mine[2]] {
+                                assert_eq!(coll.get(index).map(|v| *v / 10), Some(t));
+                            }
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+
+                assert_eq!(coll.len(), 8);
+                assert_eq!(coll.iter().count(), 8);
+            },
+            1000,
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Cross-checking the bookkeeping against the slots after every mutation.
+//!
+//! Debug builds call `debug_assert_consistent` at the end of every method
+//! that changes the slots, so a bug shows up at the call that caused it.
+//! `IndexMut` can change a slot behind the bookkeeping's back, which the
+//! collection repairs lazily, so the checks are skipped until the next
+//! `resync`.
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    pub(crate) fn is_consistent(&self) -> bool {
+        self.inconsistency().is_none()
+    }
+
+    #[track_caller]
+    pub(crate) fn debug_assert_consistent(&self) {
+        if cfg!(all(debug_assertions, not(feature = "no-panic"))) && !self.is_consistent() {
+            // Only worked out again once something is wrong
+            let inconsistency = self.inconsistency().unwrap();
+            panic!("Collection is inconsistent: {}", inconsistency);
+        }
+    }
+
+    fn inconsistency(&self) -> Option<String> {
+        if self.touched_through_index_mut {
+            return None;
+        }
+
+        let occupied = self.inner.iter().flatten().count();
+        if occupied != self.occupied {
+            return Some(format!(
+                "{} slots are occupied but the count is {}",
+                occupied, self.occupied
+            ));
+        }
+
+        // The list is a set, so no index can be on it twice
+        if let Some(index) = self
+            .free
+            .iter()
+            .find(|&index| self.inner.get(index).is_none_or(Option::is_some))
+        {
+            return Some(format!("{} is on the free list but not vacant", index));
+        }
+        let unlisted = (0..self.inner.len()).find(|&index| {
+            self.inner[index].is_none() && !self.free.contains(index) && self.is_reusable(index)
+        });
+        if let Some(index) = unlisted {
+            return Some(format!("{} is vacant but not on the free list", index));
+        }
+
+        self.groups
+            .check(&self.inner)
+            .and_then(|()| self.occupancy.check(&self.inner))
+            .and_then(|()| self.bitmap.check(&self.inner))
+            .err()
+    }
+}
+
+#[cfg(all(test, not(feature = "strict")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_mut_suspends_checks_until_resync() {
+        let mut coll = Collection::new();
+        coll.add(1);
+        coll.add(2);
+        assert!(coll.is_consistent());
+
+        coll[1] = None;
+        assert!(coll.is_consistent());
+        coll.resync();
+        assert_eq!(coll.vacant_len(), 1);
+
+        coll.free.push_back(0);
+        assert!(!coll.is_consistent());
+    }
+}
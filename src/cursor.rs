@@ -0,0 +1,129 @@
+//! Resumable scans that notice when the collection changed in between.
+//!
+//! `iter_mut_from` resumes a scan that was split across frames, but if
+//! slots were filled, vacated or moved in between it silently skips or
+//! repeats values. A `Cursor` remembers both where the scan got to and the
+//! collection's change counter, and `try_iter_mut` reports a
+//! `StructuralChange` instead of resuming a scan that has gone stale. While
+//! a `TryIterMut` is alive the borrow rules already rule out changes, so
+//! the check is only needed between resumes.
+use std::error::Error;
+use std::fmt;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::{Collection, IterMut};
+
+/// Where a scan got to, created by `cursor` and advanced by `try_iter_mut`.
+/// It holds no borrow, so the collection can be used between resumes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pos: usize,
+    seen: u64,
+}
+
+impl Cursor {
+    /// The slot index the scan resumes at.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+}
+
+/// Yielded by `try_iter_mut` when slots were filled, vacated or moved since
+/// the cursor was created. `pos` is where the scan got to, to restart from
+/// with `cursor_from` if skipping or repeating values is acceptable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StructuralChange {
+    pub pos: usize,
+}
+
+impl fmt::Display for StructuralChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the collection changed structurally since the scan reached slot {}",
+            self.pos
+        )
+    }
+}
+
+impl Error for StructuralChange {}
+
+/// Mutable iterator resuming a `Cursor`, created by `try_iter_mut`.
+pub struct TryIterMut<'a, 'c, T> {
+    cursor: &'c mut Cursor,
+    // `Err` until the change has been reported, then `None`
+    slots: Result<IterMut<'a, T>, Option<StructuralChange>>,
+}
+
+impl<'a, T> Iterator for TryIterMut<'a, '_, T> {
+    type Item = Result<&'a mut T, StructuralChange>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.slots {
+            Ok(slots) => {
+                let item = slots.next();
+                self.cursor.pos = slots.pos();
+                item.map(Ok)
+            }
+            Err(change) => change.take().map(Err),
+        }
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// A cursor at the first slot.
+    pub fn cursor(&self) -> Cursor {
+        self.cursor_from(0)
+    }
+
+    pub fn cursor_from(&self, pos: usize) -> Cursor {
+        Cursor {
+            pos,
+            seen: self.structure_changes,
+        }
+    }
+
+    /// Like `iter_mut_from(cursor.pos())`, advancing the cursor as values
+    /// are yielded. If the collection changed structurally since the cursor
+    /// was created the only item is a `StructuralChange`, and the cursor
+    /// stays stale.
+    pub fn try_iter_mut<'c>(&mut self, cursor: &'c mut Cursor) -> TryIterMut<'_, 'c, T> {
+        let slots = if cursor.seen == self.structure_changes {
+            Ok(self.iter_mut_from(cursor.pos))
+        } else {
+            Err(Some(StructuralChange { pos: cursor.pos }))
+        };
+        TryIterMut { cursor, slots }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resumes_until_the_structure_changes() {
+        let mut coll = Collection::new();
+        for i in 0..6 {
+            coll.add(i);
+        }
+        let mut cursor = coll.cursor();
+        for item in coll.try_iter_mut(&mut cursor).take(2) {
+            *item.unwrap() += 10;
+        }
+        assert_eq!(cursor.pos(), 2);
+
+        // Changing values isn't structural
+        *coll.get_mut(4).unwrap() += 100;
+        let rest: Vec<_> = coll.try_iter_mut(&mut cursor).collect();
+        assert_eq!(rest, vec![Ok(&mut 2), Ok(&mut 3), Ok(&mut 104), Ok(&mut 5)]);
+
+        let mut cursor = coll.cursor();
+        coll.try_iter_mut(&mut cursor).next();
+        coll.take(0);
+        let mut resumed = coll.try_iter_mut(&mut cursor);
+        assert_eq!(resumed.next(), Some(Err(StructuralChange { pos: 1 })));
+        assert_eq!(resumed.next(), None);
+    }
+}
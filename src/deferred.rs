@@ -0,0 +1,165 @@
+//! Removals scheduled through a shared reference.
+//!
+//! `mark_for_removal` only needs `&self`, so it can be called while iterating
+//! the collection. The marked slots are vacated in one pass by
+//! `flush_removals` once the borrow is over. A mark follows its value when
+//! compaction or a reindex moves it, and goes away when the value is taken
+//! some other way, so a flush never hits whatever took the slot over.
+use std::sync::{Mutex, PoisonError};
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+#[derive(Debug, Default)]
+pub(crate) struct PendingRemovals(Mutex<Vec<usize>>);
+
+impl PendingRemovals {
+    pub(crate) const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    pub(crate) fn vacated(&mut self, index: usize) {
+        let pending = self.pending();
+        if !pending.is_empty() {
+            pending.retain(|&marked| marked != index);
+        }
+    }
+
+    pub(crate) fn moved(&mut self, old: usize, new: usize) {
+        for marked in self.pending() {
+            if *marked == old {
+                *marked = new;
+            }
+        }
+    }
+
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        for marked in self.pending() {
+            if *marked == a {
+                *marked = b;
+            } else if *marked == b {
+                *marked = a;
+            }
+        }
+    }
+
+    // Marks for slots at `len` and up refer to values that are gone
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.pending().retain(|&index| index < len);
+    }
+
+    fn pending(&mut self) -> &mut Vec<usize> {
+        self.0.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Marks the value at `index` for `flush_removals`. Does nothing if the
+    /// slot is vacant.
+    pub fn mark_for_removal(&self, index: usize) {
+        if self.get(index).is_none() {
+            return;
+        }
+        let mut pending = self
+            .pending_removals
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        pending.push(index);
+    }
+
+    /// Takes and drops every value marked for removal, returning how many
+    /// were removed. Indices that were marked twice or are already vacant
+    /// are skipped.
+    pub fn flush_removals(&mut self) -> usize {
+        let mut pending = std::mem::take(self.pending_removals.pending());
+        pending.sort_unstable();
+        pending.dedup();
+
+        // Compacting in between would move values under the indices still
+        // to come, so only compact once they're all vacated
+        let removed = pending
+            .into_iter()
+            .filter(|&index| self.vacate(index).is_some())
+            .count();
+        self.auto_compact();
+        self.debug_assert_consistent();
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AutoCompact;
+
+    #[test]
+    fn removals_marked_while_iterating_are_flushed_later() {
+        let mut coll = Collection::new();
+        for i in 0..6 {
+            coll.add(i);
+        }
+
+        coll.take(4);
+
+        for (index, value) in coll.enumerate() {
+            if value % 2 == 1 {
+                coll.mark_for_removal(index);
+            }
+        }
+        coll.mark_for_removal(1);
+        coll.mark_for_removal(99);
+        assert_eq!(coll.len(), 5);
+
+        assert_eq!(coll.flush_removals(), 3);
+        assert_eq!(coll.occupied_indices(), vec![0, 2]);
+        assert_eq!(coll.flush_removals(), 0);
+
+        coll.mark_for_removal(0);
+        coll.clear();
+        coll.add(7);
+        assert_eq!(coll.flush_removals(), 0);
+    }
+
+    #[test]
+    fn flushing_compacts_only_after_every_removal() {
+        let mut coll = Collection::new();
+        for i in 0..10 {
+            coll.add(i);
+        }
+        for i in 0..4 {
+            coll.take(i);
+        }
+        coll.set_auto_compact(Some(AutoCompact::when_vacancy_exceeds(0.3)));
+        for index in 4..7 {
+            coll.mark_for_removal(index);
+        }
+
+        assert_eq!(coll.flush_removals(), 3);
+        let mut kept = coll.iter_copied().collect::<Vec<_>>();
+        kept.sort_unstable();
+        assert_eq!(kept, vec![7, 8, 9]);
+    }
+
+    #[test]
+    fn marks_follow_values_moved_by_compaction() {
+        let mut coll = Collection::new();
+        for i in 0..10 {
+            coll.add(i);
+        }
+        coll.set_auto_compact(Some(AutoCompact::when_vacancy_exceeds(0.1)));
+        coll.mark_for_removal(9);
+        coll.mark_for_removal(1);
+        coll.take(0);
+        coll.take(1);
+        assert_eq!(coll.get(0), Some(&9));
+
+        for i in 100..103 {
+            coll.add(i);
+        }
+        assert_eq!(coll.flush_removals(), 1);
+        assert!(!coll.iter().any(|&value| value == 9));
+        assert_eq!(coll.len(), 10);
+    }
+}
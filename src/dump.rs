@@ -0,0 +1,67 @@
+//! Debug output with one line per slot.
+//!
+//! `{:?}` prints the slots and the number of occupied ones. `{:#?}` and `dump` print every
+//! slot on its own line with its index and occupancy, which is far easier to
+//! read (and grep) for large collections.
+use std::fmt;
+use std::io::{self, Write};
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+impl<T: fmt::Debug, Tag, A: Allocator> fmt::Debug for Collection<T, Tag, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !f.alternate() {
+            return f
+                .debug_struct("Collection")
+                .field("inner", &self.inner)
+                .field("len", &self.occupied)
+                .finish();
+        }
+
+        writeln!(f, "Collection {{")?;
+        writeln!(f, "    len: {},", self.occupied)?;
+        for (index, slot) in self.inner.iter().enumerate() {
+            match slot {
+                Some(value) => writeln!(f, "    [{}] occupied: {:?}", index, value)?,
+                None => writeln!(f, "    [{}] vacant", index)?,
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
+impl<T: fmt::Debug, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Writes the `{:#?}` representation straight to `w` without building
+    /// the whole string in memory first.
+    pub fn dump<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut w = io::BufWriter::new(w);
+        writeln!(w, "{:#?}", self)?;
+        w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternate_debug_prints_one_slot_per_line() {
+        let mut coll = Collection::new();
+        coll.add(1);
+        coll.add(2);
+        coll.take(0);
+
+        let expected = "Collection {\n    len: 1,\n    [0] vacant\n    [1] occupied: 2\n}";
+        assert_eq!(format!("{:#?}", coll), expected);
+        assert_eq!(
+            format!("{:?}", coll),
+            "Collection { inner: [None, Some(2)], len: 1 }"
+        );
+
+        let mut out = Vec::new();
+        coll.dump(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", expected));
+    }
+}
@@ -0,0 +1,177 @@
+//! Entry API shaped after the `slab` crate so code written against `slab`
+//! can switch over with minimal changes. `entry` hands out the same kinds
+//! of entries `HashMap` does, keyed by index.
+use allocator_api2::alloc::{Allocator, Global};
+
+use crate::Collection;
+
+pub enum Entry<'a, T, Tag = (), A: Allocator = Global> {
+    Occupied(OccupiedEntry<'a, T, Tag, A>),
+    Vacant(VacantEntry<'a, T, Tag, A>),
+}
+
+impl<'a, T, Tag, A: Allocator> Entry<'a, T, Tag, A> {
+    pub fn key(&self) -> usize {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: T) -> &'a mut T {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with(self, default: impl FnOnce() -> T) -> &'a mut T {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    pub fn and_modify(mut self, f: impl FnOnce(&mut T)) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+pub struct OccupiedEntry<'a, T, Tag = (), A: Allocator = Global> {
+    collection: &'a mut Collection<T, Tag, A>,
+    key: usize,
+}
+
+// The slot is occupied for as long as the entry borrows the collection
+impl<'a, T, Tag, A: Allocator> OccupiedEntry<'a, T, Tag, A> {
+    pub fn key(&self) -> usize {
+        self.key
+    }
+
+    pub fn get(&self) -> &T {
+        self.collection.inner[self.key].as_ref().unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.collection.inner[self.key].as_mut().unwrap()
+    }
+
+    /// Like `get_mut`, but borrows for as long as the entry did.
+    pub fn into_mut(self) -> &'a mut T {
+        self.collection.inner[self.key].as_mut().unwrap()
+    }
+
+    /// Stores `value` in the slot and returns the old one. Keys to the old
+    /// value go stale, same as with `insert_at`.
+    pub fn replace_value(&mut self, value: T) -> T {
+        self.collection.insert_at(self.key, value).unwrap()
+    }
+
+    /// Takes the value, vacating the slot.
+    pub fn remove(self) -> T {
+        self.collection.try_take(self.key).unwrap()
+    }
+}
+
+pub struct VacantEntry<'a, T, Tag = (), A: Allocator = Global> {
+    collection: &'a mut Collection<T, Tag, A>,
+    key: usize,
+}
+
+impl<'a, T, Tag, A: Allocator> VacantEntry<'a, T, Tag, A> {
+    /// The index the value will be stored at once inserted.
+    pub fn key(&self) -> usize {
+        self.key
+    }
+
+    pub fn insert(self, value: T) -> &'a mut T {
+        // Through `add` when that's where it would go anyway, so it counts
+        // as an add, e.g. for `undo_last_add`
+        let index = if self.collection.next_index() == self.key {
+            self.collection.add(value)
+        } else {
+            self.collection.insert_at(self.key, value);
+            self.key
+        };
+        debug_assert_eq!(index, self.key);
+
+        // We just put it there
+        self.collection.inner[index].as_mut().unwrap()
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Reserves nothing, but lets the caller learn the index before inserting,
+    /// e.g. to store the index inside the value itself.
+    pub fn vacant_entry(&mut self) -> VacantEntry<'_, T, Tag, A> {
+        let key = self.next_index();
+        VacantEntry {
+            collection: self,
+            key,
+        }
+    }
+
+    /// The entry for the slot at `index`. Inserting into a vacant one
+    /// grows the collection if `index` is out of range, like `insert_at`.
+    pub fn entry(&mut self, index: usize) -> Entry<'_, T, Tag, A> {
+        if self.get(index).is_some() {
+            Entry::Occupied(OccupiedEntry {
+                collection: self,
+                key: index,
+            })
+        } else {
+            Entry::Vacant(VacantEntry {
+                collection: self,
+                key: index,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vacant_entry_key_matches_inserted_index() {
+        let mut coll = Collection::new();
+        coll.insert((0, 'a'));
+        coll.insert((1, 'b'));
+        coll.insert((2, 'c'));
+        coll.remove(1);
+
+        let entry = coll.vacant_entry();
+        let key = entry.key();
+        assert_eq!(key, 1);
+
+        let value = entry.insert((key, 'd'));
+        value.1 = 'e';
+        assert_eq!(coll[1], Some((1, 'e')));
+        assert_eq!(coll.vacant_entry().key(), 3);
+    }
+
+    #[test]
+    fn entries_work_like_hash_map_entries() {
+        let mut counts = Collection::new();
+        for index in [2, 0, 2, 2] {
+            *counts
+                .entry(index)
+                .and_modify(|count| *count += 1)
+                .or_insert(1) += 0;
+        }
+        assert_eq!(counts.occupied_indices(), vec![0, 2]);
+        assert_eq!(counts.get(2), Some(&3));
+
+        match counts.entry(2) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.replace_value(10), 3);
+                assert_eq!(*entry.get(), 10);
+                assert_eq!(entry.remove(), 10);
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert_eq!(counts.get(2), None);
+        assert_eq!(*counts.entry(5).or_insert_with(|| 7), 7);
+        assert_eq!(counts.entry(5).key(), 5);
+    }
+}
@@ -0,0 +1,57 @@
+//! Comparing collections.
+//!
+//! Two collections are equal when they hold the same values at the same
+//! indices. How many vacant slots trail the last value, and how much
+//! capacity is allocated, depends on history like compaction or `clear`,
+//! so neither counts. `layout_eq` is there for when it should.
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+impl<T: PartialEq, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Whether both collections hold the same `(index, value)` pairs. This is
+    /// what `==` compares.
+    pub fn content_eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.enumerate().eq(other.enumerate())
+    }
+
+    /// Like `content_eq`, but trailing vacant slots have to match as well.
+    pub fn layout_eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<T: PartialEq, Tag, A: Allocator> PartialEq for Collection<T, Tag, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.content_eq(other)
+    }
+}
+
+impl<T: Eq, Tag, A: Allocator> Eq for Collection<T, Tag, A> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_vacancies_only_matter_for_layout() {
+        let mut a = Collection::new();
+        a.add(1);
+        a.add(2);
+        a.take(1);
+
+        let mut b = Collection::new();
+        b.add(1);
+
+        assert!(a.content_eq(&b));
+        assert_eq!(a, b);
+        assert!(!a.layout_eq(&b));
+
+        b.insert_at(1, 2);
+        b.take(1);
+        assert!(a.layout_eq(&b));
+
+        b.insert_at(3, 1);
+        assert_ne!(a, b);
+    }
+}
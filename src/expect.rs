@@ -0,0 +1,96 @@
+//! Accessors that panic with a useful message when a slot isn't occupied.
+//!
+//! They and the other panicking accessors are `#[track_caller]`, so a panic
+//! points at the line that passed the bad index rather than into this crate,
+//! and says whether the slot was vacant, retired or out of range.
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// The value at `index`.
+    ///
+    /// # Panics
+    ///
+    /// If the slot at `index` is vacant or out of range.
+    #[track_caller]
+    pub fn assert_occupied(&self, index: usize) -> &T {
+        match self.get(index) {
+            Some(item) => item,
+            None => self.vacant_panic(index, None),
+        }
+    }
+
+    /// Like `assert_occupied`, with `msg` in front of the panic message.
+    #[track_caller]
+    pub fn expect_occupied(&self, index: usize, msg: &str) -> &T {
+        match self.get(index) {
+            Some(item) => item,
+            None => self.vacant_panic(index, Some(msg)),
+        }
+    }
+
+    #[track_caller]
+    pub fn expect_occupied_mut(&mut self, index: usize, msg: &str) -> &mut T {
+        if self.get(index).is_none() {
+            self.vacant_panic(index, Some(msg));
+        }
+        self.inner[index].as_mut().unwrap()
+    }
+
+    // Says why `index` has no value, which is only worth working out once
+    // we're panicking
+    #[cold]
+    #[track_caller]
+    pub(crate) fn vacant_panic(&self, index: usize, msg: Option<&str>) -> ! {
+        let reason = if index >= self.inner.len() {
+            format!(
+                "index {} is out of range for a collection of {} slots",
+                index,
+                self.inner.len()
+            )
+        } else if self.generations.is_retired(index) {
+            format!("slot {} is vacant and retired", index)
+        } else {
+            format!("slot {} is vacant", index)
+        };
+        match msg {
+            Some(msg) => panic!("{}: {}", msg, reason),
+            None => panic!("{}", reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+
+    fn panic_message(f: impl FnOnce()) -> String {
+        let payload = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_err();
+        payload.downcast::<String>().map(|msg| *msg).unwrap()
+    }
+
+    #[test]
+    fn panics_say_why_the_slot_is_empty() {
+        let mut coll = Collection::new();
+        coll.add("a");
+        coll.add("b");
+        coll.take(0);
+
+        assert_eq!(coll.assert_occupied(1), &"b");
+        *coll.expect_occupied_mut(1, "renaming") = "c";
+        assert_eq!(
+            panic_message(|| {
+                coll.expect_occupied(0, "looking up session");
+            }),
+            "looking up session: slot 0 is vacant"
+        );
+        assert_eq!(
+            panic_message(|| {
+                coll.take(5);
+            }),
+            "index 5 is out of range for a collection of 2 slots"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Values that expire at a deadline.
+//!
+//! Deadlines are plain `u64` ticks supplied by the caller rather than
+//! `Instant`s, so the clock can be a hardware timer, a frame counter or
+//! anything else that only moves forward. Nothing expires on its own,
+//! `purge_expired` takes everything whose deadline has passed.
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::Deref;
+
+use crate::{Collection, Key};
+
+pub struct ExpiringCollection<T, Tag = ()> {
+    collection: Collection<T, Tag>,
+    // Earliest deadline first. Entries whose key went stale, or whose slot
+    // got a new deadline since, are skipped when they come up.
+    queue: BinaryHeap<Reverse<(u64, Key<Tag>)>>,
+    deadlines: Vec<Option<u64>>,
+}
+
+impl<T> ExpiringCollection<T> {
+    pub fn new() -> Self {
+        Self::new_tagged()
+    }
+}
+
+impl<T, Tag> ExpiringCollection<T, Tag> {
+    pub fn new_tagged() -> Self {
+        Self {
+            collection: Collection::new_tagged(),
+            queue: BinaryHeap::new(),
+            deadlines: Vec::new(),
+        }
+    }
+
+    /// Stores `item` until tick `deadline` has been reached.
+    pub fn add(&mut self, item: T, deadline: u64) -> usize {
+        let index = self.collection.add(item);
+        self.set_deadline(index, deadline);
+        index
+    }
+
+    /// Moves the deadline of the value at `index`, e.g. to keep a session
+    /// alive. Returns `false` if the slot is vacant.
+    pub fn set_deadline(&mut self, index: usize, deadline: u64) -> bool {
+        let key = match self.collection.key_for_index(index) {
+            Some(key) => key,
+            None => return false,
+        };
+
+        if self.deadlines.len() <= index {
+            self.deadlines.resize(index + 1, None);
+        }
+        self.deadlines[index] = Some(deadline);
+        self.queue.push(Reverse((deadline, key)));
+        true
+    }
+
+    pub fn deadline(&self, index: usize) -> Option<u64> {
+        self.collection.get(index)?;
+        self.deadlines.get(index).copied().flatten()
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.collection.get_mut(index)
+    }
+
+    pub fn take(&mut self, index: usize) -> Option<T> {
+        let item = self.collection.try_take(index)?;
+        self.deadlines[index] = None;
+        Some(item)
+    }
+
+    /// Takes every value whose deadline is at or before `now`, earliest
+    /// deadline first. Only looks at what has expired, plus any entries left
+    /// behind by `take` or `set_deadline`.
+    pub fn purge_expired(&mut self, now: u64) -> Vec<(usize, T)> {
+        let mut expired = Vec::new();
+        while let Some(&Reverse((deadline, key))) = self.queue.peek() {
+            if deadline > now {
+                break;
+            }
+            self.queue.pop();
+
+            let index = key.index();
+            if self.collection.contains_key(key) && self.deadlines[index] == Some(deadline) {
+                // Just checked the key
+                expired.push((index, self.take(index).unwrap()));
+            }
+        }
+        expired
+    }
+
+    pub fn into_inner(self) -> Collection<T, Tag> {
+        self.collection
+    }
+}
+
+impl<T> Default for ExpiringCollection<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// No `DerefMut`, values added through it would never expire
+impl<T, Tag> Deref for ExpiringCollection<T, Tag> {
+    type Target = Collection<T, Tag>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.collection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn purges_in_deadline_order_and_respects_refreshes() {
+        let mut sessions = ExpiringCollection::new();
+        let a = sessions.add("a", 10);
+        let b = sessions.add("b", 5);
+        let c = sessions.add("c", 7);
+
+        assert!(sessions.purge_expired(4).is_empty());
+        sessions.set_deadline(c, 20);
+        assert_eq!(sessions.purge_expired(10), vec![(b, "b"), (a, "a")]);
+
+        // A reused slot doesn't inherit the old deadline
+        sessions.take(c);
+        let d = sessions.add("d", 30);
+        assert_eq!(d, a);
+        assert_eq!(sessions.purge_expired(25), vec![]);
+        assert_eq!(sessions.deadline(d), Some(30));
+        assert_eq!(sessions.len(), 1);
+    }
+}
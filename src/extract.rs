@@ -0,0 +1,249 @@
+//! Taking the values that match a predicate while iterating, or many values
+//! by index at once.
+use std::ops::{Bound, RangeBounds};
+
+use allocator_api2::alloc::{Allocator, Global};
+
+use crate::compact::AutoCompact;
+use crate::Collection;
+
+/// Returned by `Collection::extract_if`.
+pub struct ExtractIf<'a, T, Tag, F, A: Allocator = Global> {
+    collection: &'a mut Collection<T, Tag, A>,
+    pred: F,
+    pos: usize,
+    // Compaction would move values past `pos`, so it waits until we're done
+    paused: Option<AutoCompact>,
+}
+
+impl<'a, T, Tag, F, A: Allocator> Iterator for ExtractIf<'a, T, Tag, F, A>
+where
+    F: FnMut(usize, &mut T) -> bool,
+{
+    type Item = (usize, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.collection.inner.len() {
+            let index = self.pos;
+            self.pos += 1;
+
+            let matches = match &mut self.collection.inner[index] {
+                Some(item) => (self.pred)(index, item),
+                None => false,
+            };
+            if matches {
+                // Occupied, we just looked
+                let item = self.collection.vacate(index).unwrap();
+                self.collection.debug_assert_consistent();
+                return Some((index, item));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.collection.inner.len() - self.pos))
+    }
+}
+
+impl<'a, T, Tag, F, A: Allocator> Drop for ExtractIf<'a, T, Tag, F, A> {
+    fn drop(&mut self) {
+        self.collection.auto_compact = self.paused.take();
+        self.collection.auto_compact();
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Iterates over the slots in order, taking and yielding `(index, value)`
+    /// for every value `pred` returns `true` for. Values it returns `false`
+    /// for stay where they are, as do the ones the iterator never got to if
+    /// it's dropped early.
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, T, Tag, F, A>
+    where
+        F: FnMut(usize, &mut T) -> bool,
+    {
+        let paused = self.auto_compact.take();
+        ExtractIf {
+            collection: self,
+            pred,
+            pos: 0,
+            paused,
+        }
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Drops every value `pred` returns `false` for.
+    pub fn retain(&mut self, pred: impl FnMut(usize, &mut T) -> bool) {
+        self.retain_in_range(.., pred);
+    }
+
+    /// Like `retain`, but only looks at the slots in `range`. Compacts at
+    /// most once, after the whole range has been visited.
+    pub fn retain_in_range(
+        &mut self,
+        range: impl RangeBounds<usize>,
+        mut pred: impl FnMut(usize, &mut T) -> bool,
+    ) {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.saturating_add(1),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => usize::MAX,
+        };
+        let end = end.min(self.inner.len());
+
+        for index in start.min(end)..end {
+            if let Some(item) = &mut self.inner[index] {
+                if !pred(index, item) {
+                    self.vacate_in_place(index);
+                }
+            }
+        }
+
+        self.auto_compact();
+        self.debug_assert_consistent();
+    }
+
+    /// Drops the value at `index` right in its slot, which saves moving a
+    /// large value out just to drop it. Returns `false` if the slot was
+    /// already vacant.
+    pub fn discard(&mut self, index: usize) -> bool {
+        let discarded = self.vacate_in_place(index);
+        self.auto_compact();
+        self.debug_assert_consistent();
+        discarded
+    }
+
+    /// Discards every value `pred` returns `true` for, returning how many
+    /// there were. Compacts at most once, at the end.
+    pub fn discard_all_where(&mut self, mut pred: impl FnMut(usize, &T) -> bool) -> usize {
+        let mut discarded = 0;
+        for index in 0..self.inner.len() {
+            if matches!(&self.inner[index], Some(item) if pred(index, item)) {
+                self.vacate_in_place(index);
+                discarded += 1;
+            }
+        }
+
+        self.auto_compact();
+        self.debug_assert_consistent();
+        discarded
+    }
+
+    /// Takes the value at every index in `indices`, which must be sorted
+    /// and free of duplicates, returning them in the same order. Compacts
+    /// at most once, after everything has been taken.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if `indices` isn't strictly ascending. Release
+    /// builds take whatever they're given, a repeated index comes back as
+    /// `None` after the first time.
+    pub fn apply_sorted_removals(&mut self, indices: &[usize]) -> Vec<Option<T>> {
+        if cfg!(all(debug_assertions, not(feature = "no-panic"))) {
+            if let Some(pair) = indices.windows(2).find(|pair| pair[0] >= pair[1]) {
+                panic!(
+                    "indices passed to `apply_sorted_removals` aren't strictly ascending: \
+                     {} is followed by {}",
+                    pair[0], pair[1]
+                );
+            }
+        }
+
+        let items = indices.iter().map(|&index| self.vacate(index)).collect();
+        self.auto_compact();
+        self.debug_assert_consistent();
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_matches_and_stops_when_dropped() {
+        let mut coll = Collection::new();
+        for i in 0..8 {
+            coll.add(i);
+        }
+        coll.take(2);
+
+        let mut evens = coll.extract_if(|_, value| *value % 2 == 0);
+        assert_eq!(evens.next(), Some((0, 0)));
+        assert_eq!(evens.next(), Some((4, 4)));
+        drop(evens);
+
+        assert_eq!(coll.to_owned_values(), vec![1, 3, 5, 6, 7]);
+        assert_eq!(coll.len(), 5);
+        assert_eq!(coll.add(9), 0);
+
+        let rest: Vec<_> = coll.extract_if(|index, _| index > 4).collect();
+        assert_eq!(rest, vec![(5, 5), (6, 6), (7, 7)]);
+        assert_eq!(coll.vacant_len(), 5);
+    }
+
+    #[test]
+    fn retains_only_within_the_range() {
+        let mut registry = Collection::new();
+        for i in 0..10 {
+            registry.add(i);
+        }
+
+        registry.retain_in_range(4..8, |_, value| *value % 2 == 0);
+        assert_eq!(registry.to_owned_values(), vec![0, 1, 2, 3, 4, 6, 8, 9]);
+
+        registry.retain_in_range(8..=20, |index, _| index != 9);
+        registry.retain(|_, value| *value > 0);
+        assert_eq!(registry.to_owned_values(), vec![1, 2, 3, 4, 6, 8]);
+        assert_eq!(registry.vacant_len(), 4);
+    }
+
+    #[test]
+    fn compaction_waits_until_extraction_is_done() {
+        let mut coll = Collection::new();
+        coll.set_auto_compact(Some(AutoCompact::when_vacancy_exceeds(0.1)));
+        for i in 0..6 {
+            coll.add(i);
+        }
+
+        let taken: Vec<_> = coll.extract_if(|_, value| *value < 3).collect();
+        assert_eq!(taken, vec![(0, 0), (1, 1), (2, 2)]);
+        assert_eq!(coll.to_owned_values(), vec![5, 4, 3]);
+    }
+
+    #[test]
+    fn sorted_removals_come_back_in_order() {
+        let mut coll = Collection::new();
+        for i in 0..6 {
+            coll.add(i * 10);
+        }
+        coll.take(3);
+
+        let taken = coll.apply_sorted_removals(&[1, 3, 4, 9]);
+        assert_eq!(taken, vec![Some(10), None, Some(40), None]);
+        assert_eq!(coll.to_owned_values(), vec![0, 20, 50]);
+        assert_eq!(coll.vacant_len(), 3);
+    }
+
+    #[test]
+    fn discards_drop_values_in_their_slots() {
+        let mut coll = Collection::new();
+        for i in 0..6 {
+            coll.add([i as u8; 4096]);
+        }
+        let stale = coll.key_for_index(1).unwrap();
+
+        assert!(coll.discard(1));
+        assert!(!coll.discard(1));
+        assert_eq!(coll.get_key(stale), None);
+        assert_eq!(coll.discard_all_where(|index, _| index % 2 == 0), 3);
+        assert_eq!(coll.occupied_indices(), vec![3, 5]);
+        assert_eq!(coll.add([9; 4096]), 0);
+    }
+}
@@ -0,0 +1,148 @@
+//! The vacant indices `add` reuses.
+//!
+//! `add_low` wants the lowest vacancy and `add_hot` the most recently freed
+//! one, so the indices are kept both in order and in the order they were
+//! freed. Taking one from either end removes it from the ordered set right
+//! away and from the recency queue lazily: entries the set no longer holds
+//! are skipped when they come up, and the queue is rebuilt once they make up
+//! most of it. Both kinds of add are O(log vacancies) amortized.
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::ops::RangeBounds;
+
+#[derive(Debug, Default)]
+pub(crate) struct FreeList {
+    ordered: BTreeSet<usize>,
+    // Least recently freed first, possibly with stale or repeated entries
+    recency: VecDeque<usize>,
+}
+
+impl FreeList {
+    pub(crate) const fn new() -> Self {
+        Self {
+            ordered: BTreeSet::new(),
+            recency: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.ordered.len()
+    }
+
+    pub(crate) fn contains(&self, index: usize) -> bool {
+        self.ordered.contains(&index)
+    }
+
+    // In index order
+    pub(crate) fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.ordered.iter().copied()
+    }
+
+    pub(crate) fn range(&self, range: impl RangeBounds<usize>) -> impl Iterator<Item = usize> + '_ {
+        self.ordered.range(range).copied()
+    }
+
+    pub(crate) fn lowest(&self) -> Option<usize> {
+        self.ordered.first().copied()
+    }
+
+    pub(crate) fn push_back(&mut self, index: usize) {
+        if self.ordered.insert(index) {
+            self.recency.push_back(index);
+        }
+    }
+
+    pub(crate) fn pop_lowest(&mut self) -> Option<usize> {
+        let index = self.ordered.pop_first()?;
+        self.shrink_recency();
+        Some(index)
+    }
+
+    pub(crate) fn pop_most_recent(&mut self) -> Option<usize> {
+        while let Some(index) = self.recency.pop_back() {
+            if self.ordered.remove(&index) {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    pub(crate) fn remove(&mut self, index: usize) -> bool {
+        let removed = self.ordered.remove(&index);
+        self.shrink_recency();
+        removed
+    }
+
+    pub(crate) fn retain(&mut self, f: impl FnMut(&usize) -> bool) {
+        self.ordered.retain(f);
+        self.shrink_recency();
+    }
+
+    // Drops the indices from `len` up
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.ordered.split_off(&len);
+        self.shrink_recency();
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.ordered.clear();
+        self.recency.clear();
+    }
+
+    // Empties the list, returning the indices in order
+    pub(crate) fn take_all(&mut self) -> Vec<usize> {
+        self.recency.clear();
+        std::mem::take(&mut self.ordered).into_iter().collect()
+    }
+
+    // Rebuilds the queue once most of it is stale, keeping the most recent
+    // entry for every index still in the set
+    fn shrink_recency(&mut self) {
+        if self.recency.len() <= 2 * self.ordered.len() + 16 {
+            return;
+        }
+        let mut seen = HashSet::with_capacity(self.ordered.len());
+        let mut recency: Vec<usize> = self
+            .recency
+            .iter()
+            .rev()
+            .copied()
+            .filter(|index| self.ordered.contains(index) && seen.insert(*index))
+            .collect();
+        recency.reverse();
+        self.recency = recency.into();
+    }
+}
+
+impl Extend<usize> for FreeList {
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, indices: I) {
+        for index in indices {
+            self.push_back(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_from_either_end_and_skips_stale_entries() {
+        let mut free = FreeList::new();
+        free.extend([5, 1, 9, 3]);
+        assert_eq!(free.pop_lowest(), Some(1));
+        assert_eq!(free.pop_most_recent(), Some(3));
+        assert!(free.remove(9));
+
+        free.push_back(1);
+        assert_eq!(free.pop_most_recent(), Some(1));
+        assert_eq!(free.pop_most_recent(), Some(5));
+        assert_eq!(free.pop_most_recent(), None);
+
+        for round in 0..100 {
+            free.push_back(round);
+            free.pop_lowest();
+        }
+        assert_eq!(free.len(), 0);
+        assert!(free.recency.len() <= 16);
+    }
+}
@@ -0,0 +1,147 @@
+//! Read-only snapshots of a collection.
+//!
+//! A `FrozenCollection` stores its values densely, with a table translating
+//! slot indices to positions, so iterating it never has to skip holes. It
+//! can't be changed, which makes it easy to share behind an `Arc` once a
+//! registry has been built.
+use std::slice;
+
+use allocator_api2::alloc::{Allocator, Global};
+
+use crate::{Collection, Key};
+
+const VACANT: u32 = u32::MAX;
+
+pub struct FrozenCollection<T, Tag = (), A: Allocator = Global> {
+    values: Vec<T>,
+    // The slot index of every value in `values`
+    indices: Vec<usize>,
+    // The position in `values` of every slot's value
+    positions: Vec<u32>,
+    // Everything but the values, put back together by `thaw`
+    shell: Collection<T, Tag, A>,
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Turns the collection into a read-only snapshot. Pending removals are
+    /// flushed first.
+    pub fn freeze(mut self) -> FrozenCollection<T, Tag, A> {
+        self.flush_removals();
+
+        let mut values = Vec::with_capacity(self.occupied);
+        let mut indices = Vec::with_capacity(self.occupied);
+        let mut positions = Vec::with_capacity(self.inner.len());
+
+        for (index, slot) in self.inner.drain(..).enumerate() {
+            match slot {
+                Some(value) => {
+                    positions.push(values.len() as u32);
+                    values.push(value);
+                    indices.push(index);
+                }
+                None => positions.push(VACANT),
+            }
+        }
+
+        FrozenCollection {
+            values,
+            indices,
+            positions,
+            shell: self,
+        }
+    }
+}
+
+impl<T, Tag, A: Allocator> FrozenCollection<T, Tag, A> {
+    /// Turns the snapshot back into a collection with the same slots, keys
+    /// and groups as the one that was frozen.
+    pub fn thaw(self) -> Collection<T, Tag, A> {
+        let mut coll = self.shell;
+        coll.inner.resize_with(self.positions.len(), || None);
+        for (index, value) in self.indices.into_iter().zip(self.values) {
+            coll.inner[index] = Some(value);
+        }
+        coll
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        match *self.positions.get(index)? {
+            VACANT => None,
+            pos => self.values.get(pos as usize),
+        }
+    }
+
+    pub fn get_key(&self, key: Key<Tag>) -> Option<&T> {
+        if self.shell.generations.get(key.index()) != key.generation() {
+            return None;
+        }
+        self.get(key.index())
+    }
+
+    pub fn contains_key(&self, key: Key<Tag>) -> bool {
+        self.get_key(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Iterates the values in slot order.
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.values.iter()
+    }
+
+    /// The slot index of every value, in the same order as `iter`.
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+}
+
+impl<'a, T, Tag, A: Allocator> IntoIterator for &'a FrozenCollection<T, Tag, A> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn frozen_collection_is_shareable_and_thaws() {
+        let mut coll = Collection::new();
+        let a = coll.add_key("a");
+        coll.add("b");
+        let c = coll.add_key("c");
+        coll.take(1);
+
+        let frozen = Arc::new(coll.freeze());
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let frozen = Arc::clone(&frozen);
+                thread::spawn(move || frozen.iter().copied().collect::<Vec<_>>())
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), vec!["a", "c"]);
+        }
+
+        assert_eq!(frozen.get(1), None);
+        assert_eq!(frozen.get_key(c), Some(&"c"));
+        assert_eq!(frozen.indices(), &[0, 2]);
+
+        let mut coll = Arc::try_unwrap(frozen).ok().unwrap().thaw();
+        assert_eq!(coll.get_key(a), Some(&"a"));
+        assert_eq!(coll.len(), 2);
+        assert_eq!(coll.add("d"), 1);
+    }
+}
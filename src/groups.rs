@@ -0,0 +1,176 @@
+//! Intrusive lists linking occupied slots into groups.
+//!
+//! Every slot can be a member of at most one group. The links live in a
+//! vector parallel to the slots, so iterating a group only touches its
+//! members instead of scanning the whole collection.
+use std::collections::BTreeMap;
+
+use allocator_api2::alloc::{Allocator, Global};
+
+use crate::Collection;
+
+pub type GroupId = u32;
+
+#[derive(Debug, Clone, Copy)]
+struct Link {
+    group: GroupId,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Groups {
+    links: Vec<Option<Link>>,
+    heads: BTreeMap<GroupId, usize>,
+}
+
+impl Groups {
+    pub(crate) const fn new() -> Self {
+        Self {
+            links: Vec::new(),
+            heads: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn link(&mut self, index: usize, group: GroupId) {
+        self.unlink(index);
+
+        if self.links.len() <= index {
+            self.links.resize(index + 1, None);
+        }
+
+        let next = self.heads.insert(group, index);
+        if let Some(Some(next)) = next.and_then(|next| self.links.get_mut(next)) {
+            next.prev = Some(index);
+        }
+
+        self.links[index] = Some(Link {
+            group,
+            prev: None,
+            next,
+        });
+    }
+
+    pub(crate) fn unlink(&mut self, index: usize) -> Option<GroupId> {
+        let link = self.links.get_mut(index)?.take()?;
+
+        match link.prev {
+            Some(prev) => {
+                if let Some(Some(prev)) = self.links.get_mut(prev) {
+                    prev.next = link.next;
+                }
+            }
+            None => match link.next {
+                Some(next) => {
+                    self.heads.insert(link.group, next);
+                }
+                None => {
+                    self.heads.remove(&link.group);
+                }
+            },
+        }
+
+        if let Some(Some(next)) = link.next.and_then(|next| self.links.get_mut(next)) {
+            next.prev = link.prev;
+        }
+
+        Some(link.group)
+    }
+
+    pub(crate) fn check<T>(&self, slots: &[Option<T>]) -> Result<(), String> {
+        for (index, link) in self.links.iter().enumerate() {
+            if link.is_some() && !slots.get(index).is_some_and(Option::is_some) {
+                return Err(format!("vacant slot {} is linked into a group", index));
+            }
+        }
+
+        for (group, head) in &self.heads {
+            match self.links.get(*head).copied().flatten() {
+                Some(link) if link.group == *group && link.prev.is_none() => (),
+                _ => return Err(format!("group {} has a broken head at {}", group, head)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Links the occupied slot at `index` into `group`, moving it out of any
+    /// group it currently belongs to.
+    pub fn add_to_group(&mut self, index: usize, group: GroupId) {
+        assert!(
+            self.inner[index].is_some(),
+            "Only occupied slots can be added to a group"
+        );
+        self.groups.link(index, group);
+    }
+
+    pub fn remove_from_group(&mut self, index: usize) -> Option<GroupId> {
+        self.groups.unlink(index)
+    }
+
+    pub fn group_of(&self, index: usize) -> Option<GroupId> {
+        self.groups.links.get(index)?.map(|link| link.group)
+    }
+
+    /// Iterates `(index, &value)` for every member of `group`, most recently
+    /// added first.
+    pub fn iter_group(&self, group: GroupId) -> GroupIter<'_, T, Tag, A> {
+        GroupIter {
+            inner: self,
+            next: self.groups.heads.get(&group).copied(),
+        }
+    }
+}
+
+pub struct GroupIter<'a, T, Tag = (), A: Allocator = Global> {
+    inner: &'a Collection<T, Tag, A>,
+    next: Option<usize>,
+}
+
+impl<'a, T, Tag, A: Allocator> Iterator for GroupIter<'a, T, Tag, A> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.next?;
+        let inner: &'a Collection<T, Tag, A> = self.inner;
+        self.next = inner.groups.links.get(index)?.and_then(|link| link.next);
+
+        // Members are unlinked when taken, so the slot is always occupied
+        inner.get(index).map(|value| (index, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_track_membership_through_take() {
+        let mut coll = Collection::new();
+        for i in 0..5 {
+            coll.add(i * 10);
+        }
+
+        coll.add_to_group(0, 1);
+        coll.add_to_group(2, 1);
+        coll.add_to_group(3, 2);
+        coll.add_to_group(4, 1);
+
+        let members: Vec<_> = coll.iter_group(1).collect();
+        assert_eq!(members, vec![(4, &40), (2, &20), (0, &0)]);
+
+        coll.take(2);
+        coll.add_to_group(4, 2);
+        assert_eq!(coll.group_of(2), None);
+        assert_eq!(coll.iter_group(1).collect::<Vec<_>>(), vec![(0, &0)]);
+        assert_eq!(
+            coll.iter_group(2).map(|(i, _)| i).collect::<Vec<_>>(),
+            vec![4, 3]
+        );
+
+        assert_eq!(coll.remove_from_group(0), Some(1));
+        assert_eq!(coll.iter_group(1).next(), None);
+    }
+}
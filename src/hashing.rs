@@ -0,0 +1,222 @@
+//! Hashing a collection's contents, so replicas can check they agree
+//! without shipping every value.
+//!
+//! The hash covers every `(index, value)` pair, so the same values at
+//! different indices hash differently. Pairs are hashed with FNV-1a, which
+//! doesn't depend on the platform or the Rust version, and combined by
+//! wrapping addition. That makes the result independent of the order the
+//! pairs are visited in, and lets `HashedCollection` update it in O(1).
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+// Integers are written little endian and `usize` as `u64`, so a value hashes
+// the same on every target
+struct StableHasher(u64);
+
+impl StableHasher {
+    fn new() -> Self {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, n: u16) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, n: u128) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_i16(&mut self, n: i16) {
+        self.write_u16(n as u16);
+    }
+
+    fn write_i32(&mut self, n: i32) {
+        self.write_u32(n as u32);
+    }
+
+    fn write_i64(&mut self, n: i64) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_i128(&mut self, n: i128) {
+        self.write_u128(n as u128);
+    }
+
+    fn write_isize(&mut self, n: isize) {
+        self.write_u64(n as u64);
+    }
+}
+
+fn pair_hash<T: Hash>(index: usize, item: &T) -> u64 {
+    let mut hasher = StableHasher::new();
+    hasher.write_usize(index);
+    item.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<T: Hash, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Hash of every `(index, value)` pair. Takes O(slots), see
+    /// `HashedCollection` for a version that keeps it up to date instead.
+    pub fn content_hash(&self) -> u64 {
+        self.enumerate().fold(0, |hash, (index, item)| {
+            hash.wrapping_add(pair_hash(index, item))
+        })
+    }
+}
+
+/// A collection that keeps its `content_hash` up to date as values come and
+/// go. Values can only be changed through `update`, which rehashes them.
+pub struct HashedCollection<T, Tag = ()> {
+    collection: Collection<T, Tag>,
+    hash: u64,
+}
+
+impl<T: Hash> HashedCollection<T> {
+    pub fn new() -> Self {
+        Self::from(Collection::new())
+    }
+}
+
+impl<T: Hash, Tag> HashedCollection<T, Tag> {
+    pub fn add(&mut self, item: T) -> usize {
+        let index = self.collection.add(item);
+        // Just added
+        let item = self.collection.get(index).unwrap();
+        self.hash = self.hash.wrapping_add(pair_hash(index, item));
+        index
+    }
+
+    pub fn take(&mut self, index: usize) -> Option<T> {
+        let item = self.collection.try_take(index)?;
+        self.hash = self.hash.wrapping_sub(pair_hash(index, &item));
+        Some(item)
+    }
+
+    /// Same as `Collection::insert_at`.
+    pub fn insert_at(&mut self, index: usize, item: T) -> Option<T> {
+        self.hash = self.hash.wrapping_add(pair_hash(index, &item));
+        let old = self.collection.insert_at(index, item);
+        if let Some(old) = &old {
+            self.hash = self.hash.wrapping_sub(pair_hash(index, old));
+        }
+        old
+    }
+
+    /// Changes the value at `index` in place and rehashes it. Returns `None`
+    /// without calling `f` if the slot is vacant.
+    pub fn update<R>(&mut self, index: usize, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let item = self.collection.get_mut(index)?;
+        let before = pair_hash(index, item);
+        let result = f(item);
+        let after = pair_hash(index, item);
+        self.hash = self.hash.wrapping_sub(before).wrapping_add(after);
+        Some(result)
+    }
+
+    /// Same as `Collection::content_hash`, in O(1).
+    pub fn content_hash(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn into_inner(self) -> Collection<T, Tag> {
+        self.collection
+    }
+}
+
+impl<T: Hash, Tag> From<Collection<T, Tag>> for HashedCollection<T, Tag> {
+    /// Turns off auto compaction, the hash can't follow values it moves.
+    fn from(mut collection: Collection<T, Tag>) -> Self {
+        collection.auto_compact = None;
+        let hash = collection.content_hash();
+        Self { collection, hash }
+    }
+}
+
+impl<T: Hash> Default for HashedCollection<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// No `DerefMut`, changes through it would go unhashed
+impl<T, Tag> Deref for HashedCollection<T, Tag> {
+    type Target = Collection<T, Tag>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.collection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replicas_with_the_same_pairs_hash_the_same() {
+        let mut a = Collection::new();
+        a.add("x");
+        a.add("y");
+        a.take(0);
+
+        let mut b = Collection::new();
+        b.insert_at(1, "y");
+        assert_eq!(a.content_hash(), b.content_hash());
+
+        // Same values, different indices
+        let mut c = Collection::new();
+        c.add("y");
+        assert_ne!(a.content_hash(), c.content_hash());
+
+        // Pinned so a change to the algorithm doesn't go unnoticed
+        let mut d = Collection::new();
+        d.add(1u32);
+        assert_eq!(d.content_hash(), 0xb46d_04d1_c6da_a804);
+    }
+
+    #[test]
+    fn incremental_hash_matches_a_full_rehash() {
+        let mut coll = HashedCollection::new();
+        coll.add(1);
+        let two = coll.add(2);
+        coll.insert_at(4, 5);
+        coll.take(two);
+        coll.update(4, |value| *value *= 10);
+        coll.add(3);
+        assert_eq!(coll.content_hash(), coll.collection.content_hash());
+
+        coll.insert_at(0, 7);
+        assert_eq!(
+            coll.content_hash(),
+            HashedCollection::from(coll.into_inner()).hash
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Conversions to and from `slab` and `slotmap`, for code that is moving
+//! over one part at a time.
+//!
+//! A `Slab` is addressed by plain indices too, so converting either way
+//! keeps every index. `SlotMap` keys can't be made up from an index, so
+//! those conversions hand back a table from one side's keys to the other's.
+use std::mem;
+
+use crate::Collection;
+
+impl<T, Tag> Collection<T, Tag> {
+    // Moves every value out along with its index, leaving the collection
+    // empty
+    fn drain_enumerated(&mut self) -> impl Iterator<Item = (usize, T)> {
+        let inner = mem::take(&mut self.inner);
+        self.clear();
+        inner
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, slot)| Some((index, slot?)))
+    }
+}
+
+#[cfg(feature = "slab")]
+impl<T, Tag> From<slab::Slab<T>> for Collection<T, Tag> {
+    /// Every value keeps its slab key as its index.
+    fn from(slab: slab::Slab<T>) -> Self {
+        let mut coll = Collection::new_tagged();
+        for (index, item) in slab {
+            coll.insert_at(index, item);
+        }
+        coll
+    }
+}
+
+#[cfg(feature = "slab")]
+impl<T, Tag> From<Collection<T, Tag>> for slab::Slab<T> {
+    /// Every value keeps its index as its slab key.
+    fn from(mut coll: Collection<T, Tag>) -> Self {
+        coll.drain_enumerated().collect()
+    }
+}
+
+#[cfg(feature = "slotmap")]
+impl<T, Tag> Collection<T, Tag> {
+    /// Moves every value into a new collection, returning it along with the
+    /// index every `SlotMap` key ended up at.
+    pub fn from_slotmap<K: slotmap::Key>(
+        map: slotmap::SlotMap<K, T>,
+    ) -> (Self, slotmap::SecondaryMap<K, usize>) {
+        let mut coll = Collection::new_tagged();
+        let mut remap = slotmap::SecondaryMap::with_capacity(map.len());
+        for (key, item) in map {
+            remap.insert(key, coll.add(item));
+        }
+        (coll, remap)
+    }
+
+    /// Moves every value into a new `SlotMap`, returning it along with the
+    /// key that slot `i` ended up under at position `i`, or `None` for
+    /// vacant slots.
+    pub fn into_slotmap<K: slotmap::Key>(mut self) -> (slotmap::SlotMap<K, T>, Vec<Option<K>>) {
+        let mut map = slotmap::SlotMap::with_capacity_and_key(self.occupied);
+        let mut remap = Vec::new();
+        remap.resize_with(self.inner.len(), || None);
+        for (index, item) in self.drain_enumerated() {
+            remap[index] = Some(map.insert(item));
+        }
+        (map, remap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "slab")]
+    #[test]
+    fn slab_indices_survive_both_ways() {
+        let mut slab = slab::Slab::new();
+        let a = slab.insert('a');
+        let b = slab.insert('b');
+        slab.insert('c');
+        slab.remove(b);
+
+        let mut coll: Collection<char> = slab.into();
+        assert_eq!(coll.get(a), Some(&'a'));
+        assert_eq!(coll.get(b), None);
+        assert_eq!(coll.add('d'), b);
+
+        coll.take(a);
+        let slab = slab::Slab::from(coll);
+        assert_eq!(slab.get(a), None);
+        assert_eq!((slab[1], slab[2]), ('d', 'c'));
+    }
+
+    #[cfg(feature = "slotmap")]
+    #[test]
+    fn slotmap_keys_are_remapped() {
+        let mut map = slotmap::SlotMap::new();
+        let first = map.insert("first");
+        let gone = map.insert("gone");
+        let last = map.insert("last");
+        map.remove(gone);
+
+        let (mut coll, remap): (Collection<_>, _) = Collection::from_slotmap(map);
+        assert_eq!(coll.get(remap[first]), Some(&"first"));
+        assert_eq!(coll.get(remap[last]), Some(&"last"));
+        assert!(!remap.contains_key(gone));
+
+        coll.take(remap[first]);
+        let (map, keys) = coll.into_slotmap::<slotmap::DefaultKey>();
+        assert_eq!(keys[remap[first]], None);
+        assert_eq!(map[keys[remap[last]].unwrap()], "last");
+        assert_eq!(map.len(), 1);
+    }
+}
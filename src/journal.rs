@@ -0,0 +1,221 @@
+//! Operation journal for after-the-fact debugging.
+//!
+//! `Journaled` wraps a collection and records every `add` and `take` together
+//! with its result. The journal can rebuild the collection as it was after any
+//! operation, and `bisect` finds the operation where things went wrong.
+use std::ops::Deref;
+
+use crate::Collection;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op<T> {
+    Add { value: T, index: usize },
+    Take { index: usize, value: Option<T> },
+}
+
+/// What `Journal::bisect` found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bisected {
+    /// The invariant holds at the end of the journal.
+    Holds,
+    /// The invariant holds before the operation at this position in the
+    /// journal and fails after it.
+    BrokenBy(usize),
+    /// The invariant already fails on an empty collection, so no operation
+    /// is to blame.
+    BrokenFromStart,
+}
+
+#[derive(Debug, Clone)]
+pub struct Journal<T> {
+    ops: Vec<Op<T>>,
+}
+
+impl<T> Journal<T> {
+    pub fn new() -> Self {
+        Self { ops: Vec::new() }
+    }
+
+    pub fn ops(&self) -> &[Op<T>] {
+        &self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl<T> Default for Journal<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Journal<T> {
+    /// Rebuilds the collection as it was after every recorded operation.
+    pub fn replay(&self) -> Collection<T> {
+        self.replay_to(self.ops.len())
+    }
+
+    /// Rebuilds the collection as it was after the first `n` operations.
+    pub fn replay_to(&self, n: usize) -> Collection<T> {
+        let mut collection = Collection::new();
+
+        for op in &self.ops[..n] {
+            match op {
+                Op::Add { value, index } => {
+                    let replayed = collection.add(value.clone());
+                    debug_assert_eq!(replayed, *index, "Replay diverged from the journal");
+                }
+                Op::Take { index, .. } => {
+                    collection.try_take(*index);
+                }
+            }
+        }
+
+        collection
+    }
+
+    /// Finds the first operation after which `invariant` no longer holds.
+    ///
+    /// Like `git bisect` this assumes that once the invariant is broken it
+    /// stays broken.
+    pub fn bisect(&self, mut invariant: impl FnMut(&Collection<T>) -> bool) -> Bisected {
+        if invariant(&self.replay()) {
+            return Bisected::Holds;
+        }
+        if self.ops.is_empty() || !invariant(&self.replay_to(0)) {
+            return Bisected::BrokenFromStart;
+        }
+
+        // The invariant holds after `good` operations and fails after `bad`
+        let (mut good, mut bad) = (0, self.ops.len());
+        while bad - good > 1 {
+            let mid = good + (bad - good) / 2;
+            if invariant(&self.replay_to(mid)) {
+                good = mid;
+            } else {
+                bad = mid;
+            }
+        }
+
+        Bisected::BrokenBy(bad - 1)
+    }
+}
+
+/// A collection that records every `add` and `take` in a `Journal`.
+///
+/// Read-only access goes through `Deref`, mutations have to go through the
+/// wrapper so nothing escapes the journal.
+pub struct Journaled<T> {
+    collection: Collection<T>,
+    journal: Journal<T>,
+}
+
+impl<T: Clone> Journaled<T> {
+    pub fn new() -> Self {
+        Self {
+            collection: Collection::new(),
+            journal: Journal::new(),
+        }
+    }
+
+    pub fn add(&mut self, item: T) -> usize {
+        let index = self.collection.add(item.clone());
+        self.journal.ops.push(Op::Add { value: item, index });
+        index
+    }
+
+    #[track_caller]
+    pub fn take(&mut self, index: usize) -> T {
+        match self.try_take(index) {
+            Some(item) => item,
+            None => self.collection.vacant_panic(index, None),
+        }
+    }
+
+    pub fn try_take(&mut self, index: usize) -> Option<T> {
+        let value = self.collection.try_take(index);
+        self.journal.ops.push(Op::Take {
+            index,
+            value: value.clone(),
+        });
+        value
+    }
+
+    pub fn journal(&self) -> &Journal<T> {
+        &self.journal
+    }
+
+    pub fn into_parts(self) -> (Collection<T>, Journal<T>) {
+        (self.collection, self.journal)
+    }
+}
+
+impl<T: Clone> Default for Journaled<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Deref for Journaled<T> {
+    type Target = Collection<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.collection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_reconstructs_collection() {
+        let mut coll = Journaled::new();
+        coll.add('a');
+        coll.add('b');
+        coll.take(0);
+        coll.add('c');
+        assert_eq!(coll.try_take(5), None);
+
+        let replayed = coll.journal().replay();
+        assert_eq!(replayed.inner, coll.inner);
+        assert_eq!(*coll.journal().replay_to(2).inner, [Some('a'), Some('b')]);
+    }
+
+    #[test]
+    fn bisect_finds_first_breaking_operation() {
+        let mut coll = Journaled::new();
+        for i in 0..20 {
+            coll.add(i);
+            if i % 2 == 0 {
+                coll.take(i as usize / 2);
+            }
+        }
+
+        let at_most_five = |c: &Collection<i32>| c.len() <= 5;
+        let first_bad = match coll.journal().bisect(at_most_five) {
+            Bisected::BrokenBy(op) => op,
+            other => panic!("expected an operation to blame, got {:?}", other),
+        };
+
+        assert!(at_most_five(&coll.journal().replay_to(first_bad)));
+        assert!(!at_most_five(&coll.journal().replay_to(first_bad + 1)));
+        assert_eq!(coll.journal().bisect(|_| true), Bisected::Holds);
+    }
+
+    #[test]
+    fn bisect_blames_no_operation_when_broken_from_the_start() {
+        let never = |_: &Collection<u8>| false;
+        assert_eq!(Journal::new().bisect(never), Bisected::BrokenFromStart);
+
+        let mut coll = Journaled::new();
+        coll.add(1);
+        assert_eq!(coll.journal().bisect(never), Bisected::BrokenFromStart);
+    }
+}
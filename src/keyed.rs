@@ -0,0 +1,142 @@
+//! A collection addressed by slot index and by a unique key of the caller's.
+//!
+//! `KeyedCollection` stores every value next to its key and keeps a map from
+//! key to index in step with the slots, so a key always finds its own value
+//! and never a later one that reused the slot.
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::Collection;
+
+pub struct KeyedCollection<K, T> {
+    collection: Collection<(K, T)>,
+    indices: HashMap<K, usize>,
+}
+
+impl<K: Hash + Eq + Clone, T> KeyedCollection<K, T> {
+    pub fn new() -> Self {
+        Self {
+            collection: Collection::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    /// Stores `item` under `key`. If the key is taken the value is replaced
+    /// in place and the old one returned along with the index.
+    pub fn insert(&mut self, key: K, item: T) -> (usize, Option<T>) {
+        if let Some(&index) = self.indices.get(&key) {
+            let slot = self.collection.get_mut(index).unwrap();
+            return (index, Some(std::mem::replace(&mut slot.1, item)));
+        }
+
+        let index = self.collection.add((key.clone(), item));
+        self.indices.insert(key, index);
+        (index, None)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.collection.get(index).map(|(_, item)| item)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.collection.get_mut(index).map(|(_, item)| item)
+    }
+
+    pub fn get_by_key<Q>(&self, key: &Q) -> Option<&T>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(*self.indices.get(key)?)
+    }
+
+    pub fn get_by_key_mut<Q>(&mut self, key: &Q) -> Option<&mut T>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = *self.indices.get(key)?;
+        self.get_mut(index)
+    }
+
+    pub fn index_of<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.indices.get(key).copied()
+    }
+
+    pub fn key_of(&self, index: usize) -> Option<&K> {
+        self.collection.get(index).map(|(key, _)| key)
+    }
+
+    /// Takes the value at `index` along with its key.
+    pub fn take(&mut self, index: usize) -> Option<(K, T)> {
+        let (key, item) = self.collection.try_take(index)?;
+        self.indices.remove(&key);
+        Some((key, item))
+    }
+
+    pub fn take_by_key<Q>(&mut self, key: &Q) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.indices.remove(key)?;
+        self.collection.try_take(index).map(|(_, item)| item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Iterates `(index, key, value)` in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &K, &T)> {
+        self.collection
+            .enumerate()
+            .map(|(index, (key, item))| (index, key, item))
+    }
+}
+
+impl<K: Hash + Eq + Clone, T> Default for KeyedCollection<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_and_indices_stay_in_step() {
+        let mut sessions = KeyedCollection::new();
+        let (ana, _) = sessions.insert("ana".to_string(), 1);
+        let (bo, _) = sessions.insert("bo".to_string(), 2);
+
+        assert_eq!(sessions.get_by_key("ana"), Some(&1));
+        assert_eq!(sessions.insert("ana".to_string(), 3), (ana, Some(1)));
+        assert_eq!(sessions.key_of(bo).map(String::as_str), Some("bo"));
+
+        assert_eq!(sessions.take_by_key("ana"), Some(3));
+        assert_eq!(sessions.get(ana), None);
+        assert_eq!(sessions.take_by_key("ana"), None);
+
+        // The slot gets reused, but the old key doesn't find it
+        let (cy, _) = sessions.insert("cy".to_string(), 4);
+        assert_eq!(cy, ana);
+        assert_eq!(sessions.index_of("ana"), None);
+        assert_eq!(sessions.take(bo), Some(("bo".to_string(), 2)));
+        assert_eq!(
+            sessions.iter().collect::<Vec<_>>(),
+            vec![(cy, &"cy".to_string(), &4)]
+        );
+        assert_eq!(sessions.len(), 1);
+    }
+}
@@ -0,0 +1,488 @@
+//! Generational keys.
+//!
+//! A plain index stays valid after its value has been taken, and silently
+//! refers to whatever is added to the slot next. A `Key` also carries the
+//! slot's generation, which is bumped every time the slot is vacated, so a
+//! stale key simply stops resolving.
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::panic::AssertUnwindSafe;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+/// A key issued by a `Collection<T, Tag>`. The tag only exists at compile
+/// time, see the `Collection` docs.
+pub struct Key<Tag = ()> {
+    index: u32,
+    generation: u32,
+    tag: PhantomData<fn() -> Tag>,
+}
+
+impl<Tag> Key<Tag> {
+    /// A key that never resolves, for initializing key fields and statics
+    /// before there is a value to point at. Its index is one no collection
+    /// can issue a key for.
+    pub const DANGLING: Self = Self::from_raw_parts(u32::MAX, u32::MAX);
+
+    pub const fn from_raw_parts(index: u32, generation: u32) -> Self {
+        Self {
+            index,
+            generation,
+            tag: PhantomData,
+        }
+    }
+
+    pub const fn index(&self) -> usize {
+        self.index as usize
+    }
+
+    pub const fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Unpacks a key stored with `to_raw`.
+    pub const fn from_raw(raw: u64) -> Self {
+        Self::from_raw_parts(raw as u32, (raw >> 32) as u32)
+    }
+
+    /// Packs the key into a `u64` for storage outside the process, with the
+    /// generation in the high 32 bits and the index in the low 32 bits.
+    pub const fn to_raw(&self) -> u64 {
+        (self.generation as u64) << 32 | self.index as u64
+    }
+}
+
+// The traits are implemented by hand since deriving them would require the
+// tag to implement them as well
+
+impl<Tag> Clone for Key<Tag> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<Tag> Copy for Key<Tag> {}
+
+impl<Tag> PartialEq for Key<Tag> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.index, self.generation) == (other.index, other.generation)
+    }
+}
+
+impl<Tag> Eq for Key<Tag> {}
+
+impl<Tag> Hash for Key<Tag> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<Tag> PartialOrd for Key<Tag> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<Tag> Ord for Key<Tag> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.index, self.generation).cmp(&(other.index, other.generation))
+    }
+}
+
+impl<Tag> fmt::Debug for Key<Tag> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Key")
+            .field("index", &self.index)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
+
+/// Formats as `<index>v<generation>`, e.g. `3v7`.
+impl<Tag> fmt::Display for Key<Tag> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+/// What happens to a slot whose generation can't be bumped any further.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GenerationPolicy {
+    /// Start over at 0. A key that is a multiple of 2^32 generations stale
+    /// resolves again. The default.
+    #[default]
+    Wrap,
+    /// Stop at `u32::MAX` and retire the slot: once vacated, `add` never
+    /// hands it out again. Costs one slot per 2^32 reuses.
+    Retire,
+}
+
+type OnExhausted = Box<dyn FnMut(usize) + Send + Sync>;
+
+// Generation of every slot, grown lazily since a slot that was never vacated
+// is at generation 0
+#[derive(Default)]
+pub(crate) struct Generations {
+    generations: Vec<u32>,
+    policy: GenerationPolicy,
+    // Same reasoning as `AutoCompact::remap`, the generations are already
+    // updated when this runs
+    on_exhausted: Option<AssertUnwindSafe<OnExhausted>>,
+}
+
+impl Generations {
+    pub(crate) const fn new() -> Self {
+        Self {
+            generations: Vec::new(),
+            policy: GenerationPolicy::Wrap,
+            on_exhausted: None,
+        }
+    }
+
+    pub(crate) fn get(&self, index: usize) -> u32 {
+        self.generations.get(index).copied().unwrap_or(0)
+    }
+
+    // Call whenever the slot at `index` stops holding the value it held
+    pub(crate) fn bump(&mut self, index: usize) {
+        if self.generations.len() <= index {
+            self.generations.resize(index + 1, 0);
+        }
+        let generation = &mut self.generations[index];
+        let exhausted = match (self.policy, generation.checked_add(1)) {
+            (GenerationPolicy::Retire, Some(u32::MAX)) => {
+                *generation = u32::MAX;
+                true
+            }
+            (_, Some(next)) => {
+                *generation = next;
+                false
+            }
+            (GenerationPolicy::Wrap, None) => {
+                *generation = 0;
+                true
+            }
+            // Already retired
+            (GenerationPolicy::Retire, None) => false,
+        };
+
+        if exhausted {
+            if let Some(on_exhausted) = &mut self.on_exhausted {
+                on_exhausted(index);
+            }
+        }
+    }
+
+    // Whether the next `bump` of the slot at `index` retires it
+    pub(crate) fn retires_on_bump(&self, index: usize) -> bool {
+        self.policy == GenerationPolicy::Retire && self.get(index) >= u32::MAX - 1
+    }
+
+    // Retired slots must stay vacant, so they never go on the free list
+    pub(crate) fn is_retired(&self, index: usize) -> bool {
+        self.policy == GenerationPolicy::Retire && self.get(index) == u32::MAX
+    }
+}
+
+impl fmt::Debug for Generations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Generations")
+            .field("generations", &self.generations)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Sets what happens once a slot has been reused 2^32 times, see
+    /// `GenerationPolicy`. Only `add` and its variants respect retired slots,
+    /// `insert_at`, `apply` and `partition_in_place` may still fill them.
+    pub fn set_generation_policy(&mut self, policy: GenerationPolicy) {
+        self.generations.policy = policy;
+    }
+
+    pub fn generation_policy(&self) -> GenerationPolicy {
+        self.generations.policy
+    }
+
+    /// Calls `f(index)` whenever a slot's generation wraps, or with
+    /// `GenerationPolicy::Retire`, whenever a slot is retired.
+    pub fn on_generation_exhausted(&mut self, f: impl FnMut(usize) + Send + Sync + 'static) {
+        self.generations.on_exhausted = Some(AssertUnwindSafe(Box::new(f)));
+    }
+
+    /// Whether the slot at `index` has been retired and won't be reused.
+    pub fn is_retired(&self, index: usize) -> bool {
+        self.generations.is_retired(index) && self.get(index).is_none()
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Like `add`, but returns a `Key` that stops resolving once the value is
+    /// taken.
+    pub fn add_key(&mut self, item: T) -> Key<Tag> {
+        let index = self.add(item);
+        self.key_at(index)
+    }
+
+    pub fn get_key(&self, key: Key<Tag>) -> Option<&T> {
+        if !self.is_current(key) {
+            return None;
+        }
+        self.get(key.index())
+    }
+
+    pub fn get_key_mut(&mut self, key: Key<Tag>) -> Option<&mut T> {
+        if !self.is_current(key) {
+            return None;
+        }
+        self.get_mut(key.index())
+    }
+
+    pub fn take_key(&mut self, key: Key<Tag>) -> Option<T> {
+        if !self.is_current(key) {
+            return None;
+        }
+        self.try_take(key.index())
+    }
+
+    pub fn contains_key(&self, key: Key<Tag>) -> bool {
+        self.get_key(key).is_some()
+    }
+
+    /// The key for the value currently at `index`. Together with `index_of`
+    /// this lets code move from indices to keys piece by piece, the
+    /// index-based methods keep working on the same slots.
+    pub fn key_for_index(&self, index: usize) -> Option<Key<Tag>> {
+        self.get(index)?;
+        key_index(index)?;
+        Some(self.key_at(index))
+    }
+
+    /// Makes every outstanding key stale at once, keeping the values where
+    /// they are, e.g. so handles from before a reload fail instead of
+    /// aliasing whatever ends up in their slot. Indices keep working, and
+    /// `key_for_index` issues the new keys, and `undo_last_add` still takes
+    /// back the adds from before. Takes O(slots).
+    pub fn invalidate_all_keys(&mut self) {
+        let undoable = self.undoable_adds();
+        for (index, slot) in self.inner.iter().enumerate() {
+            if slot.is_some() {
+                self.generations.bump(index);
+            }
+        }
+        // Keys to vacant slots are already stale. Occupied slots bumped to
+        // retirement only retire once vacated, so the free list is unchanged
+        self.refresh_adds(&undoable);
+        self.debug_assert_consistent();
+    }
+
+    /// The index `key` refers to, or `None` if it's stale.
+    pub fn index_of(&self, key: Key<Tag>) -> Option<usize> {
+        self.get_key(key)?;
+        Some(key.index())
+    }
+
+    fn is_current(&self, key: Key<Tag>) -> bool {
+        self.generations.get(key.index()) == key.generation
+    }
+
+    fn key_at(&self, index: usize) -> Key<Tag> {
+        Key {
+            index: key_index(index).expect("Keys only address the first 2^32 - 1 slots"),
+            generation: self.generations.get(index),
+            tag: PhantomData,
+        }
+    }
+}
+
+// The last `u32` index is reserved for `Key::DANGLING`
+fn key_index(index: usize) -> Option<u32> {
+    u32::try_from(index).ok().filter(|&index| index != u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeSet, HashMap};
+
+    #[test]
+    fn stale_keys_stop_resolving() {
+        let mut coll = Collection::new();
+        let a = coll.add_key('a');
+        let b = coll.add_key('b');
+
+        assert_eq!(coll.take_key(a), Some('a'));
+        assert_eq!(coll.take_key(a), None);
+
+        let c = coll.add_key('c');
+        assert_eq!(c.index(), a.index());
+        assert_ne!(c, a);
+        assert_eq!(coll.get_key(a), None);
+        assert_eq!(coll.get_key(c), Some(&'c'));
+        assert!(coll.contains_key(b));
+
+        coll.clear();
+        coll.add('d');
+        assert_eq!(coll.get_key(c), None);
+    }
+
+    #[test]
+    fn invalidating_keys_keeps_the_values() {
+        let mut coll = Collection::new();
+        let a = coll.add_key('a');
+        let b = coll.add_key('b');
+        coll.take_key(a);
+
+        coll.invalidate_all_keys();
+        assert_eq!(coll.get_key(b), None);
+        assert_eq!(coll.get(b.index()), Some(&'b'));
+        let new_b = coll.key_for_index(b.index()).unwrap();
+        assert_eq!(coll.get_key(new_b), Some(&'b'));
+
+        let c = coll.add_key('c');
+        assert_eq!(c.index(), a.index());
+        assert_eq!(coll.get_key(a), None);
+    }
+
+    #[test]
+    fn invalidating_keys_keeps_the_undo_history() {
+        let mut coll = Collection::new();
+        coll.add("a");
+        let b = coll.add("b");
+        coll.take(b);
+
+        coll.invalidate_all_keys();
+        assert_eq!(coll.last_added_index(), Some(0));
+        assert_eq!(coll.undo_last_add(), Some((0, "a")));
+        assert_eq!(coll.undo_last_add(), None);
+    }
+
+    #[test]
+    fn keys_work_as_map_keys_and_log_tokens() {
+        let mut coll = Collection::new();
+        let first = coll.add_key(1);
+        for _ in 0..3 {
+            coll.take_key(first);
+            coll.add(2);
+            coll.take(0);
+        }
+        coll.add(3);
+        let key = coll.add_key(4);
+
+        assert_eq!(key.to_string(), "1v0");
+        assert_eq!(Key::from_raw(key.to_raw()), key);
+
+        let stale: Key = Key::from_raw(first.to_raw());
+        assert_eq!(stale.to_string(), "0v0");
+
+        let mut names = HashMap::new();
+        names.insert(key, "four");
+        assert_eq!(names[&key], "four");
+
+        let ordered: BTreeSet<_> = vec![key, first].into_iter().collect();
+        assert_eq!(ordered.into_iter().next(), Some(first));
+    }
+
+    #[test]
+    fn indices_and_keys_convert_both_ways() {
+        let mut coll = Collection::new();
+        let index = coll.add("legacy");
+        let key = coll.key_for_index(index).unwrap();
+        assert_eq!(coll.index_of(key), Some(index));
+        assert_eq!(coll.key_for_index(1), None);
+
+        // Old code taking by index leaves new code with a stale key
+        coll.take(index);
+        assert_eq!(coll.key_for_index(index), None);
+        assert_eq!(coll.index_of(key), None);
+
+        let index = coll.add("replacement");
+        assert_ne!(coll.key_for_index(index), Some(key));
+    }
+
+    #[test]
+    fn exhausted_slots_wrap_or_retire() {
+        use std::sync::{Arc, Mutex};
+
+        let exhausted = Arc::new(Mutex::new(Vec::new()));
+        let mut coll = Collection::new();
+        let log = Arc::clone(&exhausted);
+        coll.on_generation_exhausted(move |index| log.lock().unwrap().push(index));
+
+        coll.add('a');
+        coll.generations.generations = vec![u32::MAX];
+        let old = coll.key_for_index(0).unwrap();
+        coll.take(0);
+        assert_eq!(coll.generations.get(0), 0);
+        assert_eq!(coll.add('b'), 0);
+
+        coll.set_generation_policy(GenerationPolicy::Retire);
+        coll.add('c');
+        coll.generations.generations[0] = u32::MAX - 1;
+        coll.take(0);
+        assert!(coll.is_retired(0));
+        assert_eq!(coll.add('d'), 2);
+        assert_eq!(coll.get_key(old), None);
+        assert_eq!(*exhausted.lock().unwrap(), vec![0, 0]);
+
+        // Clearing doesn't bring the slot back either
+        coll.clear();
+        assert_eq!(coll.add('e'), 1);
+        assert!(coll.is_retired(0));
+        assert_eq!(coll.vacant_len(), 0);
+    }
+
+    #[test]
+    fn bulk_adds_append_past_retired_slots() {
+        let mut coll = Collection::new();
+        coll.set_generation_policy(GenerationPolicy::Retire);
+        coll.extend_from_slice(b"ab");
+        coll.generations.generations = vec![0, u32::MAX - 1];
+        coll.take(1);
+        coll.compact(|_, _| ());
+        assert_eq!(coll.max_index(), Some(0));
+
+        coll.extend_from_slice(b"cd");
+        assert_eq!(*coll.inner, [Some(b'a'), None, Some(b'c'), Some(b'd')]);
+        assert!(coll.is_retired(1));
+    }
+
+    struct Textures;
+    struct Meshes;
+
+    #[test]
+    fn tagged_collections_issue_tagged_keys() {
+        let mut textures: Collection<&str, Textures> = Collection::new_tagged();
+        let mut meshes: Collection<&str, Meshes> = Collection::default();
+
+        let texture = textures.add_key("brick.png");
+        let mesh = meshes.add_key("cube.obj");
+        assert_eq!(textures.get_key(texture), Some(&"brick.png"));
+        assert_eq!(meshes.get_key(mesh), Some(&"cube.obj"));
+
+        // `meshes.get_key(texture)` doesn't compile
+        let raw: Key<Meshes> = Key::from_raw(texture.to_raw());
+        assert_eq!(meshes.get_key(raw), Some(&"cube.obj"));
+        assert_eq!(std::mem::size_of::<Key<Textures>>(), 8);
+    }
+
+    #[test]
+    fn statics_and_sentinels_need_no_lazy_init() {
+        static REGISTRY: std::sync::Mutex<Collection<&str>> =
+            std::sync::Mutex::new(Collection::new());
+        const UNSET: Key = Key::DANGLING;
+
+        let mut registry = REGISTRY.lock().unwrap();
+        let key = registry.add_key("logger");
+        assert_eq!(key, Key::from_raw_parts(0, 0));
+        assert_eq!(registry.get_key(UNSET), None);
+        assert_ne!(key, UNSET);
+    }
+}
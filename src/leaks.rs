@@ -0,0 +1,162 @@
+//! Finding slots that are filled and never freed.
+//!
+//! With the `leak-detect` feature every fill is recorded along with a
+//! backtrace (captured if `RUST_BACKTRACE` is set), and every vacate clears
+//! it. Whatever is still recorded when the collection is dropped gets
+//! reported on stderr, or panics if `panic_on_leaks` was set. Without the
+//! feature `LeakTracker` is empty and recording compiles to nothing.
+#[cfg(feature = "leak-detect")]
+use std::backtrace::{Backtrace, BacktraceStatus};
+#[cfg(feature = "leak-detect")]
+use std::collections::BTreeMap;
+#[cfg(feature = "leak-detect")]
+use std::thread;
+
+#[cfg(feature = "leak-detect")]
+use allocator_api2::alloc::Allocator;
+
+#[cfg(feature = "leak-detect")]
+use crate::Collection;
+
+#[cfg(feature = "leak-detect")]
+#[derive(Debug, Default)]
+pub(crate) struct LeakTracker {
+    // Where every occupied slot was filled
+    outstanding: BTreeMap<usize, Backtrace>,
+    panic_on_leaks: bool,
+}
+
+#[cfg(not(feature = "leak-detect"))]
+#[derive(Debug, Default)]
+pub(crate) struct LeakTracker;
+
+#[cfg(feature = "leak-detect")]
+impl LeakTracker {
+    pub(crate) const fn new() -> Self {
+        Self {
+            outstanding: BTreeMap::new(),
+            panic_on_leaks: false,
+        }
+    }
+
+    pub(crate) fn filled(&mut self, index: usize) {
+        self.outstanding.insert(index, Backtrace::capture());
+    }
+
+    pub(crate) fn vacated(&mut self, index: usize) {
+        self.outstanding.remove(&index);
+    }
+
+    pub(crate) fn moved(&mut self, old: usize, new: usize) {
+        if let Some(backtrace) = self.outstanding.remove(&old) {
+            self.outstanding.insert(new, backtrace);
+        }
+    }
+
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        let (at_a, at_b) = (self.outstanding.remove(&a), self.outstanding.remove(&b));
+        if let Some(backtrace) = at_a {
+            self.outstanding.insert(b, backtrace);
+        }
+        if let Some(backtrace) = at_b {
+            self.outstanding.insert(a, backtrace);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.outstanding.clear();
+    }
+}
+
+#[cfg(feature = "leak-detect")]
+impl Drop for LeakTracker {
+    fn drop(&mut self) {
+        if self.outstanding.is_empty() {
+            return;
+        }
+
+        let mut report = format!(
+            "Collection dropped with {} slots never freed: {:?}",
+            self.outstanding.len(),
+            self.outstanding.keys().collect::<Vec<_>>()
+        );
+        for (index, backtrace) in &self.outstanding {
+            if backtrace.status() == BacktraceStatus::Captured {
+                report.push_str(&format!("\n\n[{}] filled at:\n{}", index, backtrace));
+            }
+        }
+
+        // Panicking while unwinding would abort
+        if self.panic_on_leaks && !thread::panicking() {
+            panic!("{}", report);
+        }
+        eprintln!("{}", report);
+    }
+}
+
+#[cfg(not(feature = "leak-detect"))]
+impl LeakTracker {
+    pub(crate) const fn new() -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    pub(crate) fn filled(&mut self, _index: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn vacated(&mut self, _index: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn moved(&mut self, _old: usize, _new: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn swap(&mut self, _a: usize, _b: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn clear(&mut self) {}
+}
+
+#[cfg(feature = "leak-detect")]
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Indices that were filled and not freed since, in order.
+    pub fn outstanding_handles(&self) -> Vec<usize> {
+        self.leaks.outstanding.keys().copied().collect()
+    }
+
+    /// Panic instead of printing a report when dropped with outstanding
+    /// slots, e.g. in tests.
+    pub fn panic_on_leaks(&mut self, panic: bool) {
+        self.leaks.panic_on_leaks = panic;
+    }
+}
+
+#[cfg(all(test, feature = "leak-detect"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_handles_until_freed() {
+        let mut coll = Collection::new();
+        for i in 0..4 {
+            coll.add(i);
+        }
+        coll.take(0);
+        coll.take(2);
+        assert_eq!(coll.outstanding_handles(), vec![1, 3]);
+
+        coll.compact(|_, _| ());
+        assert_eq!(coll.outstanding_handles(), vec![0, 1]);
+
+        coll.clear();
+        coll.panic_on_leaks(true);
+        drop(coll);
+    }
+
+    #[test]
+    #[should_panic(expected = "1 slots never freed: [0]")]
+    fn panics_on_leaks_when_asked_to() {
+        let mut coll = Collection::new();
+        coll.panic_on_leaks(true);
+        coll.add("leaked");
+    }
+}
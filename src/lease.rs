@@ -0,0 +1,209 @@
+//! Moving a value out of its slot with the option of putting it back.
+//!
+//! `lease` takes the value out and marks the slot leased: it reads as vacant,
+//! so iteration and `len` skip it, but `add` won't reuse it and compaction
+//! won't move anything into or out of it. The `SlotLease` owns the value and
+//! doesn't borrow the collection, so it can be moved into an async task while
+//! the collection stays in use. `restore` puts the value back under the same
+//! index, with its keys resolving again, and `SlotLease::into_inner` keeps it
+//! for good. Either way the index never changes hands, unlike a take followed
+//! by an add.
+use std::collections::BTreeSet;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use allocator_api2::alloc::Allocator;
+
+use crate::{wipe, Collection, GroupId};
+
+// Indices of the slots whose value is out on lease
+#[derive(Debug, Default)]
+pub(crate) struct Leases(BTreeSet<usize>);
+
+impl Leases {
+    pub(crate) const fn new() -> Self {
+        Self(BTreeSet::new())
+    }
+
+    pub(crate) fn contains(&self, index: usize) -> bool {
+        self.0.contains(&index)
+    }
+
+    // Leases for slots at `len` and up can't be restored anymore
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.0.split_off(&len);
+    }
+}
+
+/// A value leased out of a `Collection<T, Tag>`. Dropping it without handing
+/// it to `restore` or `into_inner` drops the value and keeps the slot out of
+/// use until the collection is cleared or truncated.
+#[must_use = "the slot stays leased until the lease is restored or taken"]
+pub struct SlotLease<T, Tag = ()> {
+    value: T,
+    index: usize,
+    generation: u32,
+    group: Option<GroupId>,
+    tag: PhantomData<fn() -> Tag>,
+}
+
+impl<T, Tag> SlotLease<T, Tag> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Keeps the value for good, vacating the slot like `take` would.
+    pub fn into_inner<A: Allocator>(self, collection: &mut Collection<T, Tag, A>) -> T {
+        collection.end_lease(&self);
+        self.value
+    }
+}
+
+impl<T, Tag> Deref for SlotLease<T, Tag> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T, Tag> DerefMut for SlotLease<T, Tag> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Leases the value at `index`, see `SlotLease`.
+    ///
+    /// # Panics
+    ///
+    /// If the slot at `index` is vacant or out of range.
+    #[track_caller]
+    pub fn lease(&mut self, index: usize) -> SlotLease<T, Tag> {
+        match self.try_lease(index) {
+            Some(lease) => lease,
+            None => self.vacant_panic(index, None),
+        }
+    }
+
+    pub fn try_lease(&mut self, index: usize) -> Option<SlotLease<T, Tag>> {
+        let slot = self.inner.get_mut(index)?;
+        let value = slot.take()?;
+        wipe::vacated(slot);
+
+        // Like `note_vacated`, except that the generation stays so keys
+        // resolve again after `restore`, and the slot stays off the free list
+        self.leases.0.insert(index);
+        let group = self.groups.unlink(index);
+        self.count_vacated();
+        self.stats.vacated(index);
+        self.leaks.vacated(index);
+        self.occupancy.vacated(index);
+        self.bitmap.vacated(index);
+        self.pending_removals.vacated(index);
+        self.structure_changes += 1;
+        self.debug_assert_consistent();
+
+        Some(SlotLease {
+            value,
+            index,
+            generation: self.generations.get(index),
+            group,
+            tag: PhantomData,
+        })
+    }
+
+    /// Puts a leased value back into its slot, returning the index. Hands
+    /// the value back instead if the lease has ended in the meantime, e.g.
+    /// because the collection was cleared or the slot was filled explicitly.
+    pub fn restore(&mut self, lease: SlotLease<T, Tag>) -> Result<usize, T> {
+        let index = lease.index;
+        if !self.leases.0.remove(&index) {
+            return Err(lease.value);
+        }
+        if self.inner[index].is_some() || self.generations.get(index) != lease.generation {
+            self.release_leased_slot(index);
+            return Err(lease.value);
+        }
+
+        self.inner[index] = Some(lease.value);
+        self.occupied += 1;
+        self.note_filled(index);
+        if let Some(group) = lease.group {
+            self.groups.link(index, group);
+        }
+        self.debug_assert_consistent();
+        Ok(index)
+    }
+
+    // Frees the slot of a lease that isn't coming back
+    fn end_lease(&mut self, lease: &SlotLease<T, Tag>) {
+        let index = lease.index;
+        if !self.leases.0.remove(&index) {
+            return;
+        }
+        if self.generations.get(index) == lease.generation {
+            // Keys to the value go stale, same as after a take
+            self.generations.bump(index);
+        }
+        self.release_leased_slot(index);
+    }
+
+    // A slot that's no longer leased goes back on the free list if vacant
+    fn release_leased_slot(&mut self, index: usize) {
+        if self.inner[index].is_none() && self.is_reusable(index) {
+            self.free.push_back(index);
+        }
+        self.structure_changes += 1;
+        self.debug_assert_consistent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leases_return_or_take_their_value() {
+        let mut jobs = Collection::new();
+        jobs.add(String::from("upload"));
+        let key = jobs.add_key(String::from("retry"));
+        let retry = key.index();
+
+        let mut lease = jobs.lease(retry);
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs.iter().count(), 1);
+        assert_eq!(jobs.get_key(key), None);
+        // The collection is free to use while the value is out
+        let other = jobs.add(String::from("other"));
+        assert_ne!(other, retry);
+
+        lease.push_str(" #2");
+        assert_eq!(jobs.restore(lease), Ok(retry));
+        assert_eq!(jobs.get_key(key).map(String::as_str), Some("retry #2"));
+
+        let done = jobs.lease(retry).into_inner(&mut jobs);
+        assert_eq!(done, "retry #2");
+        assert!(jobs.try_lease(retry).is_none());
+        assert_eq!(jobs.get_key(key), None);
+        assert_eq!(jobs.add(String::from("next")), retry);
+    }
+
+    #[test]
+    fn leased_slots_survive_compaction_but_not_clear() {
+        let mut coll = Collection::new();
+        for i in 0..4 {
+            coll.add(i);
+        }
+        let lease = coll.lease(3);
+        coll.take(0);
+        coll.compact(|_, _| ());
+        assert_eq!(coll.max_index(), Some(3));
+        assert_eq!(coll.restore(lease), Ok(3));
+
+        let lease = coll.lease(3);
+        coll.clear();
+        assert_eq!(coll.restore(lease), Err(3));
+    }
+}
@@ -2,121 +2,796 @@
 //!
 //! SlotVec is a Vec where you can take out and replace values without increasing the
 //! size of the map.
-use std::iter::{IntoIterator, Iterator};
-use std::ops::{Index, IndexMut};
+use std::iter::{Cloned, Copied, IntoIterator, Iterator};
+use std::marker::PhantomData;
+use std::ops::Index;
+#[cfg(not(feature = "strict"))]
+use std::ops::IndexMut;
 
-#[derive(Debug)]
-pub struct Collection {
-    inner: Vec<Option<u8>>,
-    state: CollectionState,
+use bits::OccupancyBitmap;
+use blocks::Blocks;
+use deferred::PendingRemovals;
+use free::FreeList;
+use groups::Groups;
+use keys::Generations;
+use leaks::LeakTracker;
+use lease::Leases;
+use ranges::OccupancyCounts;
+use timestamps::InsertionTimes;
+use tombstone::Tombstones;
+use undo::RecentAdds;
+
+mod added;
+mod batch;
+mod bits;
+mod blocks;
+mod borrowed;
+mod boxed;
+mod bytes;
+mod capacity;
+#[cfg(feature = "channel")]
+pub mod channel;
+mod compact;
+mod concurrent;
+mod consistency;
+mod cursor;
+mod deferred;
+mod dump;
+mod entry;
+mod equality;
+mod expect;
+mod expiry;
+mod extract;
+mod free;
+mod frozen;
+mod groups;
+mod hashing;
+#[cfg(any(feature = "slab", feature = "slotmap"))]
+mod interop;
+#[cfg(feature = "journal")]
+mod journal;
+mod keyed;
+mod keys;
+mod leaks;
+mod lease;
+mod lookup;
+#[cfg(feature = "mmap")]
+mod mapped;
+mod migrate;
+#[cfg(feature = "multi-slot")]
+mod multi;
+#[cfg(feature = "named")]
+mod named;
+mod nested;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod patch;
+mod pool;
+mod priority;
+#[cfg(feature = "rand")]
+mod random;
+mod ranges;
+mod raw;
+mod reindex;
+mod rle;
+mod scoped;
+#[cfg(feature = "serde")]
+mod serialization;
+mod sharded;
+mod small;
+#[cfg(feature = "soa-layout")]
+mod soa;
+mod split;
+mod stats;
+mod storage;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod timestamps;
+mod tombstone;
+mod transform;
+mod undo;
+mod value;
+mod versioned;
+mod waiters;
+mod weighted;
+mod wipe;
+
+#[cfg(feature = "futures")]
+pub use added::StreamAdded;
+pub use allocator_api2::alloc::{Allocator, Global};
+pub use batch::{BatchError, SlotOp, SlotOpResult};
+pub use bits::OccupiedBits;
+pub use blocks::BlockHandle;
+pub use borrowed::{BorrowedSlots, StaticCollection};
+pub use boxed::BoxedCollection;
+pub use compact::AutoCompact;
+pub use concurrent::{Align, ConcurrentCollection, SlotGuard, SlotRef};
+pub use cursor::{Cursor, StructuralChange, TryIterMut};
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use expiry::ExpiringCollection;
+pub use extract::ExtractIf;
+pub use frozen::FrozenCollection;
+pub use groups::{GroupId, GroupIter};
+pub use hashing::HashedCollection;
+#[cfg(feature = "journal")]
+pub use journal::{Bisected, Journal, Journaled, Op};
+pub use keyed::KeyedCollection;
+pub use keys::{GenerationPolicy, Key};
+pub use lease::SlotLease;
+pub use lookup::InvalidIndices;
+#[cfg(feature = "mmap")]
+pub use mapped::{MappedCollection, SyncPolicy};
+#[cfg(feature = "multi-slot")]
+pub use multi::MultiSlot;
+#[cfg(feature = "named")]
+pub use named::NamedCollection;
+pub use patch::{Patch, PatchError, PatchOp, MAX_PATCH_SLOTS};
+pub use pool::{PooledItem, SlotPool};
+pub use priority::PriorityCollection;
+pub use reindex::ReindexError;
+pub use scoped::{OutOfScope, ScopedCollection};
+#[cfg(feature = "serde")]
+pub use serialization::{serde_dense, serde_keys, serde_map, KeyRemapper, MAX_SPARSE_SLOTS};
+pub use sharded::ShardedCollection;
+pub use small::SmallCollection;
+#[cfg(feature = "soa-layout")]
+pub use soa::SoaCollection;
+pub use split::CollectionViewMut;
+#[cfg(feature = "stats")]
+pub use stats::PoolStats;
+pub use storage::SlotStorage;
+pub use tombstone::Tombstone;
+pub use value::SlotValue;
+pub use versioned::{Version, VersionConflict};
+#[cfg(feature = "futures")]
+pub use waiters::WaitForFreeSlot;
+pub use weighted::WeightedCollection;
+#[cfg(feature = "zeroize")]
+pub use wipe::WipeOnDrop;
+
+// The slot storage, a `Vec` that can live in any allocator
+pub(crate) type Slots<T, A = Global> = allocator_api2::vec::Vec<Option<T>, A>;
+
+/// # Drop order
+///
+/// Dropping the collection, or calling `clear`, drops the occupied values in
+/// slot order. If one of those drops panics the remaining values are still
+/// dropped. `take` moves the value out and drops nothing.
+///
+/// All of this follows from the `Vec` the slots live in, so there is
+/// deliberately no `Drop` impl: one would force `T` to strictly outlive the
+/// collection for no gain.
+///
+/// # Tags
+///
+/// `Tag` is a marker type that only exists at compile time. Keys carry the
+/// tag of the collection that issued them, so a `Key<Textures>` can't be used
+/// with a `Collection<Mesh, Meshes>`.
+///
+/// # Allocators
+///
+/// The slots are allocated from `A`, the global allocator unless the
+/// collection was made with `new_in` or `with_capacity_in`. `Allocator` is
+/// the `allocator-api2` polyfill of the unstable std trait, so this works on
+/// stable. Whatever hands the slots over as a plain `Vec`, like `into_raw_parts`, `map` or
+/// the serde support, is only there for the global allocator.
+pub struct Collection<T, Tag = (), A: Allocator = Global> {
+    inner: Slots<T, A>,
+    occupied: usize,
+    // Vacant indices that `add` may reuse
+    free: FreeList,
+    generations: Generations,
+    groups: Groups,
+    auto_compact: Option<AutoCompact>,
+    pending_removals: PendingRemovals,
+    tombstones: Tombstones,
+    inserted: InsertionTimes,
+    stats: stats::PoolStats,
+    on_growth: Option<capacity::OnGrowth>,
+    leaks: LeakTracker,
+    occupancy: OccupancyCounts,
+    bitmap: OccupancyBitmap,
+    blocks: Blocks,
+    leases: Leases,
+    recent_adds: RecentAdds,
+    // Bumped whenever a slot is filled, vacated or moved, see `cursor`
+    structure_changes: u64,
+    // Set by `IndexMut`, which can desync the bookkeeping until `resync`
+    touched_through_index_mut: bool,
+    tag: PhantomData<fn() -> Tag>,
 }
 
-#[derive(Debug)]
-pub enum CollectionState {
-    Empty,
-    Full(u32),
-    NotFull(u32, u32),
+impl<T> Collection<T> {
+    pub const fn new() -> Self {
+        Self::new_tagged()
+    }
 }
 
-impl Collection {
-    pub fn new() -> Self {
+impl<T, Tag> Collection<T, Tag> {
+    /// Same as `new`, for collections with a tag. `new` itself is only
+    /// available for the default tag so `Collection::new()` needs no
+    /// annotations.
+    pub const fn new_tagged() -> Self {
+        Self::new_in(Global)
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// A collection whose slots are allocated from `alloc`, e.g. an arena
+    /// that is freed all at once. The bookkeeping next to the slots still
+    /// lives on the global heap.
+    pub const fn new_in(alloc: A) -> Self {
+        Self::with_slots(Slots::new_in(alloc))
+    }
+
+    // A collection around `inner` with none of the bookkeeping set up, see
+    // `from_slots_in` for one that has it
+    const fn with_slots(inner: Slots<T, A>) -> Self {
         Self {
-            inner: Vec::new(),
-            state: CollectionState::Empty,
+            inner,
+            occupied: 0,
+            free: FreeList::new(),
+            generations: Generations::new(),
+            groups: Groups::new(),
+            auto_compact: None,
+            pending_removals: PendingRemovals::new(),
+            tombstones: Tombstones::new(),
+            inserted: InsertionTimes::new(),
+            stats: stats::PoolStats::new(),
+            on_growth: None,
+            leaks: LeakTracker::new(),
+            occupancy: OccupancyCounts::new(),
+            bitmap: OccupancyBitmap::new(),
+            blocks: Blocks::new(),
+            leases: Leases::new(),
+            recent_adds: RecentAdds::new(),
+            structure_changes: 0,
+            touched_through_index_mut: false,
+            tag: PhantomData,
         }
     }
 
-    pub fn add(&mut self, item: u8) -> usize {
-        match self.state {
-            CollectionState::Empty => {
-                self.state = CollectionState::Full(1);
-                self.inner.push(Some(item));
-                0
-            }
+    /// Like `new_in`, with room for `capacity` slots up front.
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        let mut collection = Self::new_in(alloc);
+        collection.reserve(capacity);
+        collection
+    }
 
-            CollectionState::Full(n) => {
-                self.state = CollectionState::Full(n + 1);
-                self.inner.push(Some(item));
-                n as usize
-            }
+    /// The allocator the slots live in.
+    pub fn allocator(&self) -> &A {
+        self.inner.allocator()
+    }
 
-            CollectionState::NotFull(n, avail) => {
-                let avail = avail - 1;
+    pub fn add(&mut self, item: T) -> usize {
+        self.add_low(item)
+    }
 
-                for i in 0..n {
-                    let slot = &mut self.inner[i as usize];
-                    if slot.is_none() {
-                        *slot = Some(item);
+    /// Reuses the lowest vacant index, which keeps the occupied slots packed
+    /// towards the front. Finding it takes O(log vacant slots).
+    pub fn add_low(&mut self, item: T) -> usize {
+        self.add_with(item, FreeList::pop_lowest)
+    }
 
-                        if avail > 0 {
-                            self.state = CollectionState::NotFull(n + 1, avail);
-                        } else {
-                            self.state = CollectionState::Full(n + 1);
-                        }
+    /// Reuses the most recently freed index, which is the one most likely to
+    /// still be in cache. Finding it takes O(log vacant slots), amortized.
+    pub fn add_hot(&mut self, item: T) -> usize {
+        self.add_with(item, FreeList::pop_most_recent)
+    }
 
-                        return i as usize;
-                    }
+    fn add_with(&mut self, item: T, mut pick: impl FnMut(&mut FreeList) -> Option<usize>) -> usize {
+        // Compact before adding so the index we return stays valid
+        self.auto_compact();
+
+        while let Some(i) = pick(&mut self.free) {
+            match self.inner.get_mut(i) {
+                Some(slot) if slot.is_none() => {
+                    *slot = Some(item);
+                    self.occupied += 1;
+                    self.note_filled(i);
+                    self.note_added(i);
+                    self.debug_assert_consistent();
+                    return i;
                 }
 
-                panic!("Collection notfull, but no available slot found!");
+                // The free list is out of sync with the slots, most likely
+                // because a vacancy was filled through `IndexMut`. Resync
+                // and keep looking.
+                _ => self.resync(),
             }
         }
+
+        self.before_growth(1);
+        // Retired slots past the end, left by compaction or `clear`, come
+        // back vacant
+        while self.generations.is_retired(self.inner.len()) {
+            self.inner.push(None);
+        }
+        self.inner.push(Some(item));
+        self.occupied += 1;
+        self.note_filled(self.inner.len() - 1);
+        self.note_added(self.inner.len() - 1);
+        self.debug_assert_consistent();
+        self.inner.len() - 1
     }
 
-    pub fn take(&mut self, index: usize) -> u8 {
-        let item = self[index].take().unwrap();
+    #[track_caller]
+    pub fn take(&mut self, index: usize) -> T {
+        match self.try_take(index) {
+            Some(item) => item,
+            None => self.vacant_panic(index, None),
+        }
+    }
 
-        match self.state {
-            CollectionState::Full(n) => self.state = CollectionState::NotFull(n - 1, 1),
-            CollectionState::NotFull(n, avail) => {
-                self.state = CollectionState::NotFull(n - 1, avail + 1)
-            }
-            _ => (),
+    /// Like `take`, but returns `None` instead of panicking when `index` is
+    /// out of range or vacant.
+    pub fn try_take(&mut self, index: usize) -> Option<T> {
+        let item = self.vacate(index)?;
+
+        // Compact after taking so `index` still referred to the right slot
+        self.auto_compact();
+        self.debug_assert_consistent();
+
+        Some(item)
+    }
+
+    /// Stores `item` at exactly `index`, growing the collection with vacant
+    /// slots as needed. Returns the value previously stored there, if any.
+    pub fn insert_at(&mut self, index: usize, item: T) -> Option<T> {
+        if self.inner.len() <= index {
+            self.grow_vacant_to(index + 1);
+            self.free.remove(index);
+        } else if self.inner[index].is_none() {
+            self.free.remove(index);
         }
 
-        item
+        let old = self.inner[index].replace(item);
+        if old.is_some() {
+            self.stats.vacated(index);
+            self.leaks.vacated(index);
+            self.occupancy.vacated(index);
+            self.pending_removals.vacated(index);
+            self.bitmap.vacated(index);
+            self.structure_changes += 1;
+        }
+        self.note_filled(index);
+        if old.is_some() {
+            // Keys to the replaced value mustn't resolve to the new one
+            self.generations.bump(index);
+        } else {
+            self.occupied += 1;
+        }
+        self.debug_assert_consistent();
+        old
     }
 
-    pub fn len(&self) -> u32 {
-        match self.state {
-            CollectionState::Full(n) => n,
-            CollectionState::NotFull(n, _) => n,
-            _ => 0,
+    /// Returns the value at `index`, first filling the slot with `f()` if it
+    /// is vacant or out of range.
+    pub fn get_or_insert_with(&mut self, index: usize, f: impl FnOnce() -> T) -> &mut T {
+        if self.get(index).is_none() {
+            self.insert_at(index, f());
         }
+
+        // Occupied one way or the other by now
+        self.inner[index].as_mut().unwrap()
+    }
+
+    /// The highest index backed by a slot, occupied or not.
+    pub fn max_index(&self) -> Option<usize> {
+        self.inner.len().checked_sub(1)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.inner.get(index)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.inner.get_mut(index)?.as_mut()
+    }
+
+    /// Same as `add`, named after `slab::Slab::insert`.
+    pub fn insert(&mut self, item: T) -> usize {
+        self.add(item)
+    }
+
+    /// Same as `take`, named after `slab::Slab::remove`.
+    #[track_caller]
+    pub fn remove(&mut self, index: usize) -> T {
+        self.take(index)
+    }
+
+    pub fn len(&self) -> u32 {
+        self.occupied as u32
     }
 
     pub fn is_empty(&self) -> bool {
-        match self.state {
-            CollectionState::Empty => true,
-            CollectionState::NotFull(n, _) => n == 0,
-            _ => false,
+        self.occupied == 0
+    }
+
+    /// Number of vacant slots that `add` will reuse before growing.
+    pub fn vacant_len(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Drops every value and slot, keeping the allocated capacity.
+    pub fn clear(&mut self) {
+        // Reset the bookkeeping first so a panicking `Drop` can't leave it
+        // describing slots that are gone
+        for (index, slot) in self.inner.iter().enumerate() {
+            if slot.is_some() {
+                self.generations.bump(index);
+                self.stats.vacated(index);
+            }
+        }
+        self.occupied = 0;
+        self.free.clear();
+        self.groups = Groups::new();
+        self.pending_removals = PendingRemovals::new();
+        self.tombstones = Tombstones::new();
+        self.inserted = InsertionTimes::new();
+        self.leaks.clear();
+        self.occupancy.clear();
+        self.bitmap.clear();
+        self.structure_changes += 1;
+        self.blocks = Blocks::new();
+        self.leases = Leases::new();
+        self.recent_adds = RecentAdds::new();
+        self.touched_through_index_mut = false;
+        let len = self.inner.len();
+        self.inner.clear();
+        wipe::released(&mut self.inner, len);
+    }
+
+    /// Drops every slot from `len` on, occupied or vacant, keeping the
+    /// allocated capacity. Keys to the dropped values stay stale if the
+    /// slots are added again later.
+    pub fn truncate(&mut self, len: usize) {
+        // Bookkeeping first, same reasoning as in `clear`
+        for index in len..self.inner.len() {
+            if self.inner[index].is_some() {
+                self.note_vacated(index);
+            }
+        }
+        // Including what `note_vacated` just put there
+        self.free.truncate(len);
+        self.pending_removals.truncate(len);
+        self.tombstones.truncate(len);
+        self.blocks.truncate(len);
+        self.leases.truncate(len);
+        let dropped = self.inner.len().saturating_sub(len);
+        self.inner.truncate(len);
+        wipe::released(&mut self.inner, dropped);
+        self.debug_assert_consistent();
+    }
+
+    /// Grows the collection to `len` slots, filling every new one with a
+    /// value from `f`, or shrinks it like `truncate`. Slots whose generation
+    /// was retired come back vacant instead.
+    pub fn resize_with(&mut self, len: usize, mut f: impl FnMut() -> T) {
+        if len <= self.inner.len() {
+            self.truncate(len);
+            return;
+        }
+
+        self.before_growth(len - self.inner.len());
+        while self.inner.len() < len {
+            let index = self.inner.len();
+            if self.generations.is_retired(index) {
+                self.inner.push(None);
+                continue;
+            }
+            // One slot at a time so a panicking `f` leaves everything so far
+            // accounted for
+            self.inner.push(Some(f()));
+            self.occupied += 1;
+            self.note_filled(index);
         }
+        self.debug_assert_consistent();
+    }
+
+    /// Like `into_iter`, but the emptied collection can be recovered with
+    /// `IntoIterReuse::finish` so its allocation can be used again.
+    pub fn into_iter_reuse(self) -> IntoIterReuse<T, Tag, A> {
+        IntoIterReuse(self.into_iter())
     }
 
     pub fn iter(&self) -> CollectionIter<&Self> {
         CollectionIter {
             inner: self,
             pos: 0,
+            remaining: self.counted_occupied(),
         }
     }
 
-    pub fn iter_mut(&mut self) -> CollectionIter<&mut Collection> {
-        CollectionIter {
-            inner: self,
-            pos: 0,
+    /// Iterates `(index, value)` over the occupied slots. Unlike
+    /// `iter().enumerate()`, the index is the slot's, not the position in
+    /// the iteration.
+    pub fn enumerate(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.inner
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| Some((index, slot.as_ref()?)))
+    }
+
+    /// Like `enumerate`, with mutable access to the values.
+    pub fn enumerate_mut(&mut self) -> impl Iterator<Item = (usize, &mut T)> {
+        self.inner
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| Some((index, slot.as_mut()?)))
+    }
+
+    /// Iterates `(index, value)` starting at `start` and wrapping around to
+    /// the front, so a round-robin scan can resume where the last one left
+    /// off. A `start` past the end begins at the front.
+    pub fn iter_rotated(&self, start: usize) -> impl Iterator<Item = (usize, &T)> {
+        let start = if start < self.inner.len() { start } else { 0 };
+        let (front, back) = self.inner.split_at(start);
+
+        let back = back
+            .iter()
+            .enumerate()
+            .map(move |(i, slot)| (start + i, slot));
+        back.chain(front.iter().enumerate())
+            .filter_map(|(index, slot)| Some((index, slot.as_ref()?)))
+    }
+
+    pub fn iter_copied(&self) -> Copied<CollectionIter<&Self>>
+    where
+        T: Copy,
+    {
+        self.iter().copied()
+    }
+
+    pub fn iter_cloned(&self) -> Cloned<CollectionIter<&Self>>
+    where
+        T: Clone,
+    {
+        self.iter().cloned()
+    }
+
+    /// Iterates `(index, value)` over a copy of the values taken when it's
+    /// created. It doesn't borrow the collection, so it can walk last tick's
+    /// state while this tick changes it.
+    pub fn copied_values(&self) -> std::vec::IntoIter<(usize, T)>
+    where
+        T: Copy,
+    {
+        let mut values = Vec::with_capacity(self.len() as usize);
+        values.extend(self.enumerate().map(|(index, &item)| (index, item)));
+        values.into_iter()
+    }
+
+    /// Clones every value into a `Vec` allocated up front from `len`.
+    pub fn to_owned_values(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let mut values = Vec::with_capacity(self.len() as usize);
+        values.extend(self.iter_cloned());
+        values
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            end: self.inner.len(),
+            remaining: self.counted_occupied(),
+            slots: self.inner.iter_mut(),
+        }
+    }
+
+    /// Like `iter_mut`, but starts at slot `pos`, e.g. to resume a scan
+    /// that stopped at `IterMut::pos`. Counts the occupied slots on the
+    /// shorter side of `pos` to keep `count` exact.
+    pub fn iter_mut_from(&mut self, pos: usize) -> IterMut<'_, T> {
+        let end = self.inner.len();
+        let pos = pos.min(end);
+        let remaining = if pos < end / 2 && !self.touched_through_index_mut {
+            let before = self.inner[..pos].iter().flatten().count();
+            self.occupied.saturating_sub(before)
+        } else {
+            self.inner[pos..].iter().flatten().count()
+        };
+
+        IterMut {
+            end,
+            slots: self.inner[pos..].iter_mut(),
+            remaining,
+        }
+    }
+
+    // `occupied`, or a count of the slots if `IndexMut` may have filled or
+    // emptied some behind its back. Iterators rely on it for exact counts.
+    fn counted_occupied(&self) -> usize {
+        if self.touched_through_index_mut {
+            self.inner.iter().flatten().count()
+        } else {
+            self.occupied
+        }
+    }
+
+    // The index the next `add` will use
+    // Stores `items` where that many `add` calls would, with the free list
+    // bookkeeping done once instead of per item
+    fn extend_filling_vacancies<I>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let mut items = items.into_iter();
+        let mut vacant = self.free.take_all();
+        // Skip stale entries left by `IndexMut`
+        vacant.retain(|&index| self.inner.get(index).is_some_and(Option::is_none));
+
+        let filled = vacant.len().min(items.len());
+        for (index, item) in vacant.drain(..filled).zip(&mut items) {
+            self.inner[index] = Some(item);
+            self.occupied += 1;
+            self.note_filled(index);
+        }
+        self.free.extend(vacant);
+
+        self.before_growth(items.len());
+        for item in items {
+            // Retired slots past the end come back vacant, same as in `add`
+            while self.generations.is_retired(self.inner.len()) {
+                self.inner.push(None);
+            }
+            self.inner.push(Some(item));
+            self.occupied += 1;
+            self.note_filled(self.inner.len() - 1);
+        }
+        self.debug_assert_consistent();
+    }
+
+    // Appends vacant slots until there are `len`, putting the reusable ones
+    // on the free list
+    fn grow_vacant_to(&mut self, len: usize) {
+        let start = self.inner.len();
+        if len <= start {
+            return;
+        }
+        self.before_growth(len - start);
+        self.inner.resize_with(len, || None);
+        for vacant in start..len {
+            if self.is_reusable(vacant) {
+                self.free.push_back(vacant);
+            }
+        }
+    }
+
+    // The index `add` appends at once the free list is empty, past any
+    // retired slots at the end
+    fn append_index(&self) -> usize {
+        let mut index = self.inner.len();
+        while self.generations.is_retired(index) {
+            index += 1;
+        }
+        index
+    }
+
+    fn next_index(&self) -> usize {
+        self.free.lowest().unwrap_or(self.inner.len())
+    }
+
+    // `try_take` without compacting, for callers that keep going by index
+    fn vacate(&mut self, index: usize) -> Option<T> {
+        let slot = self.inner.get_mut(index)?;
+        let item = slot.take()?;
+        wipe::vacated(slot);
+        self.note_vacated(index);
+        Some(item)
+    }
+
+    // `vacate`, dropping the value where it is instead of moving it out
+    fn vacate_in_place(&mut self, index: usize) -> bool {
+        if self.get(index).is_none() {
+            return false;
+        }
+        // Bookkeeping first, same reasoning as in `clear`. The slot is
+        // `None` afterwards even if the value's `Drop` panics.
+        self.note_vacated(index);
+        let slot = &mut self.inner[index];
+        *slot = None;
+        wipe::vacated(slot);
+        true
+    }
+
+    // Bookkeeping for a slot whose value is gone or about to be
+    fn note_vacated(&mut self, index: usize) {
+        self.groups.unlink(index);
+        self.generations.bump(index);
+        if self.is_reusable(index) {
+            self.free.push_back(index);
+        }
+        self.count_vacated();
+        self.stats.vacated(index);
+        self.leaks.vacated(index);
+        self.occupancy.vacated(index);
+        self.pending_removals.vacated(index);
+        self.bitmap.vacated(index);
+        self.structure_changes += 1;
+    }
+
+    // Bookkeeping for everything that stores a value in a slot
+    fn note_filled(&mut self, index: usize) {
+        self.inserted.stamp(index);
+        self.stats.filled(index);
+        self.leaks.filled(index);
+        self.occupancy.filled(index);
+        self.bitmap.filled(index);
+        self.structure_changes += 1;
+    }
+
+    // Called whenever a slot is vacated. The count can only be out of sync
+    // if a vacancy was filled through `IndexMut`, which debug builds report
+    // and release builds (or `no-panic`) repair.
+    fn count_vacated(&mut self) {
+        match self.occupied.checked_sub(1) {
+            Some(occupied) => self.occupied = occupied,
+            None => self.occupied_underflow(),
+        }
+    }
+
+    #[cold]
+    fn occupied_underflow(&mut self) {
+        if cfg!(all(debug_assertions, not(feature = "no-panic"))) {
+            panic!(
+                "Collection's occupied count underflowed, a vacant slot was \
+                 most likely filled through `IndexMut`"
+            );
+        }
+        self.resync();
+    }
+
+    // Rebuilds the occupied count and free list from the slots themselves
+    fn resync(&mut self) {
+        let vacant: Vec<usize> = self
+            .inner
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.is_none())
+            .map(|(index, _)| index)
+            .collect();
+
+        self.occupied = self.inner.len() - vacant.len();
+        self.touched_through_index_mut = false;
+        self.occupancy.rebuild(&self.inner);
+        self.bitmap.rebuild(&self.inner);
+        self.structure_changes += 1;
+        // Reserved and retired vacancies stay off the free list
+        self.free.clear();
+        for index in vacant {
+            self.groups.unlink(index);
+            if self.is_reusable(index) {
+                self.free.push_back(index);
+            }
         }
     }
 }
 
-impl Index<usize> for Collection {
-    type Output = Option<u8>;
+impl<T, Tag, A: Allocator + Default> Default for Collection<T, Tag, A> {
+    fn default() -> Self {
+        Self::new_in(A::default())
+    }
+}
+
+impl<T, Tag, A: Allocator> Index<usize> for Collection<T, Tag, A> {
+    type Output = Option<T>;
+    #[track_caller]
     fn index(&self, index: usize) -> &Self::Output {
         &self.inner[index]
     }
 }
 
-impl IndexMut<usize> for Collection {
+// Gone with `strict`, see `from_raw_parts`
+#[cfg(not(feature = "strict"))]
+impl<T, Tag, A: Allocator> IndexMut<usize> for Collection<T, Tag, A> {
+    #[track_caller]
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        self.touched_through_index_mut = true;
+        // Can't tell whether the slot is about to be filled or emptied
+        self.structure_changes += 1;
         &mut self.inner[index]
     }
 }
@@ -124,111 +799,260 @@ impl IndexMut<usize> for Collection {
 pub struct CollectionIter<T> {
     inner: T,
     pos: usize,
+    // Occupied slots not yet yielded, which is what lets `count` and `nth`
+    // answer without scanning the holes
+    remaining: usize,
+}
+
+/// Mutable iterator over the occupied slots, created by `iter_mut`.
+pub struct IterMut<'a, T> {
+    slots: std::slice::IterMut<'a, Option<T>>,
+    // The number of slots in the collection, which `pos` counts back from
+    end: usize,
+    remaining: usize,
+}
+
+impl<T> IterMut<'_, T> {
+    /// The slot index the next call to `next` starts looking at.
+    pub fn pos(&self) -> usize {
+        self.end - self.slots.len()
+    }
 }
 
-impl<'a> Iterator for CollectionIter<&'a mut Collection> {
-    type Item = &'a mut u8;
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let res: *mut u8 = loop {
-            let current_idx = self.pos;
-            self.pos += 1;
-            
-            if let Some(x) = self.inner.inner.get_mut(current_idx)? {
-                break x;
-            }
-        };
-        
-        // Safety: our algorithm guarantees that the iterator cannot yield a
-        // reference to the same element twice, so it's safe to reinterpret the
-        // &'self mut u8 as a &'a mut u8 as there won't be any aliasing 
-        // of the inner collection possible
-        Some(unsafe { &mut *res })
+        let item = self.slots.find_map(Option::as_mut)?;
+        self.remaining = self.remaining.saturating_sub(1);
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    fn count(self) -> usize {
+        self.remaining
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.remaining {
+            self.slots.nth(self.slots.len());
+            self.remaining = 0;
+            return None;
+        }
+        for _ in 0..n {
+            self.next();
+        }
+        self.next()
+    }
+
+    fn last(self) -> Option<Self::Item> {
+        self.slots.rev().find_map(Option::as_mut)
+    }
+
+    fn fold<B, F>(self, init: B, f: F) -> B
+    where
+        F: FnMut(B, Self::Item) -> B,
+    {
+        self.slots.flatten().fold(init, f)
     }
 }
 
-impl<'a> Iterator for CollectionIter<&'a Collection> {
-    type Item = &'a u8;
+impl<'a, T, Tag, A: Allocator> Iterator for CollectionIter<&'a Collection<T, Tag, A>> {
+    type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let limit = self.inner.inner.len() - 1;
-        if self.pos > limit {
-            return None;
-        };
+        let inner: &'a Collection<T, Tag, A> = self.inner;
 
-        while self.inner.inner[self.pos].is_none() {
+        while let Some(slot) = inner.inner.get(self.pos) {
             self.pos += 1;
-            if self.pos > limit {
-                return None;
+
+            if let Some(item) = slot {
+                self.remaining = self.remaining.saturating_sub(1);
+                return Some(item);
             }
         }
 
-        // We know it's `Some`
-        let res = &self.inner.inner[self.pos];
-        self.pos += 1;
-        res.as_ref()
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
     }
-}
 
-impl Iterator for CollectionIter<Collection> {
-    type Item = u8;
+    fn count(self) -> usize {
+        self.remaining
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let limit = self.inner.inner.len() - 1;
-        if self.pos > limit {
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.remaining {
+            self.pos = self.inner.inner.len();
+            self.remaining = 0;
             return None;
-        };
+        }
+        for _ in 0..n {
+            self.next();
+        }
+        self.next()
+    }
 
-        while self.inner.inner[self.pos].is_none() {
+    fn last(self) -> Option<Self::Item> {
+        let inner: &'a Collection<T, Tag, A> = self.inner;
+        inner
+            .inner
+            .get(self.pos..)?
+            .iter()
+            .rev()
+            .find_map(Option::as_ref)
+    }
+
+    // Folding over the remaining slice in one go lets the compiler drop the
+    // per-item bounds checks. `try_fold` can't be overridden on stable since
+    // its `Try` bound is unstable.
+    fn fold<B, F>(self, init: B, f: F) -> B
+    where
+        F: FnMut(B, Self::Item) -> B,
+    {
+        let inner: &'a Collection<T, Tag, A> = self.inner;
+        match inner.inner.get(self.pos..) {
+            Some(rest) => rest.iter().flatten().fold(init, f),
+            None => init,
+        }
+    }
+}
+
+impl<T, Tag, A: Allocator> Iterator for CollectionIter<Collection<T, Tag, A>> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(slot) = self.inner.inner.get_mut(self.pos) {
             self.pos += 1;
-            if self.pos > limit {
-                return None;
+
+            // The collection is consumed by the iterator, so there is no
+            // state left to keep in sync
+            if let Some(item) = slot.take() {
+                self.remaining = self.remaining.saturating_sub(1);
+                return Some(item);
             }
         }
 
-        // We know it's `Some`
-        let res = self.inner.inner[self.pos];
-        self.pos += 1;
-        res
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    fn count(self) -> usize {
+        self.remaining
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.remaining {
+            // Dropping the values now rather than with the iterator would
+            // be surprising, so just leave the slots as they are
+            self.pos = self.inner.inner.len();
+            self.remaining = 0;
+            return None;
+        }
+        for _ in 0..n {
+            self.next();
+        }
+        self.next()
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.inner.inner.drain(self.pos..).rev().flatten().next()
+    }
+
+    fn fold<B, F>(mut self, init: B, f: F) -> B
+    where
+        F: FnMut(B, Self::Item) -> B,
+    {
+        // Everything before `pos` has already been taken
+        self.inner.inner.drain(self.pos..).flatten().fold(init, f)
     }
 }
 
-impl IntoIterator for Collection {
-    type Item = u8;
-    type IntoIter = CollectionIter<Collection>;
+impl<T, Tag, A: Allocator> IntoIterator for Collection<T, Tag, A> {
+    type Item = T;
+    type IntoIter = CollectionIter<Collection<T, Tag, A>>;
 
     fn into_iter(self) -> Self::IntoIter {
         CollectionIter {
+            remaining: self.counted_occupied(),
             inner: self,
             pos: 0,
         }
     }
 }
 
-impl<'a> IntoIterator for &'a Collection {
-    type Item = &'a u8;
-    type IntoIter = CollectionIter<&'a Collection>;
+impl<T, Tag, A: Allocator> IntoIterator for Box<Collection<T, Tag, A>> {
+    type Item = T;
+    type IntoIter = CollectionIter<Collection<T, Tag, A>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        (*self).into_iter()
+    }
+}
+
+pub struct IntoIterReuse<T, Tag = (), A: Allocator = Global>(CollectionIter<Collection<T, Tag, A>>);
+
+impl<T, Tag, A: Allocator> IntoIterReuse<T, Tag, A> {
+    /// Drops any values not yet yielded and hands back the empty collection
+    /// with its capacity intact.
+    pub fn finish(self) -> Collection<T, Tag, A> {
+        let mut collection = self.0.inner;
+        collection.clear();
+        collection
+    }
+}
+
+impl<T, Tag, A: Allocator> Iterator for IntoIterReuse<T, Tag, A> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+
+    fn count(self) -> usize {
+        self.0.count()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.0.nth(n)
+    }
+}
+
+impl<'a, T, Tag, A: Allocator> IntoIterator for &'a Collection<T, Tag, A> {
+    type Item = &'a T;
+    type IntoIter = CollectionIter<&'a Collection<T, Tag, A>>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
     }
 }
 
-impl<'a> IntoIterator for &'a mut Collection {
-    type Item = &'a mut u8;
-    type IntoIter = CollectionIter<&'a mut Collection>;
+impl<'a, T, Tag, A: Allocator> IntoIterator for &'a mut Collection<T, Tag, A> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
 
     fn into_iter(self) -> Self::IntoIter {
-        CollectionIter {
-            inner: self,
-            pos: 0,
-        }
+        self.iter_mut()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::cell::RefCell;
+    use std::panic::{self, AssertUnwindSafe};
 
     #[test]
     fn basic_operations_doesnt_panic() {
@@ -258,4 +1082,466 @@ mod tests {
             println!("{}", item);
         }
     }
+
+    // Pins down the behavior the old `CollectionState` machine had
+    #[test]
+    fn compatible_with_state_machine_behavior() {
+        let mut coll = Collection::new();
+        assert!(coll.is_empty());
+        assert_eq!(coll.len(), 0);
+
+        assert_eq!(coll.add('a'), 0);
+        assert_eq!(coll.add('b'), 1);
+        assert_eq!(coll.add('c'), 2);
+        assert_eq!(coll.len(), 3);
+        assert_eq!(coll.vacant_len(), 0);
+
+        assert_eq!(coll.take(2), 'c');
+        assert_eq!(coll.take(0), 'a');
+        assert_eq!(coll.len(), 1);
+        assert_eq!(coll.vacant_len(), 2);
+        assert!(!coll.is_empty());
+
+        // Vacancies are reused lowest first before the collection grows
+        assert_eq!(coll.add('d'), 0);
+        assert_eq!(coll.add('e'), 2);
+        assert_eq!(coll.add('f'), 3);
+
+        for index in 0..4 {
+            coll.take(index);
+        }
+        assert!(coll.is_empty());
+        assert_eq!(coll.len(), 0);
+        assert_eq!(coll.vacant_len(), 4);
+        assert_eq!(coll.iter().next(), None);
+        assert_eq!(coll.add('g'), 0);
+    }
+
+    #[test]
+    fn fallible_apis_dont_panic_on_hostile_input() {
+        let mut coll: Collection<u8> = Collection::new();
+        assert_eq!(coll.try_take(0), None);
+        assert_eq!(coll.get(usize::MAX), None);
+        assert_eq!(coll.iter().next(), None);
+        assert_eq!(coll.iter_mut().next(), None);
+
+        coll.add(1);
+        coll.add(2);
+        assert_eq!(coll.try_take(0), Some(1));
+        assert_eq!(coll.try_take(0), None);
+        assert_eq!(coll.get_mut(0), None);
+        assert_eq!(coll.len(), 1);
+        assert_eq!(coll.into_iter().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn into_iter_reuse_keeps_capacity() {
+        let mut coll = Collection::new();
+        for i in 0..100 {
+            coll.add(i);
+        }
+        coll.take(50);
+        let capacity = coll.inner.capacity();
+
+        let mut iter = coll.into_iter_reuse();
+        assert_eq!(iter.by_ref().take(3).collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        let coll = iter.finish();
+        assert!(coll.is_empty());
+        assert_eq!(coll.inner.capacity(), capacity);
+
+        let boxed = Box::new(coll);
+        assert_eq!(boxed.into_iter().next(), None);
+    }
+
+    #[test]
+    fn owned_value_iterators_leave_collection_intact() {
+        let mut coll = Collection::new();
+        coll.add(1);
+        coll.add(2);
+        coll.add(3);
+        coll.take(1);
+
+        assert_eq!(coll.iter_copied().sum::<i32>(), 4);
+
+        let mut strings = Collection::new();
+        strings.add(String::from("a"));
+        strings.add(String::from("b"));
+        let owned = strings.to_owned_values();
+        assert_eq!(owned, vec!["a", "b"]);
+        assert_eq!(owned.capacity(), 2);
+        assert_eq!(strings.iter_cloned().count(), 2);
+    }
+
+    #[test]
+    fn insert_at_grows_and_replaces() {
+        let mut coll = Collection::new();
+        assert_eq!(coll.max_index(), None);
+
+        assert_eq!(coll.insert_at(3, 'a'), None);
+        assert_eq!(coll.max_index(), Some(3));
+        assert_eq!(coll.len(), 1);
+
+        assert_eq!(coll.insert_at(3, 'b'), Some('a'));
+        assert_eq!(coll.insert_at(1, 'c'), None);
+        assert_eq!(coll.len(), 2);
+
+        // The vacancies in front are used by regular adds
+        assert_eq!(coll.add('d'), 0);
+        assert_eq!(coll.add('e'), 2);
+        assert_eq!(coll.add('f'), 4);
+        assert_eq!(coll.len(), 5);
+    }
+
+    #[test]
+    fn get_or_insert_with_only_calls_f_for_vacancies() {
+        let mut coll = Collection::new();
+        coll.add(1);
+
+        *coll.get_or_insert_with(0, || unreachable!()) += 1;
+        assert_eq!(coll[0], Some(2));
+
+        assert_eq!(*coll.get_or_insert_with(2, || 3), 3);
+        assert_eq!(coll.len(), 2);
+        assert_eq!(coll.add(4), 1);
+    }
+
+    #[test]
+    fn add_low_and_add_hot_pick_different_vacancies() {
+        let mut coll = Collection::new();
+        for i in 0..6 {
+            coll.add(i);
+        }
+        coll.take(1);
+        coll.take(4);
+        coll.take(2);
+
+        assert_eq!(coll.add_hot(10), 2);
+        assert_eq!(coll.add_low(11), 1);
+        assert_eq!(coll.add_hot(12), 4);
+        assert_eq!(coll.add_hot(13), 6);
+        assert_eq!(coll.len(), 7);
+    }
+
+    #[test]
+    fn fold_matches_next_after_partial_iteration() {
+        let mut coll = Collection::new();
+        for i in 0..10 {
+            coll.add(i);
+        }
+        coll.take(3);
+        coll.take(8);
+
+        let push = |mut v: Vec<i32>, x| {
+            v.push(x);
+            v
+        };
+
+        let mut iter = coll.iter();
+        iter.next();
+        let folded = iter.copied().fold(Vec::new(), push);
+        assert_eq!(folded, vec![1, 2, 4, 5, 6, 7, 9]);
+
+        let mut iter = coll.iter_mut();
+        iter.next();
+        assert_eq!(iter.next(), Some(&mut 1));
+        iter.for_each(|x| *x *= 10);
+        assert_eq!(coll.to_owned_values(), vec![0, 1, 20, 40, 50, 60, 70, 90]);
+
+        let mut owned = coll.into_iter();
+        owned.next();
+        owned.next();
+        assert_eq!(owned.fold(Vec::new(), push), vec![20, 40, 50, 60, 70, 90]);
+    }
+
+    #[test]
+    fn count_nth_and_last_use_occupancy() {
+        let mut coll = Collection::new();
+        for i in 0..10 {
+            coll.add(i);
+        }
+        for i in (0..10).step_by(3) {
+            coll.take(i);
+        }
+
+        let mut iter = coll.iter();
+        assert_eq!(iter.size_hint(), (6, Some(6)));
+        assert_eq!(iter.nth(1), Some(&2));
+        assert_eq!(iter.size_hint(), (4, Some(4)));
+        assert_eq!(iter.nth(4), None);
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(coll.iter().last(), Some(&8));
+        assert_eq!(coll.iter_mut().nth(5), Some(&mut 8));
+        assert_eq!(coll.iter_mut().last(), Some(&mut 8));
+        assert_eq!(coll.iter_mut().count(), 6);
+
+        let mut owned = coll.into_iter();
+        assert_eq!(owned.nth(6), None);
+        assert_eq!(owned.count(), 0);
+    }
+
+    #[test]
+    fn enumerate_yields_slot_indices() {
+        let mut coll = Collection::new();
+        for i in 0..4 {
+            coll.add(i * 10);
+        }
+        coll.take(1);
+
+        let touched: Vec<usize> = coll
+            .enumerate_mut()
+            .filter(|(_, value)| **value > 0)
+            .map(|(index, value)| {
+                *value += 1;
+                index
+            })
+            .collect();
+        assert_eq!(touched, vec![2, 3]);
+        assert_eq!(
+            coll.enumerate().collect::<Vec<_>>(),
+            vec![(0, &0), (2, &21), (3, &31)]
+        );
+    }
+
+    #[test]
+    fn iter_mut_resumes_after_partial_consumption() {
+        struct Scheduler {
+            tasks: Collection<u32>,
+            cursor: usize,
+        }
+
+        impl Scheduler {
+            // Bumps at most `budget` tasks, continuing where the last call
+            // stopped
+            fn run(&mut self, budget: usize) {
+                let mut iter = self.tasks.iter_mut_from(self.cursor);
+                for task in iter.by_ref().take(budget) {
+                    *task += 1;
+                }
+                self.cursor = if iter.size_hint().0 == 0 {
+                    0
+                } else {
+                    iter.pos()
+                };
+            }
+        }
+
+        let mut tasks = Collection::new();
+        for _ in 0..5 {
+            tasks.add(0);
+        }
+        tasks.take(1);
+        let mut scheduler = Scheduler { tasks, cursor: 0 };
+
+        scheduler.run(2);
+        assert_eq!(scheduler.cursor, 3);
+        scheduler.run(2);
+        assert_eq!(scheduler.cursor, 0);
+        scheduler.run(1);
+        assert_eq!(scheduler.tasks.to_owned_values(), vec![2, 1, 1, 1]);
+
+        let mut iter = scheduler.tasks.iter_mut();
+        assert_eq!(iter.by_ref().nth(1), Some(&mut 1));
+        assert_eq!(iter.pos(), 3);
+        assert_eq!(iter.count(), 2);
+        assert_eq!(scheduler.tasks.iter_mut_from(4).count(), 1);
+        assert_eq!(scheduler.tasks.iter_mut_from(9).next(), None);
+    }
+
+    #[test]
+    fn iter_rotated_wraps_around() {
+        let mut coll = Collection::new();
+        for i in 0..5 {
+            coll.add(i);
+        }
+        coll.take(3);
+
+        let order: Vec<_> = coll.iter_rotated(2).map(|(index, _)| index).collect();
+        assert_eq!(order, vec![2, 4, 0, 1]);
+        assert_eq!(coll.iter_rotated(9).count(), 4);
+        assert_eq!(Collection::<u8>::new().iter_rotated(1).next(), None);
+    }
+
+    struct Noisy<'a> {
+        id: u32,
+        panics: bool,
+        log: &'a RefCell<Vec<u32>>,
+    }
+
+    impl Drop for Noisy<'_> {
+        fn drop(&mut self) {
+            self.log.borrow_mut().push(self.id);
+            if self.panics {
+                panic!("Noisy {} panicked on drop", self.id);
+            }
+        }
+    }
+
+    fn noisy(id: u32, panics: bool, log: &RefCell<Vec<u32>>) -> Noisy<'_> {
+        Noisy { id, panics, log }
+    }
+
+    #[test]
+    fn drops_values_in_slot_order() {
+        let log = RefCell::new(Vec::new());
+        let mut coll = Collection::new();
+        for id in 0..5 {
+            coll.add(noisy(id, false, &log));
+        }
+
+        let taken = coll.take(1);
+        assert!(log.borrow().is_empty());
+        coll.add(noisy(10, false, &log));
+
+        drop(coll);
+        assert_eq!(*log.borrow(), vec![0, 10, 2, 3, 4]);
+
+        drop(taken);
+        assert_eq!(log.borrow().last(), Some(&1));
+    }
+
+    #[test]
+    fn copied_values_outlive_the_borrow() {
+        let mut positions = Collection::new();
+        positions.add(1.0f32);
+        positions.add(2.0);
+        positions.add(4.0);
+        positions.take(1);
+
+        for (index, previous) in positions.copied_values() {
+            *positions.get_mut(index).unwrap() = previous * 2.0;
+            positions.add(previous);
+        }
+        assert_eq!(positions.to_owned_values(), vec![2.0, 1.0, 8.0, 4.0]);
+    }
+
+    #[test]
+    fn truncate_and_resize_keep_the_bookkeeping() {
+        let mut coll = Collection::new();
+        for i in 0..6 {
+            coll.add(i);
+        }
+        coll.take(1);
+        coll.take(4);
+        let stale = coll.key_for_index(5).unwrap();
+
+        coll.truncate(3);
+        assert_eq!(coll.to_owned_values(), vec![0, 2]);
+        assert_eq!((coll.len(), coll.vacant_len()), (2, 1));
+
+        let mut next = 10;
+        coll.resize_with(6, || {
+            next += 1;
+            next
+        });
+        assert_eq!(coll.to_owned_values(), vec![0, 2, 11, 12, 13]);
+        assert_eq!(coll.get_key(stale), None);
+        assert_eq!(coll.add(14), 1);
+
+        coll.resize_with(0, || unreachable!());
+        assert!(coll.is_empty());
+        assert_eq!(coll.add(15), 0);
+    }
+
+    #[test]
+    fn clear_drops_remaining_values_when_one_panics() {
+        let log = RefCell::new(Vec::new());
+        let mut coll = Collection::new();
+        for id in 0..5 {
+            coll.add(noisy(id, id == 2, &log));
+        }
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| coll.clear()));
+        assert!(result.is_err());
+        assert_eq!(*log.borrow(), vec![0, 1, 2, 3, 4]);
+        assert!(coll.is_empty());
+        assert_eq!(coll.add(noisy(5, false, &log)), 0);
+    }
+
+    #[cfg(not(feature = "strict"))]
+    #[test]
+    #[cfg_attr(
+        all(debug_assertions, not(feature = "no-panic")),
+        should_panic(expected = "occupied count underflowed")
+    )]
+    fn take_after_untracked_fill_doesnt_underflow() {
+        let mut coll = Collection::new();
+        coll.add(1);
+        coll.take(0);
+        coll[0] = Some(2);
+
+        assert_eq!(coll.take(0), 2);
+        assert_eq!(coll.len(), 0);
+        assert_eq!(coll.add(3), 0);
+    }
+
+    #[cfg(not(feature = "strict"))]
+    #[test]
+    fn add_recovers_from_out_of_sync_state() {
+        let mut coll = Collection::new();
+        coll.add(1);
+        coll.add(2);
+        coll.take(0);
+
+        // Fill the vacancy behind the state's back
+        coll[0] = Some(3);
+
+        assert_eq!(coll.add(4), 2);
+        assert_eq!(coll.len(), 3);
+    }
+
+    #[cfg(not(feature = "strict"))]
+    #[test]
+    fn iterators_count_slots_filled_through_index_mut() {
+        let mut coll = Collection::new();
+        coll.add(1);
+        coll.add(2);
+        coll.take(0);
+        coll[0] = Some(9);
+
+        assert_eq!(coll.iter().count(), 2);
+        assert_eq!(coll.iter().nth(1), Some(&2));
+        assert_eq!(coll.iter_mut().size_hint(), (2, Some(2)));
+        assert_eq!(coll.iter_mut_from(1).count(), 1);
+        assert_eq!(coll.into_iter().count(), 2);
+    }
+
+    // Hands out memory from the global allocator, keeping count of what's
+    // still out
+    #[derive(Default)]
+    struct Counting(std::cell::Cell<usize>);
+
+    unsafe impl Allocator for &Counting {
+        fn allocate(
+            &self,
+            layout: std::alloc::Layout,
+        ) -> Result<std::ptr::NonNull<[u8]>, allocator_api2::alloc::AllocError> {
+            self.0.set(self.0.get() + layout.size());
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: std::ptr::NonNull<u8>, layout: std::alloc::Layout) {
+            self.0.set(self.0.get() - layout.size());
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn slots_live_in_the_given_allocator() {
+        let arena = Counting::default();
+        let mut coll = Collection::<u64, (), _>::with_capacity_in(2, &arena);
+        assert!(arena.0.get() >= 2 * std::mem::size_of::<Option<u64>>());
+
+        for i in 0..10 {
+            coll.add(i);
+        }
+        coll.take(3);
+        assert_eq!(coll.add(30), 3);
+        assert_eq!(coll.iter().sum::<u64>(), 72);
+        assert!(arena.0.get() >= 10 * std::mem::size_of::<Option<u64>>());
+
+        drop(coll);
+        assert_eq!(arena.0.get(), 0);
+    }
 }
@@ -0,0 +1,75 @@
+//! Looking up many indices at once.
+//!
+//! `get_many_refs` resolves a whole batch in one pass, e.g. every handle a
+//! frame draws, and reports every index that didn't resolve together
+//! instead of failing on the first.
+use std::error::Error;
+use std::fmt;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+/// Returned by `get_many_refs`, with every index that was vacant or out of
+/// range, in the order they were given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidIndices {
+    pub indices: Vec<usize>,
+}
+
+impl fmt::Display for InvalidIndices {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} indices are vacant or out of range: {:?}",
+            self.indices.len(),
+            self.indices
+        )
+    }
+}
+
+impl Error for InvalidIndices {}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// The value at every index in `indices`, in the same order, or every
+    /// index that has none.
+    pub fn get_many_refs(&self, indices: &[usize]) -> Result<Vec<&T>, InvalidIndices> {
+        let mut found = Vec::with_capacity(indices.len());
+        let mut invalid = Vec::new();
+        for &index in indices {
+            match self.inner.get(index) {
+                Some(Some(item)) => found.push(item),
+                _ => invalid.push(index),
+            }
+        }
+
+        if invalid.is_empty() {
+            Ok(found)
+        } else {
+            Err(InvalidIndices { indices: invalid })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_every_invalid_index() {
+        let mut coll = Collection::new();
+        for c in "abcd".chars() {
+            coll.add(c);
+        }
+        coll.take(1);
+
+        assert_eq!(coll.get_many_refs(&[3, 0, 3]), Ok(vec![&'d', &'a', &'d']));
+        assert_eq!(
+            coll.get_many_refs(&[0, 1, 2, 7]),
+            Err(InvalidIndices {
+                indices: vec![1, 7]
+            })
+        );
+        assert_eq!(coll.get_many_refs(&[]), Ok(vec![]));
+    }
+}
@@ -0,0 +1,385 @@
+//! A collection stored in a memory-mapped file.
+//!
+//! The file starts with a small header, followed by a bitmap with one bit
+//! per slot telling whether it's occupied, followed by the slots themselves.
+//! Only the bitmap decides what's occupied, so after a crash `open` rebuilds
+//! everything else from it.
+//!
+//! `add` is a two-phase commit: it records the slot it's about to write in
+//! the header, writes the value, then sets the slot's bit and clears the
+//! record. If `open` finds a record left behind, the write never finished
+//! and the slot is discarded. How much of that is guaranteed to have reached
+//! the disk before a crash is up to the `SyncPolicy`.
+use bytemuck::Pod;
+use memmap2::MmapMut;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Range;
+use std::path::Path;
+
+const MAGIC: [u8; 8] = *b"SLOTVEC\0";
+const HEADER_LEN: usize = 64;
+const INITIAL_CAPACITY: usize = 64;
+
+// Byte offsets of the header fields after the magic
+const SLOT_SIZE_AT: usize = 8;
+const CAPACITY_AT: usize = 16;
+// One past the index of a slot that is being written, or 0
+const PENDING_AT: usize = 24;
+
+/// When changes are written to disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Every step of every `add` and `take` is synced before the next one,
+    /// so the file is consistent at any point a crash could hit. Costs a
+    /// few disk syncs per call.
+    Always,
+    /// Changes are synced by `flush` and when the collection is dropped.
+    OnFlush,
+    /// Changes are left to the OS to write back whenever it likes, `flush`
+    /// still syncs them. The steps may reach the disk out of order, so a
+    /// crash can leave a slot marked occupied with a half-written value.
+    /// The default.
+    #[default]
+    Os,
+}
+
+pub struct MappedCollection<T> {
+    file: File,
+    map: MmapMut,
+    capacity: usize,
+    occupied: usize,
+    // Vacant indices, lowest first
+    free: BinaryHeap<Reverse<usize>>,
+    sync: SyncPolicy,
+    values: PhantomData<T>,
+}
+
+impl<T: Pod> MappedCollection<T> {
+    /// Opens the collection stored at `path`, creating the file if it
+    /// doesn't exist.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified, truncated or mapped by anyone else
+    /// while the collection is open, or the values read from it can change
+    /// underneath you.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        assert!(mem::size_of::<T>() > 0, "Zero-sized values can't be mapped");
+        assert!(
+            mem::align_of::<T>() <= HEADER_LEN,
+            "Over-aligned values can't be mapped"
+        );
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        if file.metadata()?.len() == 0 {
+            file.set_len(Self::file_len(INITIAL_CAPACITY) as u64)?;
+            let mut map = MmapMut::map_mut(&file)?;
+            map[..8].copy_from_slice(&MAGIC);
+            write_u64(&mut map, SLOT_SIZE_AT, mem::size_of::<T>());
+            write_u64(&mut map, CAPACITY_AT, INITIAL_CAPACITY);
+            return Ok(Self::recover(file, map, INITIAL_CAPACITY));
+        }
+
+        let map = MmapMut::map_mut(&file)?;
+        if map.len() < HEADER_LEN || map[..8] != MAGIC {
+            return Err(invalid_data("not a slotvec file"));
+        }
+        if read_u64(&map, SLOT_SIZE_AT) != mem::size_of::<T>() {
+            return Err(invalid_data("the file stores values of a different size"));
+        }
+        let capacity = read_u64(&map, CAPACITY_AT);
+        if map.len() < Self::file_len(capacity) {
+            return Err(invalid_data("the file is shorter than its header claims"));
+        }
+
+        Ok(Self::recover(file, map, capacity))
+    }
+
+    pub fn add(&mut self, item: T) -> io::Result<usize> {
+        let index = match self.free.pop() {
+            Some(Reverse(index)) => index,
+            None => {
+                let index = self.capacity;
+                self.grow()?;
+                self.free.pop();
+                index
+            }
+        };
+
+        write_u64(&mut self.map, PENDING_AT, index + 1);
+        self.sync(0..HEADER_LEN)?;
+
+        let range = self.slot_range(index);
+        self.map[range.clone()].copy_from_slice(bytemuck::bytes_of(&item));
+        self.sync(range)?;
+
+        self.set_occupied(index, true);
+        write_u64(&mut self.map, PENDING_AT, 0);
+        self.sync(0..HEADER_LEN + index / 8 + 1)?;
+        self.occupied += 1;
+        Ok(index)
+    }
+
+    pub fn take(&mut self, index: usize) -> Option<T> {
+        let item = *self.get(index)?;
+        self.set_occupied(index, false);
+        // The value is still readable here, syncing can only fail to make
+        // the take durable
+        let _ = self.sync(HEADER_LEN + index / 8..HEADER_LEN + index / 8 + 1);
+        self.occupied -= 1;
+        self.free.push(Reverse(index));
+        Some(item)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if !self.is_occupied(index) {
+            return None;
+        }
+        Some(bytemuck::from_bytes(&self.map[self.slot_range(index)]))
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if !self.is_occupied(index) {
+            return None;
+        }
+        let range = self.slot_range(index);
+        Some(bytemuck::from_bytes_mut(&mut self.map[range]))
+    }
+
+    pub fn len(&self) -> usize {
+        self.occupied
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.occupied == 0
+    }
+
+    /// Number of slots the file currently has room for. Adding past it
+    /// grows the file.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Iterates `(index, value)` over the occupied slots.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> + '_ {
+        (0..self.capacity).filter_map(move |index| Some((index, self.get(index)?)))
+    }
+
+    /// Writes all changes to disk and waits for it to finish.
+    pub fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+
+    pub fn set_sync_policy(&mut self, policy: SyncPolicy) {
+        self.sync = policy;
+    }
+
+    pub fn sync_policy(&self) -> SyncPolicy {
+        self.sync
+    }
+
+    // One step of a write under `SyncPolicy::Always`
+    fn sync(&self, range: Range<usize>) -> io::Result<()> {
+        if self.sync == SyncPolicy::Always {
+            self.map.flush_range(range.start, range.len())?;
+        }
+        Ok(())
+    }
+
+    fn recover(file: File, map: MmapMut, capacity: usize) -> Self {
+        let mut coll = Self {
+            file,
+            map,
+            capacity,
+            occupied: 0,
+            free: BinaryHeap::new(),
+            sync: SyncPolicy::default(),
+            values: PhantomData,
+        };
+
+        // An `add` that didn't get to commit
+        let pending = read_u64(&coll.map, PENDING_AT);
+        if pending != 0 {
+            coll.set_occupied(pending - 1, false);
+            write_u64(&mut coll.map, PENDING_AT, 0);
+        }
+
+        for index in 0..capacity {
+            if coll.is_occupied(index) {
+                coll.occupied += 1;
+            } else {
+                coll.free.push(Reverse(index));
+            }
+        }
+        coll
+    }
+
+    // Doubles the capacity. The bitmap grows in place, so the slots have to
+    // move further into the file. This isn't crash safe: if we're
+    // interrupted halfway the file has to be restored from a backup.
+    fn grow(&mut self) -> io::Result<()> {
+        let old = self.capacity;
+        let new = old * 2;
+        let old_slots = Self::slots_at(old);
+        let new_slots = Self::slots_at(new);
+
+        self.file.set_len(Self::file_len(new) as u64)?;
+        // Safety: same contract as `open`
+        self.map = unsafe { MmapMut::map_mut(&self.file)? };
+
+        let moved = old_slots..old_slots + old * mem::size_of::<T>();
+        self.map.copy_within(moved, new_slots);
+        // The new bitmap bytes overlap what used to be slots
+        self.map[HEADER_LEN + bitmap_len(old)..new_slots].fill(0);
+        write_u64(&mut self.map, CAPACITY_AT, new);
+
+        self.capacity = new;
+        self.free.extend((old..new).map(Reverse));
+        Ok(())
+    }
+
+    fn is_occupied(&self, index: usize) -> bool {
+        index < self.capacity && self.map[HEADER_LEN + index / 8] & (1 << (index % 8)) != 0
+    }
+
+    fn set_occupied(&mut self, index: usize, occupied: bool) {
+        let byte = &mut self.map[HEADER_LEN + index / 8];
+        if occupied {
+            *byte |= 1 << (index % 8);
+        } else {
+            *byte &= !(1 << (index % 8));
+        }
+    }
+
+    fn slot_range(&self, index: usize) -> Range<usize> {
+        let start = Self::slots_at(self.capacity) + index * mem::size_of::<T>();
+        start..start + mem::size_of::<T>()
+    }
+
+    // Slots start on a `HEADER_LEN` boundary so any value up to that
+    // alignment can be read in place
+    fn slots_at(capacity: usize) -> usize {
+        let end = HEADER_LEN + bitmap_len(capacity);
+        end.div_ceil(HEADER_LEN) * HEADER_LEN
+    }
+
+    fn file_len(capacity: usize) -> usize {
+        Self::slots_at(capacity) + capacity * mem::size_of::<T>()
+    }
+}
+
+impl<T> Drop for MappedCollection<T> {
+    fn drop(&mut self) {
+        if self.sync == SyncPolicy::OnFlush {
+            // Nowhere to report a failure, `flush` first to see it
+            let _ = self.map.flush();
+        }
+    }
+}
+
+fn bitmap_len(capacity: usize) -> usize {
+    capacity.div_ceil(8)
+}
+
+fn read_u64(map: &[u8], at: usize) -> usize {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&map[at..at + 8]);
+    u64::from_le_bytes(bytes) as usize
+}
+
+fn write_u64(map: &mut [u8], at: usize, value: usize) {
+    map[at..at + 8].copy_from_slice(&(value as u64).to_le_bytes());
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("slotvec-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn reopening_recovers_occupancy() {
+        let path = scratch("reopen");
+
+        let mut coll = unsafe { MappedCollection::<u64>::open(&path) }.unwrap();
+        for i in 0..3 {
+            coll.add(i * 10).unwrap();
+        }
+        coll.take(1);
+        coll.flush().unwrap();
+        drop(coll);
+
+        let mut coll = unsafe { MappedCollection::<u64>::open(&path) }.unwrap();
+        assert_eq!(coll.len(), 2);
+        assert_eq!(coll.iter().collect::<Vec<_>>(), vec![(0, &0), (2, &20)]);
+        assert_eq!(coll.add(7).unwrap(), 1);
+
+        // Opening with values of another size is refused
+        assert!(unsafe { MappedCollection::<u32>::open(&path) }.is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unfinished_adds_are_discarded_on_open() {
+        let path = scratch("two-phase");
+
+        let mut coll = unsafe { MappedCollection::<u64>::open(&path) }.unwrap();
+        coll.set_sync_policy(SyncPolicy::Always);
+        coll.add(1).unwrap();
+        coll.add(2).unwrap();
+        assert_eq!(read_u64(&coll.map, PENDING_AT), 0);
+
+        // Crash after the bit of slot 2 reached the disk, but not its value
+        write_u64(&mut coll.map, PENDING_AT, 3);
+        coll.set_occupied(2, true);
+        coll.flush().unwrap();
+        drop(coll);
+
+        let coll = unsafe { MappedCollection::<u64>::open(&path) }.unwrap();
+        assert_eq!(coll.iter().collect::<Vec<_>>(), vec![(0, &1), (1, &2)]);
+        assert_eq!(coll.sync_policy(), SyncPolicy::Os);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn growing_keeps_values_in_place() {
+        let path = scratch("grow");
+
+        let mut coll = unsafe { MappedCollection::<[u32; 3]>::open(&path) }.unwrap();
+        for i in 0..200 {
+            assert_eq!(coll.add([i, i + 1, i + 2]).unwrap(), i as usize);
+        }
+        assert_eq!(coll.capacity(), 256);
+        assert_eq!(coll.get(63), Some(&[63, 64, 65]));
+        assert_eq!(coll.take(150), Some([150, 151, 152]));
+        drop(coll);
+
+        let coll = unsafe { MappedCollection::<[u32; 3]>::open(&path) }.unwrap();
+        assert_eq!(coll.len(), 199);
+        assert_eq!(coll.get(0), Some(&[0, 1, 2]));
+        assert_eq!(coll.get(150), None);
+        assert_eq!(coll.get(199), Some(&[199, 200, 201]));
+        fs::remove_file(&path).unwrap();
+    }
+}
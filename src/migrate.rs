@@ -0,0 +1,82 @@
+//! Moving values between collections.
+//!
+//! The value gets a fresh slot in the destination, so indices and keys into
+//! the source have to be updated with what `migrate` returns.
+use allocator_api2::alloc::Allocator;
+
+use crate::{Collection, Key};
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Moves the value at `index` into `dst`, returning its index there.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range or vacant.
+    #[track_caller]
+    pub fn migrate<DstTag>(&mut self, index: usize, dst: &mut Collection<T, DstTag>) -> usize {
+        match self.try_migrate(index, dst) {
+            Some(index) => index,
+            None => self.vacant_panic(index, None),
+        }
+    }
+
+    /// Like `migrate`, but returns `None` instead of panicking when `index`
+    /// is out of range or vacant.
+    pub fn try_migrate<DstTag>(
+        &mut self,
+        index: usize,
+        dst: &mut Collection<T, DstTag>,
+    ) -> Option<usize> {
+        let item = self.try_take(index)?;
+        Some(dst.add(item))
+    }
+
+    /// Moves the value `key` refers to into `dst`, returning its key there.
+    /// `key` stops resolving in `self`, same as after `take_key`.
+    pub fn migrate_key<DstTag>(
+        &mut self,
+        key: Key<Tag>,
+        dst: &mut Collection<T, DstTag>,
+    ) -> Option<Key<DstTag>> {
+        let item = self.take_key(key)?;
+        Some(dst.add_key(item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_vacates_source() {
+        let mut src = Collection::new();
+        let mut dst = Collection::new();
+        dst.add("resident");
+        src.add("a");
+        src.add("b");
+
+        assert_eq!(src.migrate(1, &mut dst), 1);
+        assert_eq!(src.get(1), None);
+        assert_eq!(dst.get(1), Some(&"b"));
+        assert_eq!(src.try_migrate(1, &mut dst), None);
+        assert_eq!((src.len(), dst.len()), (1, 2));
+
+        // The vacancy is reused like any other
+        assert_eq!(src.add("c"), 1);
+    }
+
+    #[test]
+    fn migrate_key_invalidates_old_key() {
+        struct ZoneA;
+        struct ZoneB;
+
+        let mut a: Collection<u32, ZoneA> = Collection::new_tagged();
+        let mut b: Collection<u32, ZoneB> = Collection::new_tagged();
+
+        let old = a.add_key(7);
+        let new = a.migrate_key(old, &mut b).unwrap();
+        assert_eq!(b.get_key(new), Some(&7));
+        assert!(!a.contains_key(old));
+        assert_eq!(a.migrate_key(old, &mut b), None);
+    }
+}
@@ -0,0 +1,124 @@
+//! Slots holding several values each.
+//!
+//! `MultiSlot` maps every slot to a bag of values, e.g. the listeners
+//! subscribed to an event. Bags of up to four values are stored in the slot
+//! itself. A slot is vacated as soon as its bag is emptied, so an
+//! occupied slot always has at least one value.
+use smallvec::SmallVec;
+
+use crate::Collection;
+
+// How many values a bag holds before it moves to the heap
+const INLINE: usize = 4;
+
+type Bag<T> = SmallVec<[T; INLINE]>;
+
+pub struct MultiSlot<T> {
+    collection: Collection<Bag<T>>,
+}
+
+impl<T> MultiSlot<T> {
+    pub fn new() -> Self {
+        Self {
+            collection: Collection::new(),
+        }
+    }
+
+    /// Adds `value` to the bag at `index`, or to a new bag in a slot of its
+    /// own when `index` is `None`. Returns the index of the bag, or hands the
+    /// value back if the slot at `index` is vacant.
+    pub fn add_to(&mut self, index: Option<usize>, value: T) -> Result<usize, T> {
+        let index = match index {
+            Some(index) => index,
+            None => return Ok(self.collection.add(smallvec::smallvec![value])),
+        };
+
+        match self.collection.get_mut(index) {
+            Some(bag) => {
+                bag.push(value);
+                Ok(index)
+            }
+            None => Err(value),
+        }
+    }
+
+    /// Removes the first value equal to `value` from the bag at `index`,
+    /// vacating the slot if that was the last one.
+    pub fn remove_from(&mut self, index: usize, value: &T) -> Option<T>
+    where
+        T: PartialEq,
+    {
+        let bag = self.collection.get_mut(index)?;
+        let pos = bag.iter().position(|item| item == value)?;
+        let item = bag.remove(pos);
+        if bag.is_empty() {
+            self.collection.take(index);
+        }
+        Some(item)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[T]> {
+        self.collection.get(index).map(|bag| bag.as_slice())
+    }
+
+    /// Takes the whole bag at `index`.
+    pub fn take(&mut self, index: usize) -> Option<Vec<T>> {
+        self.collection.try_take(index).map(SmallVec::into_vec)
+    }
+
+    /// Number of bags, i.e. of occupied slots.
+    pub fn len(&self) -> usize {
+        self.collection.len() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.collection.is_empty()
+    }
+
+    /// Iterates `(index, value)` over every value of every bag, in slot
+    /// order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.collection
+            .enumerate()
+            .flat_map(|(index, bag)| bag.iter().map(move |item| (index, item)))
+    }
+
+    pub fn into_inner(self) -> Collection<SmallVec<[T; INLINE]>> {
+        self.collection
+    }
+}
+
+impl<T> Default for MultiSlot<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bags_vacate_their_slot_once_empty() {
+        let mut listeners = MultiSlot::new();
+        let click = listeners.add_to(None, "logger").unwrap();
+        let key = listeners.add_to(None, "hotkeys").unwrap();
+        assert_eq!(listeners.add_to(Some(click), "button"), Ok(click));
+        assert_eq!(listeners.get(click), Some(&["logger", "button"][..]));
+
+        assert_eq!(
+            listeners.iter().collect::<Vec<_>>(),
+            vec![(click, &"logger"), (click, &"button"), (key, &"hotkeys")]
+        );
+
+        assert_eq!(listeners.remove_from(click, &"logger"), Some("logger"));
+        assert_eq!(listeners.remove_from(click, &"logger"), None);
+        assert_eq!(listeners.remove_from(click, &"button"), Some("button"));
+        assert_eq!(listeners.get(click), None);
+        assert_eq!(listeners.len(), 1);
+
+        assert_eq!(listeners.add_to(Some(click), "late"), Err("late"));
+        assert_eq!(listeners.take(key), Some(vec!["hotkeys"]));
+        assert!(listeners.is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+//! A collection with optional names for its slots.
+//!
+//! `NamedCollection` keeps the name to index map and its inverse in step with
+//! the slots, so a name never outlives its value or points at a reused slot.
+use std::collections::HashMap;
+use std::ops::Deref;
+
+use crate::Collection;
+
+pub struct NamedCollection<T> {
+    collection: Collection<T>,
+    indices: HashMap<String, usize>,
+    names: HashMap<usize, String>,
+}
+
+impl<T> NamedCollection<T> {
+    pub fn new() -> Self {
+        Self {
+            collection: Collection::new(),
+            indices: HashMap::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// Adds a value without a name.
+    pub fn add(&mut self, item: T) -> usize {
+        self.collection.add(item)
+    }
+
+    /// Stores `item` under `name`. If the name is taken the value is
+    /// replaced in place and the old one returned along with the index.
+    pub fn insert_named(&mut self, name: impl Into<String>, item: T) -> (usize, Option<T>) {
+        let name = name.into();
+        if let Some(&index) = self.indices.get(&name) {
+            return (index, self.collection.insert_at(index, item));
+        }
+
+        let index = self.collection.add(item);
+        self.names.insert(index, name.clone());
+        self.indices.insert(name, index);
+        (index, None)
+    }
+
+    pub fn get_named(&self, name: &str) -> Option<&T> {
+        self.collection.get(*self.indices.get(name)?)
+    }
+
+    pub fn get_named_mut(&mut self, name: &str) -> Option<&mut T> {
+        self.collection.get_mut(*self.indices.get(name)?)
+    }
+
+    pub fn index_of_name(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
+
+    pub fn name_of(&self, index: usize) -> Option<&str> {
+        self.names.get(&index).map(String::as_str)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.collection.get_mut(index)
+    }
+
+    /// Takes the value at `index`, forgetting its name if it has one.
+    pub fn take(&mut self, index: usize) -> Option<T> {
+        let item = self.collection.try_take(index)?;
+        if let Some(name) = self.names.remove(&index) {
+            self.indices.remove(&name);
+        }
+        Some(item)
+    }
+
+    pub fn take_named(&mut self, name: &str) -> Option<T> {
+        let index = self.index_of_name(name)?;
+        self.take(index)
+    }
+
+    /// Iterates `(name, value)` over the named values, in no particular
+    /// order.
+    pub fn iter_named(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.indices
+            .iter()
+            .filter_map(move |(name, &index)| Some((name.as_str(), self.collection.get(index)?)))
+    }
+
+    pub fn into_inner(self) -> Collection<T> {
+        self.collection
+    }
+}
+
+impl<T> Default for NamedCollection<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// No `DerefMut`, taking a value through the collection would leave its name
+// pointing at a vacancy
+impl<T> Deref for NamedCollection<T> {
+    type Target = Collection<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.collection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_follow_their_values() {
+        let mut assets = NamedCollection::new();
+        let (player, _) = assets.insert_named("player", "player.png");
+        let unnamed = assets.add("particle.png");
+
+        assert_eq!(assets.get_named("player"), Some(&"player.png"));
+        assert_eq!(assets.name_of(player), Some("player"));
+        assert_eq!(assets.name_of(unnamed), None);
+
+        // Reinserting under the same name replaces in place
+        assert_eq!(
+            assets.insert_named("player", "player_hd.png"),
+            (player, Some("player.png"))
+        );
+        assert_eq!(assets.len(), 2);
+
+        assert_eq!(assets.take(player), Some("player_hd.png"));
+        assert_eq!(assets.get_named("player"), None);
+
+        // The slot gets reused, but not the name
+        let (enemy, _) = assets.insert_named("enemy", "enemy.png");
+        assert_eq!(enemy, player);
+        assert_eq!(assets.name_of(enemy), Some("enemy"));
+        assert_eq!(assets.index_of_name("player"), None);
+        assert_eq!(
+            assets.iter_named().collect::<Vec<_>>(),
+            vec![("enemy", &"enemy.png")]
+        );
+    }
+}
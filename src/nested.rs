@@ -0,0 +1,69 @@
+//! Two-level addressing for a collection of collections.
+//!
+//! A registry of pools, e.g. voices held per sound bank, is a
+//! `Collection<Collection<T>>` addressed by `(outer, inner)` pairs. These
+//! helpers resolve a pair in one call instead of chaining two lookups at
+//! every use.
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+impl<T, Tag, InnerTag, A: Allocator, InnerA: Allocator>
+    Collection<Collection<T, InnerTag, InnerA>, Tag, A>
+{
+    pub fn get_nested(&self, (outer, inner): (usize, usize)) -> Option<&T> {
+        self.get(outer)?.get(inner)
+    }
+
+    pub fn get_nested_mut(&mut self, (outer, inner): (usize, usize)) -> Option<&mut T> {
+        self.get_mut(outer)?.get_mut(inner)
+    }
+
+    /// Adds `item` to the inner collection at `outer`, or hands it back if
+    /// that slot is vacant.
+    pub fn add_nested(&mut self, outer: usize, item: T) -> Result<(usize, usize), T> {
+        match self.get_mut(outer) {
+            Some(pool) => Ok((outer, pool.add(item))),
+            None => Err(item),
+        }
+    }
+
+    /// Takes the value at `(outer, inner)`, leaving the inner collection in
+    /// place even if it's now empty.
+    pub fn take_nested(&mut self, (outer, inner): (usize, usize)) -> Option<T> {
+        self.get_mut(outer)?.try_take(inner)
+    }
+
+    /// Iterates `(outer, inner, value)` over every value in every inner
+    /// collection, in index order.
+    pub fn iter_nested(&self) -> impl Iterator<Item = (usize, usize, &T)> {
+        self.enumerate().flat_map(|(outer, pool)| {
+            pool.enumerate()
+                .map(move |(inner, item)| (outer, inner, item))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_values_by_pair() {
+        let mut banks = Collection::new();
+        let drums = banks.add(Collection::new());
+        let strings = banks.add(Collection::new());
+        let kick = banks.add_nested(drums, "kick").unwrap();
+        banks.add_nested(strings, "cello").unwrap();
+        banks.add_nested(strings, "viola").unwrap();
+
+        assert_eq!(banks.get_nested(kick), Some(&"kick"));
+        *banks.get_nested_mut((strings, 1)).unwrap() = "violin";
+        assert_eq!(banks.take_nested(kick), Some("kick"));
+        assert_eq!(banks.take_nested(kick), None);
+        assert_eq!(banks.add_nested(7, "snare"), Err("snare"));
+
+        let all: Vec<_> = banks.iter_nested().collect();
+        assert_eq!(all, vec![(1, 0, &"cello"), (1, 1, &"violin")]);
+    }
+}
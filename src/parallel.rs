@@ -0,0 +1,56 @@
+//! Building collections from rayon parallel iterators.
+//!
+//! The values are produced in parallel and then stored in bulk, so nothing
+//! goes through `add` one value at a time.
+use allocator_api2::alloc::Allocator;
+use rayon::iter::{FromParallelIterator, IntoParallelIterator, ParallelExtend, ParallelIterator};
+
+use crate::{raw, Collection};
+
+impl<T: Send, Tag, A: Allocator> ParallelExtend<T> for Collection<T, Tag, A> {
+    /// Fills the vacancies lowest first, like `add` would, and appends the
+    /// rest.
+    fn par_extend<I>(&mut self, items: I)
+    where
+        I: IntoParallelIterator<Item = T>,
+    {
+        let items: Vec<T> = items.into_par_iter().collect();
+        self.extend_filling_vacancies(items);
+    }
+}
+
+impl<T: Send, Tag> FromParallelIterator<T> for Collection<T, Tag> {
+    fn from_par_iter<I>(items: I) -> Self
+    where
+        I: IntoParallelIterator<Item = T>,
+    {
+        let mut coll = Self::new_tagged();
+        coll.inner = raw::from_vec(items.into_par_iter().map(Some).collect());
+        coll.occupied = coll.inner.len();
+        for index in 0..coll.inner.len() {
+            coll.note_filled(index);
+        }
+        coll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_and_extends_in_parallel() {
+        let mut coll: Collection<u64> = (0..10_000u64).into_par_iter().collect();
+        assert_eq!(coll.len(), 10_000);
+        assert_eq!(coll.get(1234), Some(&1234));
+
+        coll.take(3);
+        coll.take(1);
+        coll.par_extend(vec![100, 200, 300]);
+        assert_eq!(coll.get(1), Some(&100));
+        assert_eq!(coll.get(3), Some(&200));
+        assert_eq!(coll.get(10_000), Some(&300));
+        assert_eq!(coll.len(), 10_001);
+        assert_eq!(coll.add(7), 10_001);
+    }
+}
@@ -0,0 +1,266 @@
+//! Slot-by-slot deltas between two collections.
+//!
+//! A `Patch` only carries the slots that differ, so a replica can be brought
+//! up to date without sending the whole collection. It also records the slot
+//! count and `content_hash` of the collection it was diffed from, and `apply`
+//! refuses it anywhere else. Patches usually arrive over the network, so
+//! `apply` also rejects slot counts from `MAX_PATCH_SLOTS` up before
+//! allocating anything.
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+
+use allocator_api2::alloc::Allocator;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::Collection;
+
+/// The number of slots `apply` grows a collection to at most.
+pub const MAX_PATCH_SLOTS: usize = 1 << 24;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PatchOp<T> {
+    Added(T),
+    Removed,
+    Changed(T),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Patch<T> {
+    base_slots: usize,
+    base_hash: u64,
+    slots: usize,
+    ops: Vec<(usize, PatchOp<T>)>,
+}
+
+/// Why `apply` rejected a patch. The collection is left as it was.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    /// The patch asks for `MAX_PATCH_SLOTS` slots or more.
+    TooManySlots { slots: usize },
+    /// The collection isn't the one the patch was diffed from.
+    WrongBase,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::TooManySlots { slots } => write!(
+                f,
+                "patch asks for {} slots, above the limit of {}",
+                slots, MAX_PATCH_SLOTS
+            ),
+            PatchError::WrongBase => {
+                write!(f, "patch was diffed from a different collection")
+            }
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+impl<T> Patch<T> {
+    /// The `content_hash` of the collection the patch was diffed from.
+    pub fn base_hash(&self) -> u64 {
+        self.base_hash
+    }
+
+    /// Number of slots (occupied or not) the patched collection ends up with.
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    pub fn ops(&self) -> &[(usize, PatchOp<T>)] {
+        &self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl<T: Clone + PartialEq + Hash, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Records what has to change to turn `self` into `other`.
+    pub fn diff<B: Allocator>(&self, other: &Collection<T, Tag, B>) -> Patch<T> {
+        let mut ops = Vec::new();
+
+        for index in 0..self.inner.len().max(other.inner.len()) {
+            let before = self.inner.get(index).and_then(Option::as_ref);
+            let after = other.inner.get(index).and_then(Option::as_ref);
+
+            let op = match (before, after) {
+                (None, Some(value)) => PatchOp::Added(value.clone()),
+                (Some(_), None) => PatchOp::Removed,
+                (Some(old), Some(new)) if old != new => PatchOp::Changed(new.clone()),
+                _ => continue,
+            };
+
+            ops.push((index, op));
+        }
+
+        Patch {
+            base_slots: self.inner.len(),
+            base_hash: self.content_hash(),
+            slots: other.inner.len(),
+            ops,
+        }
+    }
+}
+
+impl<T: Hash, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Applies a patch produced by `diff`, if this collection has the slot
+    /// count and `content_hash` of the one it was diffed from. Takes O(slots)
+    /// for the hash.
+    pub fn apply(&mut self, patch: Patch<T>) -> Result<(), PatchError> {
+        if patch.slots >= MAX_PATCH_SLOTS {
+            return Err(PatchError::TooManySlots { slots: patch.slots });
+        }
+        if self.inner.len() != patch.base_slots || self.content_hash() != patch.base_hash {
+            return Err(PatchError::WrongBase);
+        }
+
+        if self.inner.len() < patch.slots {
+            self.before_growth(patch.slots - self.inner.len());
+            let start = self.inner.len();
+            self.inner.resize_with(patch.slots, || None);
+            for index in start..patch.slots {
+                if self.is_reusable(index) {
+                    self.free.push_back(index);
+                }
+            }
+        } else {
+            self.truncate(patch.slots);
+        }
+
+        for (index, op) in patch.ops {
+            // A patch received over the network might be malformed, ignore
+            // anything outside the slots it claims to cover
+            let Some(slot) = self.inner.get_mut(index) else {
+                continue;
+            };
+
+            match op {
+                PatchOp::Added(value) | PatchOp::Changed(value) => {
+                    if slot.replace(value).is_some() {
+                        self.stats.vacated(index);
+                        self.leaks.vacated(index);
+                        self.occupancy.vacated(index);
+                        self.pending_removals.vacated(index);
+                        self.bitmap.vacated(index);
+                        self.structure_changes += 1;
+                    } else {
+                        self.free.remove(index);
+                        self.occupied += 1;
+                    }
+                    self.note_filled(index);
+                }
+                PatchOp::Removed => {
+                    self.vacate_in_place(index);
+                }
+            }
+        }
+        self.debug_assert_consistent();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_then_apply_reproduces_target() {
+        let mut source = Collection::new();
+        source.add(1);
+        source.add(2);
+        source.add(3);
+
+        let mut target = Collection::new();
+        target.add(1);
+        target.add(5);
+        target.add(3);
+        target.add(4);
+        target.take(2);
+
+        let patch = source.diff(&target);
+        assert_eq!(
+            patch.ops(),
+            &[
+                (1, PatchOp::Changed(5)),
+                (2, PatchOp::Removed),
+                (3, PatchOp::Added(4)),
+            ]
+        );
+
+        source.apply(patch).unwrap();
+        assert_eq!(source.inner, target.inner);
+        assert_eq!(source.len(), 3);
+
+        // The vacancy left by the patch must be reusable
+        assert_eq!(source.add(9), 2);
+    }
+
+    #[test]
+    fn patch_shrinks_to_target_slots() {
+        let mut source = Collection::new();
+        source.add(1);
+        source.add(2);
+
+        let mut target = Collection::new();
+        target.add(1);
+
+        let patch = source.diff(&target);
+        assert_eq!(patch.slots(), 1);
+
+        source.apply(patch).unwrap();
+        assert_eq!(*source.inner, [Some(1)]);
+        assert!(target.diff(&source).is_empty());
+    }
+
+    #[test]
+    fn shrinking_goes_through_the_bookkeeping() {
+        let mut source = Collection::new();
+        source.add(1);
+        let key = source.add_key(2);
+        source.add_to_group(key.index(), 7);
+
+        let mut target = Collection::new();
+        target.add(1);
+
+        source.apply(source.diff(&target)).unwrap();
+        assert_eq!(source.iter_group(7).count(), 0);
+        assert_eq!(source.add(3), 1);
+        assert_eq!(source.get_key(key), None);
+    }
+
+    #[test]
+    fn rejects_oversized_patches_and_other_bases() {
+        let mut source = Collection::new();
+        source.add(1);
+        let mut target = Collection::new();
+        target.add(2);
+        let patch = source.diff(&target);
+
+        let mut oversized = patch.clone();
+        oversized.slots = usize::MAX;
+        assert_eq!(
+            source.apply(oversized),
+            Err(PatchError::TooManySlots { slots: usize::MAX })
+        );
+
+        let mut other = Collection::new();
+        other.add(3);
+        assert_eq!(other.apply(patch.clone()), Err(PatchError::WrongBase));
+        assert_eq!(*other.inner, [Some(3)]);
+
+        source.apply(patch.clone()).unwrap();
+        assert_eq!(source.apply(patch), Err(PatchError::WrongBase));
+    }
+}
@@ -0,0 +1,180 @@
+//! A pool of reusable values, e.g. connections or buffers.
+//!
+//! `SlotPool` keeps its values in a `ConcurrentCollection` and the slots
+//! that aren't checked out in a queue. `checkout` pops a slot off the queue
+//! under a lock before handing it out, so two threads never get the same
+//! value, and the `PooledItem` it returns puts the slot back when dropped.
+use std::collections::VecDeque;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::{ConcurrentCollection, SlotGuard};
+
+pub struct SlotPool<T> {
+    values: ConcurrentCollection<T>,
+    idle: Mutex<VecDeque<usize>>,
+}
+
+impl<T> SlotPool<T> {
+    pub fn new() -> Self {
+        Self::from_collection(ConcurrentCollection::new())
+    }
+
+    /// Pools the values stored in `values` from here on. Values already in
+    /// it are never checked out.
+    pub fn from_collection(values: ConcurrentCollection<T>) -> Self {
+        Self {
+            values,
+            idle: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Adds `item` to the pool, ready to be checked out.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the underlying collection is at capacity.
+    pub fn put(&self, item: T) -> usize {
+        let index = self.values.add(item);
+        self.idle().push_back(index);
+        index
+    }
+
+    /// Hands out the value that has been idle the longest, or `None` if
+    /// every value is checked out.
+    pub fn checkout(&self) -> Option<PooledItem<'_, T>> {
+        let index = self.idle().pop_front()?;
+        // Only checked out slots are off the queue, and only their
+        // `PooledItem` can take the value
+        let guard = self.values.lock(index).unwrap();
+        Some(PooledItem {
+            pool: self,
+            index,
+            guard: Some(guard),
+        })
+    }
+
+    /// Values waiting to be checked out.
+    pub fn idle_len(&self) -> usize {
+        self.idle().len()
+    }
+
+    /// Values in the pool, checked out or not.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn idle(&self) -> MutexGuard<'_, VecDeque<usize>> {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> Default for SlotPool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A value checked out of a `SlotPool`. Goes back to the pool when dropped.
+pub struct PooledItem<'a, T> {
+    pool: &'a SlotPool<T>,
+    index: usize,
+    // Only `None` while being dropped or detached
+    guard: Option<SlotGuard<'a, T>>,
+}
+
+impl<'a, T> PooledItem<'a, T> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Takes the value out of the pool for good, vacating its slot.
+    pub fn detach(mut self) -> T {
+        self.guard = None;
+        let item = self.pool.values.take(self.index).unwrap();
+        // Don't requeue the slot in `drop`
+        mem::forget(self);
+        item
+    }
+}
+
+impl<T> Deref for PooledItem<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for PooledItem<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for PooledItem<'_, T> {
+    fn drop(&mut self) {
+        // Unlock before requeueing, so the next checkout doesn't block on us
+        self.guard = None;
+        self.pool.idle().push_back(self.index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn checkouts_return_to_the_pool() {
+        let pool = SlotPool::new();
+        pool.put(Vec::<u8>::new());
+        pool.put(Vec::new());
+
+        let mut a = pool.checkout().unwrap();
+        let b = pool.checkout().unwrap();
+        assert!(pool.checkout().is_none());
+        a.push(1);
+        drop(a);
+
+        let a = pool.checkout().unwrap();
+        assert_eq!(*a, vec![1]);
+        assert_eq!(b.detach(), Vec::<u8>::new());
+        assert_eq!((pool.len(), pool.idle_len()), (1, 0));
+        drop(a);
+        assert_eq!(pool.idle_len(), 1);
+    }
+
+    #[test]
+    fn threads_never_lose_a_checkout() {
+        let pool = SlotPool::new();
+        for _ in 0..4 {
+            pool.put(0usize);
+        }
+        let checkouts = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        let held: Vec<_> = (0..2).filter_map(|_| pool.checkout()).collect();
+                        checkouts.fetch_add(held.len(), Ordering::Relaxed);
+                        for mut item in held {
+                            *item += 1;
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(pool.idle_len(), 4);
+        let total: usize = (0..4).map(|i| *pool.values.get(i).unwrap()).sum();
+        assert_eq!(total, checkouts.into_inner());
+    }
+}
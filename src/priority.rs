@@ -0,0 +1,148 @@
+//! Values ranked by a priority.
+//!
+//! `PriorityCollection` pairs the slots with a binary heap, so a value can
+//! be found by index in O(1), e.g. to cancel a task by its handle, and the
+//! highest ranked one popped in O(log n). For earliest-deadline-first use
+//! `Reverse(deadline)` as the priority.
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::Deref;
+
+use crate::{Collection, Key};
+
+pub struct PriorityCollection<T, P, Tag = ()> {
+    collection: Collection<T, Tag>,
+    // Highest priority first, ties going to the lowest key. Entries whose key
+    // went stale, or whose slot got a new priority since, are skipped when
+    // they come up.
+    heap: BinaryHeap<(P, Reverse<Key<Tag>>)>,
+    priorities: Vec<Option<P>>,
+}
+
+impl<T, P: Ord + Clone> PriorityCollection<T, P> {
+    pub fn new() -> Self {
+        Self::new_tagged()
+    }
+}
+
+impl<T, P: Ord + Clone, Tag> PriorityCollection<T, P, Tag> {
+    pub fn new_tagged() -> Self {
+        Self {
+            collection: Collection::new_tagged(),
+            heap: BinaryHeap::new(),
+            priorities: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, item: T, priority: P) -> usize {
+        let index = self.collection.add(item);
+        self.set_priority(index, priority);
+        index
+    }
+
+    /// Re-ranks the value at `index`. Returns `false` if the slot is vacant.
+    pub fn set_priority(&mut self, index: usize, priority: P) -> bool {
+        let key = match self.collection.key_for_index(index) {
+            Some(key) => key,
+            None => return false,
+        };
+
+        if self.priorities.len() <= index {
+            self.priorities.resize(index + 1, None);
+        }
+        self.priorities[index] = Some(priority.clone());
+        self.heap.push((priority, Reverse(key)));
+        true
+    }
+
+    pub fn priority(&self, index: usize) -> Option<&P> {
+        self.collection.get(index)?;
+        self.priorities.get(index)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.collection.get_mut(index)
+    }
+
+    pub fn take(&mut self, index: usize) -> Option<T> {
+        let item = self.collection.try_take(index)?;
+        self.priorities[index] = None;
+        Some(item)
+    }
+
+    /// The highest ranked value. Takes `&mut self` to drop the entries
+    /// `take` and `set_priority` left behind on the way.
+    pub fn peek_highest(&mut self) -> Option<(usize, &T)> {
+        let index = self.highest()?;
+        Some((index, self.collection.get(index)?))
+    }
+
+    /// Takes the highest ranked value.
+    pub fn pop_highest(&mut self) -> Option<(usize, T)> {
+        let index = self.highest()?;
+        self.heap.pop();
+        Some((index, self.take(index)?))
+    }
+
+    pub fn into_inner(self) -> Collection<T, Tag> {
+        self.collection
+    }
+
+    // Drops outdated entries until the top of the heap is current
+    fn highest(&mut self) -> Option<usize> {
+        loop {
+            let (priority, Reverse(key)) = self.heap.peek()?;
+            let index = key.index();
+            if self.collection.contains_key(*key)
+                && self.priorities[index]
+                    .as_ref()
+                    .is_some_and(|current| current == priority)
+            {
+                return Some(index);
+            }
+            self.heap.pop();
+        }
+    }
+}
+
+impl<T, P: Ord + Clone> Default for PriorityCollection<T, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// No `DerefMut`, values added through it would have no priority
+impl<T, P, Tag> Deref for PriorityCollection<T, P, Tag> {
+    type Target = Collection<T, Tag>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.collection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pops_by_priority_and_skips_cancelled_tasks() {
+        let mut tasks = PriorityCollection::new();
+        let backup = tasks.add("backup", Reverse(30));
+        let email = tasks.add("email", Reverse(10));
+        let report = tasks.add("report", Reverse(20));
+
+        assert_eq!(tasks.peek_highest(), Some((email, &"email")));
+        tasks.take(email);
+        tasks.set_priority(backup, Reverse(5));
+        assert_eq!(tasks.priority(backup), Some(&Reverse(5)));
+
+        assert_eq!(tasks.pop_highest(), Some((backup, "backup")));
+        // A reused slot doesn't inherit the old priority
+        let audit = tasks.add("audit", Reverse(25));
+        assert_eq!(audit, backup);
+        assert_eq!(tasks.pop_highest(), Some((report, "report")));
+        assert_eq!(tasks.pop_highest(), Some((audit, "audit")));
+        assert_eq!(tasks.pop_highest(), None);
+        assert!(tasks.is_empty());
+    }
+}
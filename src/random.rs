@@ -0,0 +1,79 @@
+//! Uniform sampling of occupied slots.
+use allocator_api2::alloc::Allocator;
+use rand::seq::IteratorRandom;
+use rand::Rng;
+
+use crate::Collection;
+
+// How many random slots `random_occupied` probes before falling back to a
+// scan. A collection that's at least a quarter full almost never needs it.
+const PROBES: usize = 16;
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Picks one of the occupied slots, each with the same probability.
+    ///
+    /// Random slots are probed until an occupied one turns up, which takes
+    /// O(1) tries unless most of the collection is holes, in which case it
+    /// falls back to picking from a scan.
+    pub fn random_occupied<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<(usize, &T)> {
+        if self.inner.is_empty() {
+            return None;
+        }
+
+        for _ in 0..PROBES {
+            let index = rng.gen_range(0..self.inner.len());
+            if let Some(item) = &self.inner[index] {
+                return Some((index, item));
+            }
+        }
+
+        self.enumerate().choose(rng)
+    }
+
+    /// Picks `n` distinct occupied slots, or all of them if there are fewer,
+    /// each set with the same probability. The order of the result is not
+    /// random. Takes one pass over the slots.
+    pub fn sample_occupied<R: Rng + ?Sized>(&self, n: usize, rng: &mut R) -> Vec<(usize, &T)> {
+        self.enumerate().choose_multiple(rng, n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn sampling_is_uniform_over_occupied_slots() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut coll = Collection::new();
+        for i in 0..100 {
+            coll.add(i);
+        }
+        // Leave slots 0, 50 and 99, mostly holes so the fallback is hit too
+        for i in 1..99 {
+            if i != 50 {
+                coll.take(i);
+            }
+        }
+
+        let mut hits = [0; 100];
+        for _ in 0..3000 {
+            let (index, value) = coll.random_occupied(&mut rng).unwrap();
+            assert_eq!(index, *value);
+            hits[index] += 1;
+        }
+        for index in [0, 50, 99] {
+            assert!((800..1200).contains(&hits[index]), "{:?}", hits[index]);
+        }
+
+        let mut sample = coll.sample_occupied(2, &mut rng);
+        sample.sort();
+        sample.dedup();
+        assert_eq!(sample.len(), 2);
+        assert_eq!(coll.sample_occupied(5, &mut rng).len(), 3);
+
+        assert_eq!(Collection::<u8>::new().random_occupied(&mut rng), None);
+    }
+}
@@ -0,0 +1,226 @@
+//! Counting the occupied slots in a range of indices.
+//!
+//! With the `range-count` feature the collection keeps a Fenwick tree over
+//! occupancy, a word per slot, so `count_occupied_in` takes O(log slots).
+//! Without it `OccupancyCounts` is empty and `count_occupied_in` scans the
+//! range instead.
+use std::ops::{Bound, RangeBounds};
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+#[cfg(feature = "range-count")]
+#[derive(Debug, Default)]
+pub(crate) struct OccupancyCounts {
+    occupied: Vec<bool>,
+    // 1-based, `tree[i]` counts the occupied slots among the
+    // `i & i.wrapping_neg()` ending at slot `i - 1`
+    tree: Vec<usize>,
+}
+
+#[cfg(not(feature = "range-count"))]
+#[derive(Debug, Default)]
+pub(crate) struct OccupancyCounts;
+
+#[cfg(feature = "range-count")]
+impl OccupancyCounts {
+    pub(crate) const fn new() -> Self {
+        Self {
+            occupied: Vec::new(),
+            tree: Vec::new(),
+        }
+    }
+
+    pub(crate) fn filled(&mut self, index: usize) {
+        self.set(index, true);
+    }
+
+    pub(crate) fn vacated(&mut self, index: usize) {
+        self.set(index, false);
+    }
+
+    pub(crate) fn moved(&mut self, old: usize, new: usize) {
+        self.set(old, false);
+        self.set(new, true);
+    }
+
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        let (at_a, at_b) = (self.is_occupied(a), self.is_occupied(b));
+        self.set(a, at_b);
+        self.set(b, at_a);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    pub(crate) fn rebuild<T>(&mut self, slots: &[Option<T>]) {
+        self.clear();
+        for (index, slot) in slots.iter().enumerate() {
+            self.set(index, slot.is_some());
+        }
+    }
+
+    pub(crate) fn check<T>(&self, slots: &[Option<T>]) -> Result<(), String> {
+        let counted = self.prefix(self.occupied.len());
+        match (0..slots.len()).find(|&index| slots[index].is_some() != self.is_occupied(index)) {
+            Some(index) => Err(format!(
+                "{} has the wrong occupancy in the range counts",
+                index
+            )),
+            None if counted != slots.iter().flatten().count() => Err(format!(
+                "the range counts add up to {} occupied slots",
+                counted
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn count(&self, start: usize, end: usize) -> Option<usize> {
+        let end = end.min(self.occupied.len());
+        Some(self.prefix(end) - self.prefix(start.min(end)))
+    }
+
+    fn is_occupied(&self, index: usize) -> bool {
+        self.occupied.get(index).copied().unwrap_or(false)
+    }
+
+    fn set(&mut self, index: usize, occupied: bool) {
+        if self.is_occupied(index) == occupied {
+            return;
+        }
+        self.grow(index + 1);
+        self.occupied[index] = occupied;
+
+        let mut i = index + 1;
+        while i < self.tree.len() {
+            if occupied {
+                self.tree[i] += 1;
+            } else {
+                self.tree[i] -= 1;
+            }
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    // Every new node covers slots that are either vacant or already counted
+    // by the nodes below it, so it can be summed from those
+    fn grow(&mut self, slots: usize) {
+        if self.tree.is_empty() {
+            self.tree.push(0);
+        }
+        while self.occupied.len() < slots {
+            self.occupied.push(false);
+            let i = self.occupied.len();
+            let node = self.prefix(i - 1) - self.prefix(i - (i & i.wrapping_neg()));
+            self.tree.push(node);
+        }
+    }
+
+    // Occupied slots among the first `len`
+    fn prefix(&self, mut len: usize) -> usize {
+        let mut sum = 0;
+        while len > 0 {
+            sum += self.tree[len];
+            len -= len & len.wrapping_neg();
+        }
+        sum
+    }
+}
+
+#[cfg(not(feature = "range-count"))]
+impl OccupancyCounts {
+    pub(crate) const fn new() -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    pub(crate) fn filled(&mut self, _index: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn vacated(&mut self, _index: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn moved(&mut self, _old: usize, _new: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn swap(&mut self, _a: usize, _b: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn clear(&mut self) {}
+
+    #[inline(always)]
+    pub(crate) fn rebuild<T>(&mut self, _slots: &[Option<T>]) {}
+
+    #[inline(always)]
+    pub(crate) fn check<T>(&self, _slots: &[Option<T>]) -> Result<(), String> {
+        Ok(())
+    }
+
+    // Nothing to count with, the caller scans instead
+    #[inline(always)]
+    fn count(&self, _start: usize, _end: usize) -> Option<usize> {
+        None
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Number of occupied slots with an index in `range`, e.g. to tell how
+    /// many values come before a page boundary.
+    pub fn count_occupied_in(&self, range: impl RangeBounds<usize>) -> usize {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.saturating_add(1),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.inner.len(),
+        };
+        if start >= end {
+            return 0;
+        }
+
+        self.occupancy.count(start, end).unwrap_or_else(|| {
+            self.inner
+                .get(start..end.min(self.inner.len()))
+                .map_or(0, |slots| slots.iter().flatten().count())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_follow_takes_moves_and_reuse() {
+        let mut coll = Collection::new();
+        for i in 0..20 {
+            coll.add(i);
+        }
+        for i in (0..20).step_by(3) {
+            coll.take(i);
+        }
+
+        assert_eq!(coll.count_occupied_in(..), 13);
+        assert_eq!(coll.count_occupied_in(0..10), 6);
+        assert_eq!(coll.count_occupied_in(3..=6), 2);
+        assert_eq!(coll.count_occupied_in(15..99), 3);
+        assert_eq!(coll.count_occupied_in(99..), 0);
+
+        coll.compact(|_, _| ());
+        assert_eq!(coll.count_occupied_in(..13), 13);
+        assert_eq!(coll.count_occupied_in(13..), 0);
+
+        coll.insert_at(30, 30);
+        coll.inner[0] = None;
+        coll.resync();
+        assert_eq!(coll.count_occupied_in(..), 13);
+        assert_eq!(coll.count_occupied_in(..1), 0);
+        coll.clear();
+        assert_eq!(coll.count_occupied_in(..), 0);
+    }
+}
@@ -0,0 +1,107 @@
+//! Building a collection from its slots and taking it apart again.
+//!
+//! With the `strict` feature `IndexMut` is gone, so these are the only ways
+//! to touch the slots without going through the bookkeeping, and they
+//! rebuild it as part of the handover.
+use std::mem::{self, ManuallyDrop};
+
+use allocator_api2::alloc::Allocator;
+
+use crate::{Collection, Slots};
+
+impl<T, Tag> Collection<T, Tag> {
+    /// Takes over `slots`, with every `Some` slot counting as occupied and
+    /// every `None` one as vacant. Generations start over at 0.
+    ///
+    /// # Safety
+    ///
+    /// `occupied` must be the number of `Some` slots. The collection counts
+    /// them itself either way, so `len` stays right, and debug builds check
+    /// the two agree.
+    pub unsafe fn from_raw_parts(slots: Vec<Option<T>>, occupied: usize) -> Self {
+        let coll = Self::from_slots(slots);
+        coll.debug_assert_consistent();
+        debug_assert_eq!(
+            occupied, coll.occupied,
+            "from_raw_parts got a wrong occupied count"
+        );
+        coll
+    }
+
+    /// The slots and the number of occupied ones, which can be handed back
+    /// to `from_raw_parts` as they are. Generations, groups and everything
+    /// else tracked per slot are dropped.
+    pub fn into_raw_parts(mut self) -> (Vec<Option<T>>, usize) {
+        let occupied = self.occupied;
+        let slots = mem::take(&mut self.inner);
+        self.clear();
+        (into_vec(slots), occupied)
+    }
+
+    pub(crate) fn from_slots(inner: Vec<Option<T>>) -> Self {
+        Self::from_slots_in(from_vec(inner))
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    // `from_slots` for any allocator
+    pub(crate) fn from_slots_in(inner: Slots<T, A>) -> Self {
+        let mut coll = Self::with_slots(inner);
+        coll.resync();
+        for index in 0..coll.inner.len() {
+            if coll.inner[index].is_some() {
+                coll.note_filled(index);
+            }
+        }
+        coll
+    }
+}
+
+// The slot storage of a collection in the global allocator and a `Vec` get
+// their buffers from the same place, so one can take over the other's
+pub(crate) fn from_vec<T>(slots: Vec<Option<T>>) -> Slots<T> {
+    let mut slots = ManuallyDrop::new(slots);
+    // SAFETY: the buffer was allocated by the global allocator with the
+    // layout `Slots` expects for this length and capacity
+    unsafe { Slots::from_raw_parts(slots.as_mut_ptr(), slots.len(), slots.capacity()) }
+}
+
+pub(crate) fn into_vec<T>(slots: Slots<T>) -> Vec<Option<T>> {
+    let (ptr, len, capacity) = slots.into_raw_parts();
+    // SAFETY: same as in `from_vec`, the other way around
+    unsafe { Vec::from_raw_parts(ptr, len, capacity) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_parts_round_trip() {
+        let mut coll = Collection::new();
+        coll.add('a');
+        coll.add('b');
+        coll.add('c');
+        coll.take(1);
+
+        let (mut slots, occupied) = coll.into_raw_parts();
+        assert_eq!(
+            (slots.as_slice(), occupied),
+            (&[Some('a'), None, Some('c')][..], 2)
+        );
+
+        slots[1] = Some('x');
+        let mut coll: Collection<char> = unsafe { Collection::from_raw_parts(slots, 3) };
+        assert_eq!(coll.len(), 3);
+        assert_eq!(coll.take(1), 'x');
+        assert_eq!(coll.add('y'), 1);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "wrong occupied count"))]
+    fn from_raw_parts_goes_by_its_own_count() {
+        let slots = vec![Some('a'), None];
+        let coll: Collection<char> = unsafe { Collection::from_raw_parts(slots, 2) };
+        assert_eq!(coll.len(), 1);
+    }
+}
@@ -0,0 +1,193 @@
+//! Moving values to slots chosen by the caller.
+//!
+//! `reindex` applies a remap table such as the `(old, new)` pairs another
+//! replica's `compact` reported, so both end up with the same layout. The
+//! whole table is validated before anything moves.
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+/// Why a remap table was rejected. `pair` is the position of the offending
+/// `(old, new)` pair within the table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReindexError {
+    /// The slot to move from is vacant or out of range.
+    VacantSource { pair: usize, index: usize },
+    /// The slot to move from is listed twice.
+    DuplicateSource { pair: usize, index: usize },
+    /// The slot to move to is listed twice.
+    DuplicateTarget { pair: usize, index: usize },
+    /// The slot to move to holds a value that isn't moving away.
+    TargetOccupied { pair: usize, index: usize },
+}
+
+impl fmt::Display for ReindexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReindexError::VacantSource { pair, index } => {
+                write!(f, "pair {}: slot {} is vacant", pair, index)
+            }
+            ReindexError::DuplicateSource { pair, index } => {
+                write!(f, "pair {}: slot {} is moved twice", pair, index)
+            }
+            ReindexError::DuplicateTarget { pair, index } => {
+                write!(f, "pair {}: slot {} is filled twice", pair, index)
+            }
+            ReindexError::TargetOccupied { pair, index } => {
+                write!(f, "pair {}: slot {} is occupied", pair, index)
+            }
+        }
+    }
+}
+
+impl Error for ReindexError {}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Moves the value at `old` to `new` for every `(old, new)` in `remap`,
+    /// growing the collection if a target is past the end. Values that
+    /// aren't listed stay where they are. Keys to moved values go stale,
+    /// same as with `compact`.
+    pub fn reindex(&mut self, remap: &[(usize, usize)]) -> Result<(), ReindexError> {
+        let sources: BTreeSet<usize> = remap.iter().map(|&(old, _)| old).collect();
+        let mut targets = BTreeSet::new();
+        let mut dest = BTreeMap::new();
+        for (pair, &(old, new)) in remap.iter().enumerate() {
+            if self.get(old).is_none() {
+                return Err(ReindexError::VacantSource { pair, index: old });
+            }
+            if dest.insert(old, new).is_some() {
+                return Err(ReindexError::DuplicateSource { pair, index: old });
+            }
+            if !targets.insert(new) {
+                return Err(ReindexError::DuplicateTarget { pair, index: new });
+            }
+            if self.get(new).is_some() && !sources.contains(&new) {
+                return Err(ReindexError::TargetOccupied { pair, index: new });
+            }
+        }
+
+        let end = targets.iter().next_back().map_or(0, |last| last + 1);
+        if end > self.inner.len() {
+            self.before_growth(end - self.inner.len());
+            let start = self.inner.len();
+            self.inner.resize_with(end, || None);
+            // Retired slots past the end stay retired
+            for index in start..end {
+                if self.is_reusable(index) {
+                    self.free.push_back(index);
+                }
+            }
+        }
+
+        // Follow every chain of moves from its first pair, each swap puts one
+        // value in its final slot and brings the next one in the chain, or a
+        // vacancy, to `start`
+        for &(start, _) in remap {
+            while let Some(new) = dest.remove(&start) {
+                if new == start {
+                    break;
+                }
+                self.swap_slots(start, new);
+                if let Some(next) = dest.remove(&new) {
+                    dest.insert(start, next);
+                }
+            }
+        }
+
+        for &old in &sources {
+            self.generations.bump(old);
+        }
+        self.free.retain(|index| !targets.contains(index));
+        for &old in sources.difference(&targets) {
+            if self.is_reusable(old) {
+                self.free.push_back(old);
+            }
+        }
+
+        self.debug_assert_consistent();
+        Ok(())
+    }
+
+    fn swap_slots(&mut self, a: usize, b: usize) {
+        self.inner.swap(a, b);
+        self.inserted.swap(a, b);
+        self.stats.swap(a, b);
+        self.leaks.swap(a, b);
+        self.occupancy.swap(a, b);
+        self.pending_removals.swap(a, b);
+        self.bitmap.swap(a, b);
+        self.structure_changes += 1;
+
+        let group_a = self.groups.unlink(a);
+        if let Some(group) = self.groups.unlink(b) {
+            self.groups.link(a, group);
+        }
+        if let Some(group) = group_a {
+            self.groups.link(b, group);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replicas_end_up_with_the_same_layout() {
+        let mut primary = Collection::new();
+        let mut replica = Collection::new();
+        for c in "abcdef".chars() {
+            primary.add(c);
+            replica.add(c);
+        }
+        for index in [0, 2] {
+            primary.take(index);
+            replica.take(index);
+        }
+
+        let mut moves = Vec::new();
+        primary.compact(|old, new| moves.push((old, new)));
+        let stale = replica.key_for_index(5).unwrap();
+        replica.reindex(&moves).unwrap();
+
+        assert_eq!(replica.to_owned_values(), primary.to_owned_values());
+        assert_eq!(replica.get_key(stale), None);
+        assert_eq!(replica.add('g'), primary.add('g'));
+
+        // Cycles and growth, `c` moving out of the way for `a`
+        let mut coll = Collection::new();
+        coll.add('a');
+        coll.add('b');
+        coll.add('c');
+        coll.reindex(&[(0, 2), (2, 4), (1, 0)]).unwrap();
+        assert_eq!(*coll.inner, [Some('b'), None, Some('a'), None, Some('c')]);
+        assert_eq!(coll.vacant_len(), 2);
+    }
+
+    #[test]
+    fn conflicting_tables_change_nothing() {
+        let mut coll = Collection::new();
+        coll.add('a');
+        coll.add('b');
+        coll.add('c');
+        coll.take(1);
+
+        assert_eq!(
+            coll.reindex(&[(0, 1), (2, 1)]),
+            Err(ReindexError::DuplicateTarget { pair: 1, index: 1 })
+        );
+        assert_eq!(
+            coll.reindex(&[(0, 2)]),
+            Err(ReindexError::TargetOccupied { pair: 0, index: 2 })
+        );
+        assert_eq!(
+            coll.reindex(&[(1, 3)]),
+            Err(ReindexError::VacantSource { pair: 0, index: 1 })
+        );
+        assert_eq!(*coll.inner, [Some('a'), None, Some('c')]);
+    }
+}
@@ -0,0 +1,107 @@
+//! Occupancy as runs of occupied and vacant slots, for syncing replicas.
+//!
+//! Sparse or dense registries compress to a handful of runs, which is a lot
+//! less to send than a bitmap of every slot. A replica applies the runs it
+//! was sent to learn which ranges it's missing values for and asks for
+//! just those.
+use std::ops::Range;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// `(occupied, length)` for every run of slots with the same occupancy,
+    /// in index order. Runs longer than `u32::MAX` are split.
+    pub fn occupancy_rle(&self) -> Vec<(bool, u32)> {
+        let mut runs: Vec<(bool, u32)> = Vec::new();
+        for slot in &self.inner {
+            match runs.last_mut() {
+                Some((occupied, len)) if *occupied == slot.is_some() && *len < u32::MAX => {
+                    *len += 1
+                }
+                _ => runs.push((slot.is_some(), 1)),
+            }
+        }
+        runs
+    }
+
+    /// Brings occupancy in line with `runs` from another replica as far as
+    /// it can without values: slots vacant there are vacated here, same
+    /// as if taken, including any past the end of `runs`. Returns the
+    /// ranges occupied there but not here, whose values have to be fetched.
+    pub fn apply_occupancy_rle(&mut self, runs: &[(bool, u32)]) -> Vec<Range<usize>> {
+        let mut missing: Vec<Range<usize>> = Vec::new();
+        let mut push_missing = |range: Range<usize>| match missing.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => missing.push(range),
+        };
+
+        // Only the slots we have are visited one by one, runs past the end
+        // cost the same however long they claim to be
+        let slots = self.inner.len();
+        let mut start: usize = 0;
+        for &(occupied, len) in runs {
+            let end = start.saturating_add(len as usize);
+            for index in start.min(slots)..end.min(slots) {
+                match (occupied, self.get(index).is_some()) {
+                    (true, false) => push_missing(index..index + 1),
+                    (false, true) => drop(self.vacate(index)),
+                    _ => {}
+                }
+            }
+            if occupied && end > slots {
+                push_missing(start.max(slots)..end);
+            }
+            start = end;
+        }
+        for index in start..slots {
+            drop(self.vacate(index));
+        }
+
+        self.debug_assert_consistent();
+        missing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replicas_converge_on_occupancy() {
+        let mut primary = Collection::new();
+        for i in 0..8 {
+            primary.add(i);
+        }
+        primary.take(2);
+        primary.take(3);
+        primary.take(7);
+        let runs = primary.occupancy_rle();
+        assert_eq!(runs, vec![(true, 2), (false, 2), (true, 3), (false, 1)]);
+
+        let mut replica = Collection::new();
+        for i in 0..3 {
+            replica.add(i);
+        }
+        replica.insert_at(9, 9);
+        assert_eq!(replica.apply_occupancy_rle(&runs), vec![4..7]);
+        assert_eq!(replica.occupied_indices(), vec![0, 1]);
+
+        for index in 4..7 {
+            replica.insert_at(index, primary[index].unwrap());
+        }
+        assert_eq!(replica.occupancy_rle()[..3], runs[..3]);
+        assert!(replica.apply_occupancy_rle(&runs).is_empty());
+    }
+
+    #[test]
+    fn runs_past_the_end_are_not_walked() {
+        let mut replica = Collection::new();
+        replica.add('a');
+        let runs = [(true, 1), (false, u32::MAX), (false, u32::MAX), (true, 2)];
+        let gap = 1 + 2 * u32::MAX as usize;
+        assert_eq!(replica.apply_occupancy_rle(&runs), vec![gap..gap + 2]);
+        assert_eq!(replica.len(), 1);
+    }
+}
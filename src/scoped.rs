@@ -0,0 +1,188 @@
+//! Restricted views over part of a collection's index range.
+//!
+//! A `ScopedCollection` can be handed to code that should only ever touch a
+//! given range of indices, e.g. plugin logic sharing a registry with its host.
+//! Indices outside the range are rejected with `OutOfScope` instead of being
+//! forwarded to the collection. Auto-compaction is paused while the view
+//! lives, since it would move values in and out of the range.
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use allocator_api2::alloc::{Allocator, Global};
+
+use crate::compact::AutoCompact;
+use crate::Collection;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutOfScope {
+    pub index: usize,
+    pub allowed: Range<usize>,
+}
+
+impl fmt::Display for OutOfScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "index {} is outside the allowed range {}..{}",
+            self.index, self.allowed.start, self.allowed.end
+        )
+    }
+}
+
+impl Error for OutOfScope {}
+
+pub struct ScopedCollection<'a, T, Tag = (), A: Allocator = Global> {
+    inner: &'a mut Collection<T, Tag, A>,
+    allowed: Range<usize>,
+    // Resumed once the view is dropped
+    paused: Option<AutoCompact>,
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    pub fn scoped(&mut self, allowed: Range<usize>) -> ScopedCollection<'_, T, Tag, A> {
+        let paused = self.auto_compact.take();
+        ScopedCollection {
+            inner: self,
+            allowed,
+            paused,
+        }
+    }
+}
+
+impl<'a, T, Tag, A: Allocator> ScopedCollection<'a, T, Tag, A> {
+    pub fn allowed(&self) -> Range<usize> {
+        self.allowed.clone()
+    }
+
+    /// Adds `item` at the lowest free index within the allowed range, or
+    /// hands it back if the range is full.
+    pub fn add(&mut self, item: T) -> Result<usize, T> {
+        let allowed = self.allowed.clone();
+        if allowed.is_empty() {
+            return Err(item);
+        }
+        self.inner.grow_vacant_to(allowed.start);
+
+        let vacant = self.inner.free.range(allowed.clone()).next();
+        if vacant.is_none() && !allowed.contains(&self.inner.append_index()) {
+            return Err(item);
+        }
+        Ok(self.inner.add_with(item, |free| {
+            let index = free.range(allowed.clone()).next()?;
+            free.remove(index);
+            Some(index)
+        }))
+    }
+
+    pub fn insert_at(&mut self, index: usize, item: T) -> Result<Option<T>, OutOfScope> {
+        self.check(index)?;
+        Ok(self.inner.insert_at(index, item))
+    }
+
+    pub fn take(&mut self, index: usize) -> Result<Option<T>, OutOfScope> {
+        self.check(index)?;
+        Ok(self.inner.try_take(index))
+    }
+
+    pub fn get(&self, index: usize) -> Result<Option<&T>, OutOfScope> {
+        self.check(index)?;
+        Ok(self.inner.get(index))
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Result<Option<&mut T>, OutOfScope> {
+        self.check(index)?;
+        Ok(self.inner.get_mut(index))
+    }
+
+    /// Iterates `(index, &value)` for the occupied slots within the range.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> + '_ {
+        let end = self.allowed.end.min(self.inner.inner.len());
+        let start = self.allowed.start.min(end);
+
+        self.inner.inner[start..end]
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, slot)| slot.as_ref().map(|value| (start + i, value)))
+    }
+
+    /// Drops the values within the range that `pred` returns `false` for.
+    pub fn retain(&mut self, pred: impl FnMut(usize, &mut T) -> bool) {
+        self.inner.retain_in_range(self.allowed.clone(), pred);
+    }
+
+    /// Number of occupied slots within the range.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    fn check(&self, index: usize) -> Result<(), OutOfScope> {
+        if self.allowed.contains(&index) {
+            Ok(())
+        } else {
+            Err(OutOfScope {
+                index,
+                allowed: self.allowed.clone(),
+            })
+        }
+    }
+}
+
+impl<T, Tag, A: Allocator> Drop for ScopedCollection<'_, T, Tag, A> {
+    fn drop(&mut self) {
+        self.inner.auto_compact = self.paused.take();
+        self.inner.auto_compact();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scoped_view_stays_within_its_range() {
+        let mut coll = Collection::new();
+        coll.add("host");
+
+        let mut plugin = coll.scoped(4..6);
+        assert_eq!(plugin.add("a"), Ok(4));
+        assert_eq!(plugin.add("b"), Ok(5));
+        assert_eq!(plugin.add("c"), Err("c"));
+
+        let err = plugin.take(0).unwrap_err();
+        assert_eq!(err.to_string(), "index 0 is outside the allowed range 4..6");
+        assert_eq!(plugin.take(4), Ok(Some("a")));
+        assert_eq!(plugin.iter().collect::<Vec<_>>(), vec![(5, &"b")]);
+        assert_eq!(plugin.add("d"), Ok(4));
+        drop(plugin);
+
+        // Vacancies created in front of the range are left to the host
+        assert_eq!(coll.len(), 3);
+        assert_eq!(coll.add("host 2"), 1);
+    }
+
+    #[test]
+    fn compaction_waits_until_the_view_is_dropped() {
+        let mut coll = Collection::new();
+        for i in 0..10 {
+            coll.add(format!("v{}", i));
+        }
+        coll.set_auto_compact(Some(AutoCompact::when_vacancy_exceeds(0.1)));
+
+        let mut plugin = coll.scoped(0..3);
+        plugin.take(0).unwrap();
+        plugin.take(1).unwrap();
+        assert_eq!(plugin.len(), 1);
+        assert_eq!(plugin.take(0), Ok(None));
+        assert_eq!(plugin.add("p".into()), Ok(0));
+        drop(plugin);
+
+        // Undo sees the plugin's add, and compaction is back afterwards
+        assert_eq!(coll.undo_last_add(), Some((0, "p".to_string())));
+        assert_eq!(coll.get(0).map(String::as_str), Some("v9"));
+    }
+}
@@ -0,0 +1,316 @@
+//! Serde support.
+//!
+//! Human-readable formats get a map from index to value, e.g.
+//! `{"3": "a", "7": "b"}` in JSON. Binary formats get the slot count, an
+//! occupancy bitmap and the values without holes. `serde_map` and
+//! `serde_dense` force one or the other through `#[serde(with = "...")]`.
+//!
+//! Only the values are stored, so keys issued before serializing don't
+//! resolve in the deserialized collection. `serde_keys` stores the
+//! generations as well, and hands back a `KeyRemapper` that translates keys
+//! saved alongside the collection, or tells that they were already stale.
+//!
+//! The map form and `serde_keys` allocate every slot up to the highest
+//! index, holes included, so a short hostile input could ask for any amount
+//! of memory. Both reject indices from `MAX_SPARSE_SLOTS` up; the dense
+//! form doesn't need a limit since its bitmap grows with the slot count.
+use allocator_api2::alloc::Allocator;
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, Serializer};
+
+use crate::{Collection, Key};
+
+/// The number of slots the map forms deserialize at most.
+pub const MAX_SPARSE_SLOTS: usize = 1 << 24;
+
+// The slot count for a sparse input whose highest index is `last`
+fn sparse_slot_count<E: Error>(last: Option<&usize>) -> Result<usize, E> {
+    match last {
+        None => Ok(0),
+        Some(&last) if last < MAX_SPARSE_SLOTS => Ok(last + 1),
+        Some(&last) => Err(E::custom(format!(
+            "index {} is above the limit of {} slots",
+            last, MAX_SPARSE_SLOTS
+        ))),
+    }
+}
+
+impl<T: Serialize, Tag, A: Allocator> Serialize for Collection<T, Tag, A> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serde_map::serialize(self, serializer)
+        } else {
+            serde_dense::serialize(self, serializer)
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>, Tag> Deserialize<'de> for Collection<T, Tag> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            serde_map::deserialize(deserializer)
+        } else {
+            serde_dense::deserialize(deserializer)
+        }
+    }
+}
+
+/// The map form, `{index: value}`.
+pub mod serde_map {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    pub fn serialize<S, T, Tag, A: Allocator>(
+        coll: &Collection<T, Tag, A>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        serializer.collect_map(coll.enumerate())
+    }
+
+    pub fn deserialize<'de, D, T, Tag>(deserializer: D) -> Result<Collection<T, Tag>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        let values = BTreeMap::<usize, T>::deserialize(deserializer)?;
+
+        let slots = sparse_slot_count(values.keys().next_back())?;
+        let mut inner = Vec::new();
+        inner.resize_with(slots, || None);
+        for (index, value) in values {
+            inner[index] = Some(value);
+        }
+
+        Ok(Collection::from_slots(inner))
+    }
+}
+
+/// The dense form, `(slots, occupancy bitmap, values)`.
+pub mod serde_dense {
+    use super::*;
+
+    pub fn serialize<S, T, Tag, A: Allocator>(
+        coll: &Collection<T, Tag, A>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        let mut bitmap = vec![0u8; coll.inner.len().div_ceil(8)];
+        for (index, slot) in coll.inner.iter().enumerate() {
+            if slot.is_some() {
+                bitmap[index / 8] |= 1 << (index % 8);
+            }
+        }
+
+        let values: Vec<&T> = coll.inner.iter().flatten().collect();
+        (coll.inner.len(), bitmap, values).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D, T, Tag>(deserializer: D) -> Result<Collection<T, Tag>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        let (slots, bitmap, values) = <(usize, Vec<u8>, Vec<T>)>::deserialize(deserializer)?;
+
+        // Checking the bitmap against the slot count first also keeps a
+        // hostile slot count from making us allocate more than the input
+        if bitmap.len() != slots.div_ceil(8) {
+            return Err(D::Error::custom("bitmap doesn't match the slot count"));
+        }
+        let is_occupied = |index: usize| bitmap[index / 8] & (1 << (index % 8)) != 0;
+        if values.len() != (0..slots).filter(|&index| is_occupied(index)).count() {
+            return Err(D::Error::custom("value count doesn't match the bitmap"));
+        }
+
+        let mut values = values.into_iter();
+        let inner = (0..slots)
+            .map(|index| {
+                if is_occupied(index) {
+                    values.next()
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        Ok(Collection::from_slots(inner))
+    }
+}
+
+/// Translates keys issued before a collection was serialized with
+/// `serde_keys` into keys for the deserialized one.
+pub struct KeyRemapper<Tag = ()> {
+    // The generation every occupied slot was saved at, and its new key
+    keys: Vec<Option<(u32, Key<Tag>)>>,
+}
+
+impl<Tag> KeyRemapper<Tag> {
+    /// The key that refers to the same value as `old` did, or `None` if
+    /// `old` was already stale when the collection was saved.
+    pub fn remap(&self, old: Key<Tag>) -> Option<Key<Tag>> {
+        match self.keys.get(old.index()).copied().flatten() {
+            Some((generation, new)) if generation == old.generation() => Some(new),
+            _ => None,
+        }
+    }
+
+    pub fn is_stale(&self, old: Key<Tag>) -> bool {
+        self.remap(old).is_none()
+    }
+}
+
+/// A sequence of `(index, generation, value)`, deserialized together with a
+/// `KeyRemapper`. Not usable through `#[serde(with = "...")]`, since that
+/// would have nowhere to put the remapper.
+pub mod serde_keys {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    type WithRemapper<T, Tag> = (Collection<T, Tag>, KeyRemapper<Tag>);
+
+    pub fn serialize<S, T, Tag, A: Allocator>(
+        coll: &Collection<T, Tag, A>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        serializer.collect_seq(
+            coll.enumerate()
+                .map(|(index, item)| (index, coll.generations.get(index), item)),
+        )
+    }
+
+    pub fn deserialize<'de, D, T, Tag>(deserializer: D) -> Result<WithRemapper<T, Tag>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        let mut values = BTreeMap::new();
+        for (index, generation, value) in Vec::<(usize, u32, T)>::deserialize(deserializer)? {
+            if values.insert(index, (generation, value)).is_some() {
+                return Err(D::Error::custom(format!("slot {} appears twice", index)));
+            }
+        }
+
+        let slots = sparse_slot_count(values.keys().next_back())?;
+        let mut inner = Vec::new();
+        inner.resize_with(slots, || None);
+        let mut generations = vec![None; slots];
+        for (index, (generation, value)) in values {
+            inner[index] = Some(value);
+            generations[index] = Some(generation);
+        }
+
+        let coll = Collection::from_slots(inner);
+        let keys = generations
+            .into_iter()
+            .enumerate()
+            .map(|(index, generation)| Some((generation?, coll.key_for_index(index)?)))
+            .collect();
+        Ok((coll, KeyRemapper { keys }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Collection<String> {
+        let mut coll = Collection::new();
+        for value in &["a", "b", "c", "d"] {
+            coll.add(value.to_string());
+        }
+        coll.take(0);
+        coll.take(2);
+        coll
+    }
+
+    #[test]
+    fn json_uses_the_map_form() {
+        let json = serde_json::to_string(&sample()).unwrap();
+        assert_eq!(json, r#"{"1":"b","3":"d"}"#);
+
+        let mut coll: Collection<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(coll.get(3).map(String::as_str), Some("d"));
+        assert_eq!(coll.len(), 2);
+        assert_eq!(coll.add("e".to_string()), 0);
+    }
+
+    #[test]
+    fn bincode_uses_the_dense_form() {
+        let bytes = bincode::serialize(&sample()).unwrap();
+        let coll: Collection<String> = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(coll.inner, sample().inner);
+
+        // Slot count, one bitmap byte and two values
+        let dense: (usize, Vec<u8>, Vec<String>) = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(dense, (4, vec![0b1010], vec!["b".into(), "d".into()]));
+
+        let bad = bincode::serialize(&(4usize, vec![0b1011u8], vec!["b"])).unwrap();
+        assert!(bincode::deserialize::<Collection<String>>(&bad).is_err());
+    }
+
+    #[test]
+    fn forms_can_be_forced() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Config {
+            #[serde(with = "serde_dense")]
+            entries: Collection<u8>,
+        }
+
+        let mut entries = Collection::new();
+        entries.add(1);
+        let json = serde_json::to_string(&Config { entries }).unwrap();
+        assert_eq!(json, r#"{"entries":[1,[1],[1]]}"#);
+
+        let config: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(config.entries.get(0), Some(&1));
+    }
+
+    #[test]
+    fn map_form_rejects_huge_indices() {
+        for json in [r#"{"18446744073709551615":1}"#, r#"{"16777216":1}"#] {
+            let err = serde_json::from_str::<Collection<u8>>(json).unwrap_err();
+            assert!(err.to_string().contains("above the limit"));
+        }
+    }
+
+    #[test]
+    fn saved_keys_are_remapped_or_flagged_stale() {
+        let mut coll = sample();
+        let d = coll.key_for_index(3).unwrap();
+        let e = coll.add_key("e".to_string());
+        let stale = coll.add_key("gone".to_string());
+        coll.take_key(stale);
+        let reused = coll.add_key("f".to_string());
+        assert_eq!(reused.index(), stale.index());
+
+        let mut json = Vec::new();
+        serde_keys::serialize(&coll, &mut serde_json::Serializer::new(&mut json)).unwrap();
+        let (loaded, remapper): (Collection<String>, _) =
+            serde_keys::deserialize(&mut serde_json::Deserializer::from_slice(&json)).unwrap();
+
+        for old in [d, e, reused] {
+            let new = remapper.remap(old).unwrap();
+            assert_eq!(loaded.get_key(new), coll.get_key(old));
+        }
+        assert!(remapper.is_stale(stale));
+        assert!(remapper.is_stale(Key::from_raw(99)));
+
+        let huge = br#"[[18446744073709551615, 0, "a"]]"#;
+        let loaded: Result<(Collection<String>, _), _> =
+            serde_keys::deserialize(&mut serde_json::Deserializer::from_slice(huge));
+        assert!(loaded.is_err());
+    }
+}
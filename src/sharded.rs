@@ -0,0 +1,179 @@
+//! A set of collections sharing one index namespace.
+//!
+//! Each shard is an ordinary `Collection`. The shard number is stored in the
+//! high bits of the indices handed out, so `take` and `get` find the right
+//! shard on their own while per-core code can keep to its own shard.
+use crate::Collection;
+
+pub struct ShardedCollection<T> {
+    shards: Vec<Collection<T>>,
+    shard_bits: u32,
+}
+
+impl<T> ShardedCollection<T> {
+    pub fn new(shards: usize) -> Self {
+        assert!(shards > 0, "A sharded collection needs at least one shard");
+
+        Self {
+            shards: (0..shards).map(|_| Collection::new()).collect(),
+            shard_bits: shards.next_power_of_two().trailing_zeros(),
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Combines a shard number and an index local to that shard.
+    pub fn encode(&self, shard: usize, local: usize) -> usize {
+        debug_assert!(
+            local <= self.local_mask(),
+            "Local index overflows into shard bits"
+        );
+        shard.checked_shl(self.shift()).unwrap_or(0) | local
+    }
+
+    /// Splits an index into its shard number and the index local to that shard.
+    pub fn decode(&self, index: usize) -> (usize, usize) {
+        (
+            index.checked_shr(self.shift()).unwrap_or(0),
+            index & self.local_mask(),
+        )
+    }
+
+    pub fn shard_of(&self, index: usize) -> usize {
+        self.decode(index).0
+    }
+
+    pub fn add_to_shard(&mut self, shard: usize, item: T) -> usize {
+        let local = self.shards[shard].add(item);
+        self.encode(shard, local)
+    }
+
+    #[track_caller]
+    pub fn take(&mut self, index: usize) -> T {
+        let (shard, local) = self.decode(index);
+        let shards = self.shards.len();
+        let Some(collection) = self.shards.get_mut(shard) else {
+            panic!(
+                "index {} is in shard {}, but there are only {} shards",
+                index, shard, shards
+            );
+        };
+        match collection.try_take(local) {
+            Some(item) => item,
+            None => {
+                let msg = format!("index {} is in shard {}", index, shard);
+                collection.vacant_panic(local, Some(&msg))
+            }
+        }
+    }
+
+    pub fn try_take(&mut self, index: usize) -> Option<T> {
+        let (shard, local) = self.decode(index);
+        self.shards.get_mut(shard)?.try_take(local)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let (shard, local) = self.decode(index);
+        self.shards.get(shard)?.get(local)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let (shard, local) = self.decode(index);
+        self.shards.get_mut(shard)?.get_mut(local)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.len() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(Collection::is_empty)
+    }
+
+    pub fn shard(&self, shard: usize) -> &Collection<T> {
+        &self.shards[shard]
+    }
+
+    /// Indices returned by the shard itself are local, use `encode` to turn
+    /// them into indices for the sharded collection.
+    pub fn shard_mut(&mut self, shard: usize) -> &mut Collection<T> {
+        &mut self.shards[shard]
+    }
+
+    /// All shards at once, e.g. to hand one to each worker thread.
+    pub fn shards_mut(&mut self) -> &mut [Collection<T>] {
+        &mut self.shards
+    }
+
+    /// Iterates `(index, &value)` over one shard, with indices already encoded.
+    pub fn iter_shard(&self, shard: usize) -> impl Iterator<Item = (usize, &T)> + '_ {
+        self.shards[shard]
+            .inner
+            .iter()
+            .enumerate()
+            .filter_map(move |(local, slot)| {
+                slot.as_ref()
+                    .map(|value| (self.encode(shard, local), value))
+            })
+    }
+
+    fn shift(&self) -> u32 {
+        usize::BITS - self.shard_bits
+    }
+
+    fn local_mask(&self) -> usize {
+        usize::MAX >> self.shard_bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_route_to_their_shard() {
+        let mut sharded = ShardedCollection::new(3);
+        let a = sharded.add_to_shard(0, 'a');
+        let b = sharded.add_to_shard(2, 'b');
+        let c = sharded.add_to_shard(2, 'c');
+
+        assert_eq!(a, 0);
+        assert_ne!(b, c);
+        assert_eq!(sharded.shard_of(b), 2);
+        assert_eq!(sharded.decode(c), (2, 1));
+        assert_eq!(sharded.len(), 3);
+
+        assert_eq!(sharded.take(b), 'b');
+        assert_eq!(sharded.try_take(b), None);
+        assert_eq!(sharded.get(c), Some(&'c'));
+
+        let shard: Vec<_> = sharded.iter_shard(2).collect();
+        assert_eq!(shard, vec![(c, &'c')]);
+    }
+
+    #[test]
+    fn take_says_which_shard_the_index_is_in() {
+        let mut sharded = ShardedCollection::new(2);
+        let a = sharded.add_to_shard(1, 'a');
+        sharded.take(a);
+
+        let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            sharded.take(a);
+        }))
+        .unwrap_err();
+        assert_eq!(
+            payload.downcast_ref::<String>().map(String::as_str),
+            Some(format!("index {} is in shard 1: slot 0 is vacant", a).as_str())
+        );
+    }
+
+    #[test]
+    fn single_shard_uses_plain_indices() {
+        let mut sharded = ShardedCollection::new(1);
+        assert_eq!(sharded.add_to_shard(0, 1), 0);
+        assert_eq!(sharded.add_to_shard(0, 2), 1);
+        assert_eq!(sharded.decode(1), (0, 1));
+    }
+}
@@ -0,0 +1,194 @@
+//! A fixed-capacity collection stored inline, without allocating.
+//!
+//! `SmallCollection<T, N, W>` keeps its values in a `[MaybeUninit<T>; N]` and
+//! which of them are occupied in a `[u64; W]` bitmap, so no slot pays for an
+//! `Option` discriminant and `iter` jumps from one set bit to the next
+//! instead of testing every slot. `W` has to be `(N + 63) / 64`, the number
+//! of words it takes to cover `N` bits. It defaults to 1, so only
+//! collections of more than 64 slots need to spell it out.
+use std::fmt;
+use std::mem::{self, MaybeUninit};
+
+pub struct SmallCollection<T, const N: usize, const W: usize = 1> {
+    values: [MaybeUninit<T>; N],
+    // Bit `i % 64` of word `i / 64` is set iff `values[i]` is initialized
+    occupied: [u64; W],
+}
+
+impl<T, const N: usize, const W: usize> SmallCollection<T, N, W> {
+    // Referenced in `new` so a mismatched `W` fails to compile
+    const FITS: () = assert!(
+        W == N.div_ceil(64),
+        "SmallCollection<T, N, W> needs W == (N + 63) / 64"
+    );
+
+    pub const fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::FITS;
+        Self {
+            // Safety: an array of `MaybeUninit` needs no initialization
+            values: unsafe { MaybeUninit::uninit().assume_init() },
+            occupied: [0; W],
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Adds `item` at the lowest vacant index, or hands it back if every
+    /// slot is taken.
+    pub fn add(&mut self, item: T) -> Result<usize, T> {
+        let vacant = self
+            .occupied
+            .iter()
+            .position(|&word| word != u64::MAX)
+            .map(|word| word * 64 + (!self.occupied[word]).trailing_zeros() as usize);
+        let index = match vacant {
+            Some(index) if index < N => index,
+            _ => return Err(item),
+        };
+        self.values[index].write(item);
+        self.occupied[index / 64] |= 1 << (index % 64);
+        Ok(index)
+    }
+
+    pub fn take(&mut self, index: usize) -> Option<T> {
+        if !self.is_occupied(index) {
+            return None;
+        }
+        self.occupied[index / 64] &= !(1 << (index % 64));
+        // Safety: the bit was set, and clearing it first means the value
+        // is never read or dropped again
+        Some(unsafe { self.values[index].assume_init_read() })
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if !self.is_occupied(index) {
+            return None;
+        }
+        // Safety: the bit is set
+        Some(unsafe { self.values[index].assume_init_ref() })
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if !self.is_occupied(index) {
+            return None;
+        }
+        // Safety: the bit is set
+        Some(unsafe { self.values[index].assume_init_mut() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.occupied
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.occupied.iter().all(|&word| word == 0)
+    }
+
+    /// Iterates `(index, value)` over the occupied slots, in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        set_bits(self.occupied).map(move |index| {
+            // Safety: only set bits are visited
+            (index, unsafe { self.values[index].assume_init_ref() })
+        })
+    }
+
+    pub fn clear(&mut self) {
+        // Clear the bitmap first, a panicking `Drop` then leaks the rest
+        // instead of dropping anything twice
+        let occupied = mem::replace(&mut self.occupied, [0; W]);
+        for index in set_bits(occupied) {
+            // Safety: the bit was set
+            unsafe { self.values[index].assume_init_drop() };
+        }
+    }
+
+    fn is_occupied(&self, index: usize) -> bool {
+        index < N && self.occupied[index / 64] & (1 << (index % 64)) != 0
+    }
+}
+
+// The indices of the set bits, lowest first
+fn set_bits<const W: usize>(words: [u64; W]) -> impl Iterator<Item = usize> {
+    (0..W).flat_map(move |word| {
+        let mut bits = words[word];
+        std::iter::from_fn(move || {
+            if bits == 0 {
+                return None;
+            }
+            let index = bits.trailing_zeros() as usize;
+            bits &= bits - 1;
+            Some(word * 64 + index)
+        })
+    })
+}
+
+impl<T, const N: usize, const W: usize> Default for SmallCollection<T, N, W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize, const W: usize> Drop for SmallCollection<T, N, W> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T: fmt::Debug, const N: usize, const W: usize> fmt::Debug for SmallCollection<T, N, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn fills_the_lowest_vacancy_and_drops_what_is_left() {
+        let shared = Rc::new(());
+        let mut coll = SmallCollection::<_, 3>::new();
+        for _ in 0..3 {
+            coll.add(Rc::clone(&shared)).unwrap();
+        }
+        assert!(coll.add(Rc::clone(&shared)).is_err());
+
+        drop(coll.take(1));
+        assert_eq!(coll.take(1), None);
+        assert_eq!(
+            coll.iter().map(|(index, _)| index).collect::<Vec<_>>(),
+            vec![0, 2]
+        );
+        assert_eq!(coll.add(Rc::clone(&shared)), Ok(1));
+        assert_eq!(Rc::strong_count(&shared), 4);
+
+        drop(coll);
+        assert_eq!(Rc::strong_count(&shared), 1);
+        assert_eq!(mem::size_of::<SmallCollection<u32, 4>>(), 4 * 4 + 8);
+    }
+
+    #[test]
+    fn spans_several_bitmap_words() {
+        let mut coll = SmallCollection::<usize, 100, 2>::new();
+        for i in 0..100 {
+            assert_eq!(coll.add(i), Ok(i));
+        }
+        assert_eq!(coll.add(100), Err(100));
+        assert_eq!(coll.len(), 100);
+
+        assert_eq!(coll.take(70), Some(70));
+        assert_eq!(coll.take(3), Some(3));
+        assert_eq!(coll.get(99), Some(&99));
+        assert_eq!(coll.iter().nth(64), Some((65, &65)));
+        assert_eq!(coll.add(0), Ok(3));
+        assert_eq!(coll.add(0), Ok(70));
+        assert_eq!(mem::size_of::<SmallCollection<u8, 100, 2>>(), 100 + 4 + 16);
+    }
+}
@@ -0,0 +1,247 @@
+//! A collection with occupancy split out of the slots.
+//!
+//! `SoaCollection` keeps its values in a `Vec<MaybeUninit<T>>` and which of
+//! them are occupied in a bitmap alongside, instead of one `Option<T>` per
+//! slot. Large values with no niche then don't pay for padding after the
+//! discriminant, and scans skip whole words of vacant slots. It has the
+//! same indices as `Collection` given the same adds and takes, but none of
+//! its bookkeeping: no keys, groups or compaction. `cargo bench --bench
+//! layout --features soa-layout` compares the two.
+use std::fmt;
+use std::mem::MaybeUninit;
+
+use crate::SlotStorage;
+
+pub struct SoaCollection<T> {
+    values: Vec<MaybeUninit<T>>,
+    // Bit `i % 64` of word `i / 64` is set iff `values[i]` is initialized
+    occupied: Vec<u64>,
+    len: usize,
+}
+
+impl<T> SoaCollection<T> {
+    pub const fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            occupied: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Stores `item` at the lowest vacant index, like `Collection::add`.
+    /// Finding it takes O(slots / 64).
+    pub fn add(&mut self, item: T) -> usize {
+        let vacant = self
+            .occupied
+            .iter()
+            .enumerate()
+            .find(|(_, &word)| word != u64::MAX)
+            .map(|(word, &bits)| word * 64 + (!bits).trailing_zeros() as usize)
+            .filter(|&index| index < self.values.len());
+
+        let index = match vacant {
+            Some(index) => {
+                self.values[index].write(item);
+                index
+            }
+            None => {
+                self.values.push(MaybeUninit::new(item));
+                if self.values.len() > self.occupied.len() * 64 {
+                    self.occupied.push(0);
+                }
+                self.values.len() - 1
+            }
+        };
+        self.occupied[index / 64] |= 1 << (index % 64);
+        self.len += 1;
+        index
+    }
+
+    pub fn try_take(&mut self, index: usize) -> Option<T> {
+        if !self.is_occupied(index) {
+            return None;
+        }
+        self.occupied[index / 64] &= !(1 << (index % 64));
+        self.len -= 1;
+        // Safety: the bit was set, and clearing it first means the value
+        // is never read or dropped again
+        Some(unsafe { self.values[index].assume_init_read() })
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if !self.is_occupied(index) {
+            return None;
+        }
+        // Safety: the bit is set
+        Some(unsafe { self.values[index].assume_init_ref() })
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if !self.is_occupied(index) {
+            return None;
+        }
+        // Safety: the bit is set
+        Some(unsafe { self.values[index].assume_init_mut() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterates `(index, value)` over the occupied slots, in index order.
+    pub fn enumerate(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.occupied_indices().map(move |index| {
+            // Safety: only set bits are visited
+            (index, unsafe { self.values[index].assume_init_ref() })
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.enumerate().map(|(_, item)| item)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        let values = self.values.as_mut_ptr();
+        Ones::new(&self.occupied).map(move |index| {
+            // Safety: every index is visited once, so the references don't
+            // alias, and only set bits are visited. The bitmap is borrowed
+            // by the iterator, so the values can't be reallocated under it.
+            unsafe { (*values.add(index)).assume_init_mut() }
+        })
+    }
+
+    pub fn clear(&mut self) {
+        // Clear the bitmap first, a panicking `Drop` then leaks the rest
+        // instead of dropping anything twice
+        let occupied = std::mem::take(&mut self.occupied);
+        self.len = 0;
+        let mut values = std::mem::take(&mut self.values);
+        for index in Ones::new(&occupied) {
+            // Safety: the bit was set
+            unsafe { values[index].assume_init_drop() };
+        }
+        values.clear();
+        self.values = values;
+    }
+
+    fn is_occupied(&self, index: usize) -> bool {
+        self.occupied
+            .get(index / 64)
+            .is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+
+    fn occupied_indices(&self) -> Ones<'_> {
+        Ones::new(&self.occupied)
+    }
+}
+
+// The set bits of a bitmap, lowest first
+struct Ones<'a> {
+    words: std::slice::Iter<'a, u64>,
+    base: usize,
+    word: u64,
+}
+
+impl<'a> Ones<'a> {
+    fn new(words: &'a [u64]) -> Self {
+        Self {
+            words: words.iter(),
+            // Wraps to 0 on the first word
+            base: 0usize.wrapping_sub(64),
+            word: 0,
+        }
+    }
+}
+
+impl Iterator for Ones<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.word == 0 {
+            self.word = *self.words.next()?;
+            self.base = self.base.wrapping_add(64);
+        }
+        let bit = self.word.trailing_zeros() as usize;
+        self.word &= self.word - 1;
+        Some(self.base + bit)
+    }
+}
+
+impl<T> Default for SoaCollection<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for SoaCollection<T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SoaCollection<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.enumerate()).finish()
+    }
+}
+
+impl<T> SlotStorage<T> for SoaCollection<T> {
+    fn add(&mut self, item: T) -> Result<usize, T> {
+        Ok(SoaCollection::add(self, item))
+    }
+
+    fn take(&mut self, index: usize) -> Option<T> {
+        self.try_take(index)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        SoaCollection::get(self, index)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        Box::new(SoaCollection::iter(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Collection;
+    use std::rc::Rc;
+
+    #[test]
+    fn indices_match_the_option_layout() {
+        let shared = Rc::new(());
+        let mut soa = SoaCollection::new();
+        let mut coll = Collection::new();
+        for i in 0..150 {
+            assert_eq!(soa.add((i, Rc::clone(&shared))), coll.add(i));
+        }
+        for i in (0..150).filter(|i| i % 3 != 1) {
+            assert_eq!(soa.try_take(i).map(|(i, _)| i), coll.try_take(i));
+        }
+        assert_eq!(soa.add((1000, Rc::clone(&shared))), coll.add(1000));
+
+        for (i, _) in soa.iter_mut() {
+            *i += 1;
+        }
+        let soa_values: Vec<_> = soa
+            .enumerate()
+            .map(|(index, (i, _))| (index, i - 1))
+            .collect();
+        let values: Vec<_> = coll.enumerate().map(|(index, &i)| (index, i)).collect();
+        assert_eq!(soa_values, values);
+        assert_eq!(soa.len(), 51);
+
+        drop(soa);
+        assert_eq!(Rc::strong_count(&shared), 1);
+    }
+}
@@ -0,0 +1,97 @@
+//! Disjoint mutable views over a collection's slots.
+//!
+//! `split_mut` hands out two `CollectionViewMut`s over the slots below and
+//! from a given index. Each is `Send` when `T` is, so the halves can be
+//! mutated on different threads, e.g. with `std::thread::scope`. Views only
+//! change values in place, so the collection's bookkeeping stays valid.
+use std::ops::Range;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+pub struct CollectionViewMut<'a, T> {
+    slots: &'a mut [Option<T>],
+    // Index of the first slot in `slots`
+    start: usize,
+}
+
+impl<'a, T> CollectionViewMut<'a, T> {
+    /// The indices this view covers.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.start + self.slots.len()
+    }
+
+    /// The value at `index`, which is an index into the whole collection.
+    /// `None` if it's vacant or outside the view.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.slots.get(index.checked_sub(self.start)?)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.slots.get_mut(index.checked_sub(self.start)?)?.as_mut()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().flatten()
+    }
+
+    /// Like `iter_mut`, with the collection index of every value.
+    pub fn enumerate_mut(&mut self) -> impl Iterator<Item = (usize, &mut T)> {
+        let start = self.start;
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter_map(move |(offset, slot)| Some((start + offset, slot.as_mut()?)))
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Splits the slots into the views `..at` and `at..`. An `at` past the
+    /// last slot leaves the second view empty.
+    pub fn split_mut(&mut self, at: usize) -> (CollectionViewMut<'_, T>, CollectionViewMut<'_, T>) {
+        let at = at.min(self.inner.len());
+        let (low, high) = self.inner.split_at_mut(at);
+        (
+            CollectionViewMut {
+                slots: low,
+                start: 0,
+            },
+            CollectionViewMut {
+                slots: high,
+                start: at,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn halves_are_mutated_on_separate_threads() {
+        let mut scores = Collection::new();
+        for score in 0..6 {
+            scores.add(score);
+        }
+        scores.take(4);
+
+        let (mut low, mut high) = scores.split_mut(3);
+        assert_eq!(high.range(), 3..6);
+        assert_eq!(low.get_mut(3), None);
+        assert_eq!(high.get(4), None);
+
+        thread::scope(|s| {
+            s.spawn(|| low.iter_mut().for_each(|score| *score += 10));
+            s.spawn(|| {
+                high.enumerate_mut()
+                    .for_each(|(index, score)| *score = index * 100)
+            });
+        });
+
+        assert_eq!(scores.to_owned_values(), vec![10, 11, 12, 300, 500]);
+        assert_eq!(scores.split_mut(99).1.range(), 6..6);
+    }
+}
@@ -0,0 +1,145 @@
+//! How long values stay in their slots.
+//!
+//! With the `stats` feature the collection counts every fill and vacate,
+//! and records how many of those operations a value lived through before it
+//! was taken. Without it `PoolStats` is empty and recording compiles to
+//! nothing.
+#[cfg(feature = "stats")]
+use allocator_api2::alloc::Allocator;
+
+#[cfg(feature = "stats")]
+use crate::Collection;
+
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Default)]
+pub struct PoolStats {
+    // Fills and vacates so far, the clock lifetimes are measured with
+    ops: u64,
+    fills: u64,
+    vacates: u64,
+    filled_at: Vec<u64>,
+    histogram: Vec<u64>,
+}
+
+#[cfg(not(feature = "stats"))]
+#[derive(Debug, Default)]
+pub(crate) struct PoolStats;
+
+#[cfg(feature = "stats")]
+impl PoolStats {
+    pub(crate) const fn new() -> Self {
+        Self {
+            ops: 0,
+            fills: 0,
+            vacates: 0,
+            filled_at: Vec::new(),
+            histogram: Vec::new(),
+        }
+    }
+
+    pub(crate) fn filled(&mut self, index: usize) {
+        self.ops += 1;
+        self.fills += 1;
+        if self.filled_at.len() <= index {
+            self.filled_at.resize(index + 1, 0);
+        }
+        self.filled_at[index] = self.ops;
+    }
+
+    pub(crate) fn vacated(&mut self, index: usize) {
+        self.ops += 1;
+        self.vacates += 1;
+
+        let filled_at = self.filled_at.get(index).copied().unwrap_or(0);
+        // Counting both the fill and this vacate
+        let lifetime = self.ops - filled_at + 1;
+        let bucket = (63 - lifetime.leading_zeros()) as usize;
+        if self.histogram.len() <= bucket {
+            self.histogram.resize(bucket + 1, 0);
+        }
+        self.histogram[bucket] += 1;
+    }
+
+    pub(crate) fn moved(&mut self, old: usize, new: usize) {
+        let filled_at = self.filled_at.get(old).copied().unwrap_or(0);
+        if self.filled_at.len() <= new {
+            self.filled_at.resize(new + 1, 0);
+        }
+        self.filled_at[new] = filled_at;
+    }
+
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        let len = self.filled_at.len().max(a + 1).max(b + 1);
+        self.filled_at.resize(len, 0);
+        self.filled_at.swap(a, b);
+    }
+
+    /// Bucket `i` counts the values that were taken after living through
+    /// `2^i..2^(i + 1)` operations, counting the fill and vacate that
+    /// bracket them as well as every other fill and vacate in between.
+    pub fn lifetime_histogram(&self) -> &[u64] {
+        &self.histogram
+    }
+
+    /// Values stored so far, including replacements.
+    pub fn fills(&self) -> u64 {
+        self.fills
+    }
+
+    /// Values taken, replaced or cleared so far.
+    pub fn vacates(&self) -> u64 {
+        self.vacates
+    }
+}
+
+#[cfg(not(feature = "stats"))]
+impl PoolStats {
+    pub(crate) const fn new() -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    pub(crate) fn filled(&mut self, _index: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn vacated(&mut self, _index: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn moved(&mut self, _old: usize, _new: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn swap(&mut self, _a: usize, _b: usize) {}
+}
+
+#[cfg(feature = "stats")]
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    pub fn pool_stats(&self) -> &PoolStats {
+        &self.stats
+    }
+}
+
+#[cfg(all(test, feature = "stats"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_lifetimes_by_power_of_two() {
+        let mut coll = Collection::new();
+        let short = coll.add(1);
+        coll.take(short);
+
+        let long = coll.add(2);
+        for i in 0..6 {
+            coll.add(i);
+        }
+        coll.take(long);
+
+        // 2 ops, then 8 ops
+        assert_eq!(coll.pool_stats().lifetime_histogram(), &[0, 1, 0, 1]);
+
+        coll.insert_at(1, 9);
+        coll.clear();
+        let stats = coll.pool_stats();
+        assert_eq!((stats.fills(), stats.vacates()), (9, 9));
+    }
+}
@@ -0,0 +1,170 @@
+//! A trait over the slot storages, for code that doesn't care which one it
+//! gets.
+//!
+//! `SlotStorage` is object safe, so `&mut dyn SlotStorage<T>` works too.
+//! `ConcurrentCollection` doesn't implement it, its values are only
+//! reachable through lock guards.
+use std::hash::Hash;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::{Collection, HashedCollection, SmallCollection, StaticCollection};
+
+pub trait SlotStorage<T> {
+    /// Stores `item` and returns its index, or hands it back if the storage
+    /// is full.
+    fn add(&mut self, item: T) -> Result<usize, T>;
+
+    fn take(&mut self, index: usize) -> Option<T>;
+
+    fn get(&self, index: usize) -> Option<&T>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the values in index order.
+    fn iter(&self) -> Box<dyn Iterator<Item = &T> + '_>;
+}
+
+impl<T, Tag, A: Allocator> SlotStorage<T> for Collection<T, Tag, A> {
+    fn add(&mut self, item: T) -> Result<usize, T> {
+        Ok(Collection::add(self, item))
+    }
+
+    fn take(&mut self, index: usize) -> Option<T> {
+        self.try_take(index)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        Collection::get(self, index)
+    }
+
+    fn len(&self) -> usize {
+        Collection::len(self) as usize
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        Box::new(Collection::iter(self))
+    }
+}
+
+impl<T, Tag> SlotStorage<T> for StaticCollection<'_, T, Tag> {
+    fn add(&mut self, item: T) -> Result<usize, T> {
+        StaticCollection::add(self, item)
+    }
+
+    fn take(&mut self, index: usize) -> Option<T> {
+        StaticCollection::take(self, index)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        StaticCollection::get(self, index)
+    }
+
+    fn len(&self) -> usize {
+        StaticCollection::len(self)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        Box::new(StaticCollection::iter(self))
+    }
+}
+
+impl<T, const N: usize, const W: usize> SlotStorage<T> for SmallCollection<T, N, W> {
+    fn add(&mut self, item: T) -> Result<usize, T> {
+        SmallCollection::add(self, item)
+    }
+
+    fn take(&mut self, index: usize) -> Option<T> {
+        SmallCollection::take(self, index)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        SmallCollection::get(self, index)
+    }
+
+    fn len(&self) -> usize {
+        SmallCollection::len(self)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        Box::new(SmallCollection::iter(self).map(|(_, item)| item))
+    }
+}
+
+impl<T: Hash, Tag> SlotStorage<T> for HashedCollection<T, Tag> {
+    fn add(&mut self, item: T) -> Result<usize, T> {
+        Ok(HashedCollection::add(self, item))
+    }
+
+    fn take(&mut self, index: usize) -> Option<T> {
+        HashedCollection::take(self, index)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        Collection::get(self, index)
+    }
+
+    fn len(&self) -> usize {
+        Collection::len(self) as usize
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        Box::new(Collection::iter(self))
+    }
+}
+
+#[cfg(feature = "named")]
+impl<T> SlotStorage<T> for crate::NamedCollection<T> {
+    fn add(&mut self, item: T) -> Result<usize, T> {
+        Ok(crate::NamedCollection::add(self, item))
+    }
+
+    fn take(&mut self, index: usize) -> Option<T> {
+        crate::NamedCollection::take(self, index)
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        Collection::get(self, index)
+    }
+
+    fn len(&self) -> usize {
+        Collection::len(self) as usize
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        Box::new(Collection::iter(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(storage: &mut dyn SlotStorage<u8>) -> Vec<usize> {
+        (0..3).map_while(|i| storage.add(i).ok()).collect()
+    }
+
+    #[test]
+    fn storages_can_be_used_through_the_trait() {
+        let mut owned = Collection::new();
+        assert_eq!(fill(&mut owned), vec![0, 1, 2]);
+
+        let mut slots = [None, None];
+        let mut borrowed = StaticCollection::new(&mut slots);
+        assert_eq!(fill(&mut borrowed), vec![0, 1]);
+
+        let mut small = SmallCollection::<_, 2>::new();
+        assert_eq!(fill(&mut small), vec![0, 1]);
+
+        let storages: [&mut dyn SlotStorage<u8>; 3] = [&mut owned, &mut borrowed, &mut small];
+        for storage in storages {
+            assert_eq!(storage.take(0), Some(0));
+            assert_eq!(storage.get(1), Some(&1));
+            assert_eq!(storage.iter().count(), storage.len());
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Helpers for downstream tests.
+//!
+//! `assert_invariants` cross-checks a collection's bookkeeping against its
+//! slots, `ops::Script` replays sequences of operations against a model and
+//! shrinks failing ones, and `PoisonedCollection` catches use of stale
+//! indices in debug builds.
+use std::backtrace::Backtrace;
+use std::fmt;
+use std::mem;
+
+use crate::Collection;
+
+pub mod ops;
+
+/// Returns a description of the first broken invariant, if any.
+pub fn check_invariants<T, Tag>(collection: &Collection<T, Tag>) -> Result<(), String> {
+    let slots = collection.inner.len();
+    let occupied = collection
+        .inner
+        .iter()
+        .filter(|slot| slot.is_some())
+        .count();
+
+    if occupied != collection.len() as usize {
+        return Err(format!(
+            "len() is {} but {} slots are occupied",
+            collection.len(),
+            occupied
+        ));
+    }
+
+    let free: Vec<_> = collection.free.iter().collect();
+    let vacant: Vec<_> = (0..slots)
+        .filter(|&index| collection.inner[index].is_none() && collection.is_reusable(index))
+        .collect();
+    if free != vacant {
+        return Err(format!(
+            "free list {:?} doesn't match vacant slots {:?}",
+            free, vacant
+        ));
+    }
+
+    collection.groups.check(&collection.inner)
+}
+
+#[track_caller]
+pub fn assert_invariants<T, Tag>(collection: &Collection<T, Tag>) {
+    if let Err(violation) = check_invariants(collection) {
+        panic!("Collection invariant violated: {}", violation);
+    }
+}
+
+enum Entry<T> {
+    Live(T),
+    Poisoned(Backtrace),
+}
+
+/// A collection that never reuses freed indices in debug builds and panics,
+/// showing where the index was freed, whenever a freed index is used.
+///
+/// In release builds it behaves like a plain `Collection`.
+pub struct PoisonedCollection<T> {
+    inner: Collection<Entry<T>>,
+    live: usize,
+}
+
+impl<T> PoisonedCollection<T> {
+    pub fn new() -> Self {
+        Self {
+            inner: Collection::new(),
+            live: 0,
+        }
+    }
+
+    pub fn add(&mut self, item: T) -> usize {
+        self.live += 1;
+        self.inner.add(Entry::Live(item))
+    }
+
+    /// Takes the value at `index`, or returns `None` if nothing was ever
+    /// added there. Panics if it was already taken.
+    #[track_caller]
+    pub fn take(&mut self, index: usize) -> Option<T> {
+        self.check(index);
+
+        let item = if cfg!(debug_assertions) {
+            let entry = self.inner.get_mut(index)?;
+            let poison = Entry::Poisoned(Backtrace::force_capture());
+            mem::replace(entry, poison)
+        } else {
+            self.inner.try_take(index)?
+        };
+        self.live -= 1;
+        match item {
+            Entry::Live(item) => Some(item),
+            Entry::Poisoned(_) => unreachable!(),
+        }
+    }
+
+    #[track_caller]
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.check(index);
+        match self.inner.get(index)? {
+            Entry::Live(item) => Some(item),
+            Entry::Poisoned(_) => None,
+        }
+    }
+
+    #[track_caller]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.check(index);
+        match self.inner.get_mut(index)? {
+            Entry::Live(item) => Some(item),
+            Entry::Poisoned(_) => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live == 0
+    }
+
+    #[track_caller]
+    fn check(&self, index: usize) {
+        if let Some(Entry::Poisoned(freed_at)) = self.inner.get(index) {
+            panic!("Index {} used after it was freed at:\n{}", index, freed_at);
+        }
+    }
+}
+
+impl<T> Default for PoisonedCollection<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PoisonedCollection<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.inner
+                    .inner
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, slot)| match slot {
+                        Some(Entry::Live(item)) => Some((i, item)),
+                        _ => None,
+                    }),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(feature = "strict"))]
+    #[test]
+    fn detects_out_of_sync_bookkeeping() {
+        let mut coll = Collection::new();
+        coll.add(1);
+        coll.add(2);
+        assert_invariants(&coll);
+
+        coll[0] = None;
+        let violation = check_invariants(&coll).unwrap_err();
+        assert_eq!(violation, "len() is 2 but 1 slots are occupied");
+    }
+
+    #[test]
+    fn reserved_slots_arent_expected_on_the_free_list() {
+        let mut coll = Collection::new();
+        coll.add(1);
+        coll.reserve_block(2);
+        coll.add(2);
+        coll.take(0);
+        assert_invariants(&coll);
+    }
+
+    #[test]
+    fn poisoned_collection_doesnt_reuse_freed_indices() {
+        let mut coll = PoisonedCollection::new();
+        let a = coll.add("a");
+        coll.take(a);
+
+        let b = coll.add("b");
+        assert_eq!(coll.len(), 1);
+        assert_eq!(coll.take(b + 1), None);
+        assert_eq!(coll.len(), 1);
+        if cfg!(debug_assertions) {
+            assert_ne!(a, b);
+            let stale = std::panic::catch_unwind(|| coll.get(a).copied());
+            assert!(stale.is_err());
+        }
+    }
+}
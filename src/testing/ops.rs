@@ -0,0 +1,155 @@
+//! Scripted operation sequences checked against a simple model.
+use std::fmt::Debug;
+
+use super::check_invariants;
+use crate::Collection;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step<T> {
+    Add(T),
+    Take(usize),
+}
+
+/// A sequence of operations replayed against both a collection and a
+/// `Vec<Option<T>>` model. After every step the collection has to satisfy its
+/// invariants and every index the model considers live has to still resolve
+/// to the value it was added with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script<T> {
+    steps: Vec<Step<T>>,
+}
+
+impl<T: Clone + PartialEq + Debug> Script<T> {
+    pub fn new(steps: Vec<Step<T>>) -> Self {
+        Self { steps }
+    }
+
+    pub fn steps(&self) -> &[Step<T>] {
+        &self.steps
+    }
+
+    /// Runs the script, returning the final collection or a description of
+    /// the first step that went wrong.
+    pub fn check(&self) -> Result<Collection<T>, String> {
+        let mut collection = Collection::new();
+        let mut model: Vec<Option<T>> = Vec::new();
+
+        for (n, step) in self.steps.iter().enumerate() {
+            match step {
+                Step::Add(value) => {
+                    let index = collection.add(value.clone());
+                    if model.get(index).is_some_and(Option::is_some) {
+                        return Err(format!("step {}: add reused live index {}", n, index));
+                    }
+                    if model.len() <= index {
+                        model.resize(index + 1, None);
+                    }
+                    model[index] = Some(value.clone());
+                }
+                Step::Take(index) => {
+                    let expected = model.get_mut(*index).and_then(Option::take);
+                    let taken = collection.try_take(*index);
+                    if taken != expected {
+                        return Err(format!(
+                            "step {}: take({}) returned {:?}, expected {:?}",
+                            n, index, taken, expected
+                        ));
+                    }
+                }
+            }
+
+            check_invariants(&collection).map_err(|e| format!("step {}: {}", n, e))?;
+
+            for (index, value) in model.iter().enumerate() {
+                if let Some(value) = value {
+                    if collection.get(index) != Some(value) {
+                        return Err(format!(
+                            "step {}: index {} no longer holds {:?}",
+                            n, index, value
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(collection)
+    }
+
+    #[track_caller]
+    pub fn run(&self) -> Collection<T> {
+        match self.check() {
+            Ok(collection) => collection,
+            Err(e) => panic!("Script failed at {}", e),
+        }
+    }
+
+    /// Removes steps for as long as `fails` keeps returning `true`, giving a
+    /// (locally) minimal script that still reproduces the failure.
+    pub fn shrink(&self, mut fails: impl FnMut(&Script<T>) -> bool) -> Script<T> {
+        let mut current = self.clone();
+        let mut chunk = (current.steps.len() / 2).max(1);
+
+        loop {
+            let mut start = 0;
+            let mut shrunk = false;
+
+            while start < current.steps.len() {
+                let mut candidate = current.clone();
+                let end = (start + chunk).min(candidate.steps.len());
+                candidate.steps.drain(start..end);
+
+                if fails(&candidate) {
+                    current = candidate;
+                    shrunk = true;
+                } else {
+                    start += chunk;
+                }
+            }
+
+            if !shrunk {
+                if chunk == 1 {
+                    return current;
+                }
+                chunk /= 2;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_runs_against_model() {
+        let script = Script::new(vec![
+            Step::Add('a'),
+            Step::Add('b'),
+            Step::Take(0),
+            Step::Take(0),
+            Step::Add('c'),
+        ]);
+
+        let coll = script.run();
+        assert_eq!(coll.get(0), Some(&'c'));
+    }
+
+    #[test]
+    fn shrink_keeps_only_relevant_steps() {
+        let mut steps = Vec::new();
+        for i in 0..20 {
+            steps.push(Step::Add(i));
+        }
+        steps.push(Step::Take(7));
+        steps.push(Step::Add(99));
+
+        // Pretend a value of 99 ending up at index 7 is a bug
+        let fails = |s: &Script<i32>| s.check().map_or(true, |c| c.get(7) == Some(&99));
+        let minimal = Script::new(steps).shrink(fails);
+
+        // The take isn't needed, 99 lands at index 7 after seven other adds
+        assert!(fails(&minimal));
+        assert_eq!(minimal.steps().len(), 8);
+        assert_eq!(minimal.steps()[7], Step::Add(99));
+    }
+}
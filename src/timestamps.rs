@@ -0,0 +1,143 @@
+//! When each occupied slot was filled.
+//!
+//! With the `time` feature every slot remembers the `Instant` its current
+//! value was stored. Without it `InsertionTimes` is empty and recording
+//! compiles to nothing.
+#[cfg(feature = "time")]
+use std::time::Instant;
+
+#[cfg(feature = "time")]
+use allocator_api2::alloc::Allocator;
+
+#[cfg(feature = "time")]
+use crate::Collection;
+
+#[cfg(feature = "time")]
+#[derive(Debug, Default)]
+pub(crate) struct InsertionTimes(Vec<Option<Instant>>);
+
+#[cfg(not(feature = "time"))]
+#[derive(Debug, Default)]
+pub(crate) struct InsertionTimes;
+
+#[cfg(feature = "time")]
+impl InsertionTimes {
+    pub(crate) const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    // Records that `index` was just filled
+    pub(crate) fn stamp(&mut self, index: usize) {
+        if self.0.len() <= index {
+            self.0.resize(index + 1, None);
+        }
+        self.0[index] = Some(Instant::now());
+    }
+
+    // A value moved from `old` to `new`, its time goes with it
+    pub(crate) fn moved(&mut self, old: usize, new: usize) {
+        let time = self.get(old);
+        if self.0.len() <= new {
+            self.0.resize(new + 1, None);
+        }
+        self.0[new] = time;
+    }
+
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        let (time_a, time_b) = (self.get(a), self.get(b));
+        self.0.resize(self.0.len().max(a + 1).max(b + 1), None);
+        self.0[a] = time_b;
+        self.0[b] = time_a;
+    }
+
+    fn get(&self, index: usize) -> Option<Instant> {
+        self.0.get(index).copied().flatten()
+    }
+}
+
+#[cfg(not(feature = "time"))]
+impl InsertionTimes {
+    pub(crate) const fn new() -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    pub(crate) fn stamp(&mut self, _index: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn moved(&mut self, _old: usize, _new: usize) {}
+
+    #[inline(always)]
+    pub(crate) fn swap(&mut self, _a: usize, _b: usize) {}
+}
+
+#[cfg(feature = "time")]
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// When the value at `index` was stored.
+    pub fn inserted_at(&self, index: usize) -> Option<Instant> {
+        self.get(index)?;
+        self.inserted.get(index)
+    }
+
+    /// Iterates `(index, value)` over the values stored at or after `since`,
+    /// in slot order.
+    pub fn iter_inserted_since(&self, since: Instant) -> impl Iterator<Item = (usize, &T)> {
+        self.inner
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, slot)| {
+                let item = slot.as_ref()?;
+                let time = self.inserted.get(index)?;
+                if time >= since {
+                    Some((index, item))
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// The least recently stored value. Takes O(slots).
+    pub fn oldest(&self) -> Option<(usize, &T)> {
+        self.inner
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| Some((self.inserted.get(index)?, index, slot.as_ref()?)))
+            .min_by_key(|&(time, index, _)| (time, index))
+            .map(|(_, index, item)| (index, item))
+    }
+}
+
+#[cfg(all(test, feature = "time"))]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn tracks_insertion_order_across_reuse_and_moves() {
+        let mut coll = Collection::new();
+        coll.add('a');
+        coll.add('b');
+        thread::sleep(Duration::from_millis(2));
+        let since = Instant::now();
+        coll.add('c');
+        coll.add('d');
+
+        let recent: Vec<_> = coll.iter_inserted_since(since).collect();
+        assert_eq!(recent, vec![(2, &'c'), (3, &'d')]);
+        assert_eq!(coll.oldest(), Some((0, &'a')));
+
+        // Reusing a slot gives it a new time
+        coll.take(0);
+        coll.add('e');
+        assert_eq!(coll.oldest(), Some((1, &'b')));
+        assert!(coll.inserted_at(0).unwrap() >= since);
+
+        // Compacting moves the time with the value
+        let d = coll.inserted_at(3);
+        coll.take(2);
+        coll.compact(|_, _| ());
+        assert_eq!(coll.get(2), Some(&'d'));
+        assert_eq!(coll.inserted_at(2), d);
+    }
+}
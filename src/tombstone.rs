@@ -0,0 +1,117 @@
+//! Reasons left behind in vacated slots.
+//!
+//! `take_with_tombstone` records why a value was taken, so code holding a
+//! stale index or key can say what happened to it instead of just that it's
+//! gone. A tombstone disappears as soon as its slot is reused.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::{Collection, Key};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tombstone {
+    reason: String,
+    // The generation of the value that was taken
+    generation: u32,
+}
+
+impl Tombstone {
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for Tombstone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.reason)
+    }
+}
+
+// Tombstones aren't removed when their slot is reused, they're recognized as
+// outdated by the generation having moved on or the slot being occupied
+#[derive(Debug, Default)]
+pub(crate) struct Tombstones(BTreeMap<usize, Tombstone>);
+
+impl Tombstones {
+    pub(crate) const fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+
+    // Forgets the tombstones of slots that no longer exist
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.0.split_off(&len);
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Like `try_take`, but leaves `reason` behind in the slot until it's
+    /// reused.
+    pub fn take_with_tombstone(&mut self, index: usize, reason: impl Into<String>) -> Option<T> {
+        let generation = self.generations.get(index);
+        let item = self.try_take(index)?;
+        self.tombstones.0.insert(
+            index,
+            Tombstone {
+                reason: reason.into(),
+                generation,
+            },
+        );
+        Some(item)
+    }
+
+    /// Why the value at `index` was taken, if it was taken with
+    /// `take_with_tombstone` and nothing has been stored there since.
+    pub fn tombstone(&self, index: usize) -> Option<&Tombstone> {
+        let tombstone = self.tombstones.0.get(&index)?;
+        let current = self.generations.get(index) == tombstone.generation.wrapping_add(1);
+        if current && self.get(index).is_none() {
+            Some(tombstone)
+        } else {
+            None
+        }
+    }
+
+    /// Why the value `key` referred to was taken. Unlike `tombstone` this
+    /// keeps working after the slot has been reused.
+    pub fn tombstone_for_key(&self, key: Key<Tag>) -> Option<&Tombstone> {
+        self.tombstones
+            .0
+            .get(&key.index())
+            .filter(|tombstone| tombstone.generation == key.generation())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tombstones_explain_stale_handles() {
+        let mut coll = Collection::new();
+        let conn = coll.add_key("conn");
+        coll.add("other");
+
+        coll.take_with_tombstone(conn.index(), "closed by peer at t=17");
+        assert_eq!(
+            coll.tombstone(0).unwrap().reason(),
+            "closed by peer at t=17"
+        );
+        assert_eq!(coll.tombstone(1), None);
+        assert_eq!(coll.take_with_tombstone(0, "again"), None);
+
+        let next = coll.add_key("next");
+        assert_eq!(next.index(), conn.index());
+        assert_eq!(coll.tombstone(0), None);
+        assert_eq!(
+            coll.tombstone_for_key(conn).map(ToString::to_string),
+            Some("closed by peer at t=17".to_string())
+        );
+        assert_eq!(coll.tombstone_for_key(next), None);
+
+        // Taking again without a reason doesn't resurrect the old one
+        coll.take(0);
+        assert_eq!(coll.tombstone(0), None);
+    }
+}
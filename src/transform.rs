@@ -0,0 +1,106 @@
+//! Turning a collection of one type into a collection of another.
+//!
+//! Both conversions keep every index, vacancy and key, so anything that
+//! refers into the old collection refers to the same values in the new one.
+use std::mem;
+
+use crate::{raw, Collection};
+
+impl<T, Tag> Collection<T, Tag> {
+    /// Converts every value with `f`. The slot storage is reused when `U`
+    /// has the same size and alignment as `T`.
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> Collection<U, Tag> {
+        self.filter_map(|item| Some(f(item)))
+    }
+
+    /// Like `map`, but values `f` returns `None` for are dropped and their
+    /// slots vacated, same as if they had been taken.
+    pub fn filter_map<U>(mut self, mut f: impl FnMut(T) -> Option<U>) -> Collection<U, Tag> {
+        let mut was_occupied = Vec::new();
+        // Through `Vec`, whose `collect` reuses the buffer
+        let inner: Vec<_> = raw::into_vec(mem::take(&mut self.inner))
+            .into_iter()
+            .map(|slot| {
+                was_occupied.push(slot.is_some());
+                slot.and_then(&mut f)
+            })
+            .collect();
+
+        let mut coll = Collection {
+            inner: raw::from_vec(inner),
+            occupied: self.occupied,
+            free: mem::take(&mut self.free),
+            generations: mem::take(&mut self.generations),
+            groups: mem::take(&mut self.groups),
+            auto_compact: mem::take(&mut self.auto_compact),
+            pending_removals: mem::take(&mut self.pending_removals),
+            tombstones: mem::take(&mut self.tombstones),
+            inserted: mem::take(&mut self.inserted),
+            stats: mem::take(&mut self.stats),
+            on_growth: mem::take(&mut self.on_growth),
+            leaks: mem::take(&mut self.leaks),
+            occupancy: mem::take(&mut self.occupancy),
+            bitmap: mem::take(&mut self.bitmap),
+            blocks: mem::take(&mut self.blocks),
+            leases: mem::take(&mut self.leases),
+            recent_adds: mem::take(&mut self.recent_adds),
+            structure_changes: self.structure_changes,
+            touched_through_index_mut: self.touched_through_index_mut,
+            tag: self.tag,
+        };
+
+        for (index, was_occupied) in was_occupied.into_iter().enumerate() {
+            if was_occupied && coll.inner[index].is_none() {
+                coll.groups.unlink(index);
+                coll.generations.bump(index);
+                if coll.is_reusable(index) {
+                    coll.free.push_back(index);
+                }
+                coll.count_vacated();
+                coll.stats.vacated(index);
+                coll.leaks.vacated(index);
+                coll.occupancy.vacated(index);
+                coll.pending_removals.vacated(index);
+                coll.bitmap.vacated(index);
+                coll.structure_changes += 1;
+            }
+        }
+
+        coll.debug_assert_consistent();
+        coll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_keeps_indices_and_keys() {
+        let mut configs = Collection::new();
+        let a = configs.add_key("8080");
+        configs.add("skip");
+        configs.add("443");
+        configs.take(1);
+
+        let ports = configs.map(|s| s.len() as u16);
+        assert_eq!(ports.get_key(a), Some(&4));
+        assert_eq!(ports.get(1), None);
+        assert_eq!(ports.get(2), Some(&3));
+        assert_eq!(ports.len(), 2);
+    }
+
+    #[test]
+    fn filter_map_vacates_rejected_slots() {
+        let mut raw = Collection::new();
+        raw.add("1");
+        let bad = raw.add_key("x");
+        raw.add("3");
+
+        let mut parsed = raw.filter_map(|s| s.parse::<u32>().ok());
+        assert_eq!(parsed.to_owned_values(), vec![1, 3]);
+        assert_eq!(parsed.get_key(bad), None);
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed.add(2), 1);
+    }
+}
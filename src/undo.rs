@@ -0,0 +1,108 @@
+//! Rolling back the most recent adds.
+//!
+//! The collection remembers the index and generation of its last few adds,
+//! so code that adds a value and then fails validation can call
+//! `undo_last_add` instead of threading the index back to where the error
+//! is handled. An add that was already taken, or whose value compaction
+//! moved, is skipped.
+use std::collections::VecDeque;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+// How many adds can be undone, so the history never grows unbounded
+const REMEMBERED: usize = 16;
+
+#[derive(Debug, Default)]
+pub(crate) struct RecentAdds(VecDeque<(usize, u32)>);
+
+impl RecentAdds {
+    pub(crate) const fn new() -> Self {
+        Self(VecDeque::new())
+    }
+}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// The index of the most recent add that still holds its value.
+    pub fn last_added_index(&self) -> Option<usize> {
+        self.recent_adds
+            .0
+            .iter()
+            .rev()
+            .find(|&&(index, generation)| self.is_unchanged_since_add(index, generation))
+            .map(|&(index, _)| index)
+    }
+
+    /// Takes the value of the most recent add that still holds it. Call
+    /// again to undo the add before, up to the last 16.
+    pub fn undo_last_add(&mut self) -> Option<(usize, T)> {
+        while let Some((index, generation)) = self.recent_adds.0.pop_back() {
+            if self.is_unchanged_since_add(index, generation) {
+                return Some((index, self.try_take(index)?));
+            }
+        }
+        None
+    }
+
+    // Call from every `add`
+    pub(crate) fn note_added(&mut self, index: usize) {
+        let adds = &mut self.recent_adds.0;
+        if adds.len() == REMEMBERED {
+            adds.pop_front();
+        }
+        adds.push_back((index, self.generations.get(index)));
+    }
+
+    // Which remembered adds still hold their value, for `refresh_adds`
+    pub(crate) fn undoable_adds(&self) -> Vec<bool> {
+        self.recent_adds
+            .0
+            .iter()
+            .map(|&(index, generation)| self.is_unchanged_since_add(index, generation))
+            .collect()
+    }
+
+    // Takes over the current generations for the adds `undoable_adds`
+    // reported, after something bumped them without moving the values
+    pub(crate) fn refresh_adds(&mut self, undoable: &[bool]) {
+        for (add, &undoable) in self.recent_adds.0.iter_mut().zip(undoable) {
+            if undoable {
+                add.1 = self.generations.get(add.0);
+            }
+        }
+    }
+
+    fn is_unchanged_since_add(&self, index: usize, generation: u32) -> bool {
+        self.generations.get(index) == generation && self.get(index).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undoes_adds_newest_first_and_skips_taken_ones() {
+        let mut coll = Collection::new();
+        let a = coll.add("a");
+        let b = coll.add("b");
+        let c = coll.add("c");
+        assert_eq!(coll.last_added_index(), Some(c));
+
+        coll.take(c);
+        assert_eq!(coll.last_added_index(), Some(b));
+        // Reusing the slot doesn't make the old add undoable again
+        coll.insert_at(c, "not added");
+        assert_eq!(coll.undo_last_add(), Some((b, "b")));
+        assert_eq!(coll.undo_last_add(), Some((a, "a")));
+        assert_eq!(coll.undo_last_add(), None);
+        assert_eq!(coll.get(c), Some(&"not added"));
+
+        for _ in 0..20 {
+            coll.add("x");
+        }
+        let undone = std::iter::from_fn(|| coll.undo_last_add()).count();
+        assert_eq!(undone, 16);
+    }
+}
@@ -0,0 +1,133 @@
+//! Richer diagnostics for values that describe themselves.
+//!
+//! Values implementing `SlotValue` get labelled lines in `dump_labels`, a
+//! byte count in `value_bytes` and, with `leak-detect`, labels next to the
+//! handles that were never freed. Both methods have defaults, so opting in
+//! is an empty `impl`.
+use std::any;
+use std::io::{self, Write};
+use std::mem;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::Collection;
+
+pub trait SlotValue {
+    /// A short description of the value, the type name by default.
+    fn debug_label(&self) -> &str {
+        any::type_name::<Self>()
+    }
+
+    /// Bytes the value takes up, including what it owns on the heap.
+    /// Defaults to only its inline size.
+    fn size_hint(&self) -> usize {
+        mem::size_of_val(self)
+    }
+}
+
+impl SlotValue for String {
+    fn debug_label(&self) -> &str {
+        self
+    }
+
+    fn size_hint(&self) -> usize {
+        mem::size_of::<Self>() + self.capacity()
+    }
+}
+
+impl<T> SlotValue for Vec<T> {
+    fn size_hint(&self) -> usize {
+        mem::size_of::<Self>() + self.capacity() * mem::size_of::<T>()
+    }
+}
+
+impl<T: SlotValue + ?Sized> SlotValue for Box<T> {
+    fn debug_label(&self) -> &str {
+        (**self).debug_label()
+    }
+
+    fn size_hint(&self) -> usize {
+        mem::size_of::<Self>() + (**self).size_hint()
+    }
+}
+
+impl<T: SlotValue, Tag, A: Allocator> Collection<T, Tag, A> {
+    /// Like `dump`, with every value's label and size instead of its
+    /// `Debug` output.
+    pub fn dump_labels<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut w = io::BufWriter::new(w);
+        writeln!(w, "Collection {{")?;
+        writeln!(w, "    len: {},", self.occupied)?;
+        for (index, slot) in self.inner.iter().enumerate() {
+            match slot {
+                Some(value) => writeln!(
+                    w,
+                    "    [{}] occupied: {} ({} bytes)",
+                    index,
+                    value.debug_label(),
+                    value.size_hint()
+                )?,
+                None => writeln!(w, "    [{}] vacant", index)?,
+            }
+        }
+        writeln!(w, "}}")?;
+        w.flush()
+    }
+
+    /// The `size_hint` of every value added up.
+    pub fn value_bytes(&self) -> usize {
+        self.inner.iter().flatten().map(T::size_hint).sum()
+    }
+
+    /// `outstanding_handles` along with the label of each value.
+    #[cfg(feature = "leak-detect")]
+    pub fn outstanding_labels(&self) -> Vec<(usize, &str)> {
+        self.outstanding_handles()
+            .into_iter()
+            .filter_map(|index| Some((index, self.get(index)?.debug_label())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Session {
+        user: String,
+    }
+
+    impl SlotValue for Session {
+        fn debug_label(&self) -> &str {
+            &self.user
+        }
+    }
+
+    #[test]
+    fn dump_uses_labels_and_sizes() {
+        let mut coll = Collection::new();
+        coll.add(Session {
+            user: "ana".to_string(),
+        });
+        coll.add(Session {
+            user: "bo".to_string(),
+        });
+        coll.take(0);
+
+        let mut out = Vec::new();
+        coll.dump_labels(&mut out).unwrap();
+        let size = mem::size_of::<Session>();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "Collection {{\n    len: 1,\n    [0] vacant\n    [1] occupied: bo ({} bytes)\n}}\n",
+                size
+            )
+        );
+        assert_eq!(coll.value_bytes(), size);
+
+        let mut names = Collection::new();
+        names.add(String::with_capacity(10));
+        assert_eq!(names.value_bytes(), mem::size_of::<String>() + 10);
+    }
+}
@@ -0,0 +1,163 @@
+//! Optimistic updates: read a value with its version, compute a new one,
+//! and store it only if nobody changed the slot in the meantime.
+//!
+//! For a `Collection` the version is the slot's generation, so a successful
+//! `compare_and_set` makes keys to the old value stale like `insert_at`
+//! does. A `ConcurrentCollection` keeps a sequence number per slot instead,
+//! bumped by every add, take, `compare_and_set` and exclusive `lock`.
+use std::error::Error;
+use std::fmt;
+
+use allocator_api2::alloc::Allocator;
+
+use crate::{Collection, ConcurrentCollection, SlotRef};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Version(pub(crate) u64);
+
+/// Returned by `compare_and_set` when the slot changed since it was read.
+/// Hands back the value that wasn't stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionConflict<T> {
+    pub index: usize,
+    pub expected: Version,
+    /// `None` if the slot is vacant.
+    pub current: Option<Version>,
+    pub value: T,
+}
+
+impl<T> fmt::Display for VersionConflict<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.current {
+            Some(current) => write!(
+                f,
+                "slot {} is at version {} instead of {}",
+                self.index, current.0, self.expected.0
+            ),
+            None => write!(f, "slot {} is vacant", self.index),
+        }
+    }
+}
+
+impl<T: fmt::Debug> Error for VersionConflict<T> {}
+
+impl<T, Tag, A: Allocator> Collection<T, Tag, A> {
+    pub fn read_versioned(&self, index: usize) -> Option<(Version, &T)> {
+        let item = self.get(index)?;
+        Some((Version(u64::from(self.generations.get(index))), item))
+    }
+
+    /// Replaces the value at `index` if it's still at `version`.
+    pub fn compare_and_set(
+        &mut self,
+        index: usize,
+        version: Version,
+        value: T,
+    ) -> Result<(), VersionConflict<T>> {
+        let current = self.read_versioned(index).map(|(current, _)| current);
+        if current != Some(version) {
+            return Err(VersionConflict {
+                index,
+                expected: version,
+                current,
+                value,
+            });
+        }
+
+        self.insert_at(index, value);
+        Ok(())
+    }
+}
+
+impl<T> ConcurrentCollection<T> {
+    pub fn read_versioned(&self, index: usize) -> Option<(Version, SlotRef<'_, T>)> {
+        let slot = self.get(index)?;
+        // Writers bump the version under the write lock, which our read
+        // lock keeps them from holding
+        let version = self.version(index)?;
+        Some((version, slot))
+    }
+
+    /// Replaces the value at `index` if it's still at `version`.
+    pub fn compare_and_set(
+        &self,
+        index: usize,
+        version: Version,
+        value: T,
+    ) -> Result<(), VersionConflict<T>> {
+        let mut guard = match self.lock(index) {
+            Some(guard) => guard,
+            None => {
+                return Err(VersionConflict {
+                    index,
+                    expected: version,
+                    current: None,
+                    value,
+                })
+            }
+        };
+
+        // The guard bumps the version once it's dropped
+        let current = guard.version();
+        if current != version {
+            return Err(VersionConflict {
+                index,
+                expected: version,
+                current: Some(current),
+                value,
+            });
+        }
+        *guard = value;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_versions_are_rejected() {
+        let mut coll = Collection::new();
+        let index = coll.add(1);
+        let (version, &value) = coll.read_versioned(index).unwrap();
+
+        assert_eq!(coll.compare_and_set(index, version, value + 1), Ok(()));
+        let conflict = coll
+            .compare_and_set(index, version, value + 10)
+            .unwrap_err();
+        assert_eq!(conflict.value, 11);
+        assert_eq!(coll.get(index), Some(&2));
+
+        coll.take(index);
+        let conflict = coll.compare_and_set(index, version, 3).unwrap_err();
+        assert_eq!(conflict.current, None);
+    }
+
+    #[test]
+    fn concurrent_versions_follow_every_write() {
+        let coll = ConcurrentCollection::new();
+        let index = coll.add("a");
+        let (version, value) = coll.read_versioned(index).unwrap();
+        assert_eq!(*value, "a");
+        drop(value);
+
+        coll.compare_and_set(index, version, "b").unwrap();
+        let conflict = coll.compare_and_set(index, version, "c").unwrap_err();
+        assert_eq!(
+            conflict.to_string(),
+            format!(
+                "slot {} is at version {} instead of {}",
+                index,
+                version.0 + 1,
+                version.0
+            )
+        );
+
+        // Taking and re-adding counts too, even though the index is the same
+        let (version, _) = coll.read_versioned(index).unwrap();
+        coll.take(index);
+        coll.add("d");
+        assert!(coll.compare_and_set(index, version, "e").is_err());
+    }
+}
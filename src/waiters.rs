@@ -0,0 +1,143 @@
+//! Waiting for a `ConcurrentCollection` to have room.
+//!
+//! With the `futures` feature `wait_for_free_slot` returns a future that
+//! resolves once a slot is free, and `take` wakes every waiting task.
+//! Without it `Waiters` is empty and `take` has nothing to wake.
+#[cfg(feature = "futures")]
+use std::future::Future;
+#[cfg(feature = "futures")]
+use std::pin::Pin;
+#[cfg(feature = "futures")]
+use std::sync::{Mutex, PoisonError};
+#[cfg(feature = "futures")]
+use std::task::{Context, Poll, Waker};
+
+#[cfg(feature = "futures")]
+use crate::ConcurrentCollection;
+
+#[cfg(feature = "futures")]
+#[derive(Default)]
+pub(crate) struct Waiters(Mutex<Vec<Waker>>);
+
+#[cfg(not(feature = "futures"))]
+#[derive(Default)]
+pub(crate) struct Waiters;
+
+#[cfg(feature = "futures")]
+impl Waiters {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    // Call after a slot has been freed
+    pub(crate) fn wake_all(&self) {
+        let wakers = std::mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner));
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(not(feature = "futures"))]
+impl Waiters {
+    pub(crate) fn new() -> Self {
+        Self
+    }
+
+    #[inline(always)]
+    pub(crate) fn wake_all(&self) {}
+}
+
+/// Returned by `ConcurrentCollection::wait_for_free_slot`.
+#[cfg(feature = "futures")]
+#[must_use = "futures do nothing unless polled"]
+pub struct WaitForFreeSlot<'a, T> {
+    collection: &'a ConcurrentCollection<T>,
+}
+
+#[cfg(feature = "futures")]
+impl<'a, T> Future for WaitForFreeSlot<'a, T> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let collection = self.collection;
+        // Checking under the lock means a `take` either frees its slot before
+        // the check or wakes us after we're registered
+        let mut wakers = collection
+            .waiters
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if collection.has_free_slot() {
+            return Poll::Ready(());
+        }
+
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T> ConcurrentCollection<T> {
+    /// Resolves once a slot is free. Another producer may get to it first,
+    /// so follow it up with `try_add` in a loop.
+    pub fn wait_for_free_slot(&self) -> WaitForFreeSlot<'_, T> {
+        WaitForFreeSlot { collection: self }
+    }
+}
+
+#[cfg(all(test, feature = "futures"))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn producers_wake_up_when_a_slot_is_taken() {
+        let coll = ConcurrentCollection::with_segments(2, 1);
+        coll.add('a');
+        coll.add('b');
+        assert_eq!(coll.try_add('c'), Err('c'));
+
+        thread::scope(|scope| {
+            let producer = scope.spawn(|| {
+                let mut item = 'c';
+                loop {
+                    block_on(coll.wait_for_free_slot());
+                    match coll.try_add(item) {
+                        Ok(index) => return index,
+                        Err(back) => item = back,
+                    }
+                }
+            });
+
+            thread::sleep(std::time::Duration::from_millis(10));
+            coll.take(1);
+            assert_eq!(producer.join().unwrap(), 1);
+        });
+        assert_eq!(coll.len(), 2);
+    }
+}
@@ -0,0 +1,206 @@
+//! Slots with weights, for picking values in proportion to them.
+//!
+//! The weights live in a Fenwick tree, so changing one and drawing a
+//! weighted random slot both take O(log slots). A vacant slot weighs 0, and
+//! a reused one starts over with the weight it's added with.
+#[cfg(feature = "rand")]
+use rand::Rng;
+use std::ops::Deref;
+
+use crate::Collection;
+
+pub struct WeightedCollection<T, Tag = ()> {
+    collection: Collection<T, Tag>,
+    weights: Vec<u64>,
+    // 1-based, `tree[i]` sums the `i & i.wrapping_neg()` weights ending at
+    // slot `i - 1`. Sized to a power of two so a draw can descend it.
+    tree: Vec<u64>,
+}
+
+impl<T> WeightedCollection<T> {
+    pub fn new() -> Self {
+        Self::new_tagged()
+    }
+}
+
+impl<T, Tag> WeightedCollection<T, Tag> {
+    pub fn new_tagged() -> Self {
+        Self {
+            collection: Collection::new_tagged(),
+            weights: Vec::new(),
+            tree: vec![0],
+        }
+    }
+
+    pub fn add(&mut self, item: T, weight: u64) -> usize {
+        let index = self.collection.add(item);
+        self.update(index, weight);
+        index
+    }
+
+    pub fn take(&mut self, index: usize) -> Option<T> {
+        let item = self.collection.try_take(index)?;
+        self.update(index, 0);
+        Some(item)
+    }
+
+    /// Returns `false` without changing anything if the slot is vacant.
+    pub fn set_weight(&mut self, index: usize, weight: u64) -> bool {
+        if self.collection.get(index).is_none() {
+            return false;
+        }
+        self.update(index, weight);
+        true
+    }
+
+    pub fn weight(&self, index: usize) -> u64 {
+        self.weights.get(index).copied().unwrap_or(0)
+    }
+
+    pub fn total_weight(&self) -> u64 {
+        self.prefix_sum(self.tree.len() - 1)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.collection.get_mut(index)
+    }
+
+    /// Picks an occupied slot with probability proportional to its weight,
+    /// or `None` if every weight is 0.
+    #[cfg(feature = "rand")]
+    pub fn pick_weighted<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<(usize, &T)> {
+        let total = self.total_weight();
+        if total == 0 {
+            return None;
+        }
+        let index = self.find(rng.gen_range(0..total));
+        Some((index, self.collection.get(index)?))
+    }
+
+    pub fn into_inner(self) -> Collection<T, Tag> {
+        self.collection
+    }
+
+    fn update(&mut self, index: usize, weight: u64) {
+        if self.weights.len() <= index {
+            self.weights.resize(index + 1, 0);
+        }
+        if self.tree.len() <= index + 1 {
+            self.rebuild((index + 2).next_power_of_two());
+        }
+
+        let old = std::mem::replace(&mut self.weights[index], weight);
+        let mut i = index + 1;
+        while i < self.tree.len() {
+            self.tree[i] = self.tree[i].wrapping_sub(old).wrapping_add(weight);
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    // Recomputes the tree with room for `size - 1` slots
+    fn rebuild(&mut self, size: usize) {
+        self.tree = vec![0; size];
+        self.tree[1..=self.weights.len()].copy_from_slice(&self.weights);
+        for i in 1..size {
+            let parent = i + (i & i.wrapping_neg());
+            if parent < size {
+                self.tree[parent] += self.tree[i];
+            }
+        }
+    }
+
+    // Sum of the weights of slots `0..len`
+    fn prefix_sum(&self, mut len: usize) -> u64 {
+        let mut sum = 0;
+        while len > 0 {
+            sum += self.tree[len];
+            len -= len & len.wrapping_neg();
+        }
+        sum
+    }
+
+    // The slot whose share of the total covers `target`
+    #[cfg(feature = "rand")]
+    fn find(&self, mut target: u64) -> usize {
+        let mut pos = 0;
+        let mut step = (self.tree.len() - 1).next_power_of_two();
+        while step > 0 {
+            if pos + step < self.tree.len() && self.tree[pos + step] <= target {
+                pos += step;
+                target -= self.tree[pos];
+            }
+            step /= 2;
+        }
+        pos
+    }
+}
+
+impl<T> Default for WeightedCollection<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// No `DerefMut`, values taken through it would keep their weight
+impl<T, Tag> Deref for WeightedCollection<T, Tag> {
+    type Target = Collection<T, Tag>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.collection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights_follow_vacancy_and_reuse() {
+        let mut servers = WeightedCollection::new();
+        let small = servers.add("small", 1);
+        let big = servers.add("big", 8);
+        for i in 0..5 {
+            servers.add("filler", i);
+        }
+        assert_eq!(servers.total_weight(), 19);
+
+        servers.take(big);
+        assert_eq!(servers.total_weight(), 11);
+        assert_eq!(servers.add("medium", 4), big);
+        assert!(servers.set_weight(small, 3));
+        assert!(!servers.set_weight(99, 3));
+        assert_eq!(servers.total_weight(), 17);
+        assert_eq!(servers.prefix_sum(2), 7);
+
+        // Growing past a power of two rebuilds the tree
+        for _ in 0..10 {
+            servers.add("more", 2);
+        }
+        assert_eq!(servers.total_weight(), 37);
+        assert_eq!(servers.prefix_sum(9), 17 + 4);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn picks_in_proportion_to_weight() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let mut servers = WeightedCollection::new();
+        servers.add('a', 1);
+        servers.add('z', 0);
+        servers.add('b', 3);
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut counts = [0; 3];
+        for _ in 0..4000 {
+            counts[servers.pick_weighted(&mut rng).unwrap().0] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        assert!((2700..3300).contains(&counts[2]), "{:?}", counts);
+
+        // Each slot's range of targets
+        let owners: Vec<_> = (0..4).map(|target| servers.find(target)).collect();
+        assert_eq!(owners, vec![0, 2, 2, 2]);
+    }
+}
@@ -0,0 +1,212 @@
+//! Wiping the memory values leave behind.
+//!
+//! With the `zeroize` feature a slot is overwritten with zeros as soon as
+//! its value is taken, dropped or moved elsewhere, and the storage grows by
+//! copying into a new buffer and zeroing the old one before freeing it, so
+//! secrets like keys or tokens don't linger in memory the collection let go
+//! of. Without it the wipes compile to nothing and growth is left to `Vec`.
+//!
+//! Wiping the buffer when the collection itself is dropped takes a `Drop`
+//! impl, which `Collection` deliberately doesn't have, so that's left to
+//! the `WipeOnDrop` wrapper.
+#[cfg(feature = "zeroize")]
+use std::alloc::{handle_alloc_error, Layout};
+#[cfg(feature = "zeroize")]
+use std::mem::{ManuallyDrop, MaybeUninit};
+#[cfg(feature = "zeroize")]
+use std::ops::{Deref, DerefMut};
+#[cfg(feature = "zeroize")]
+use std::ptr::{self, NonNull};
+#[cfg(feature = "zeroize")]
+use std::slice;
+
+use allocator_api2::alloc::Allocator;
+#[cfg(feature = "zeroize")]
+use allocator_api2::alloc::Global;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+#[cfg(feature = "zeroize")]
+use crate::Collection;
+use crate::Slots;
+
+/// Zeroes a slot whose value was just taken out of it.
+#[cfg(feature = "zeroize")]
+pub(crate) fn vacated<T>(slot: &mut Option<T>) {
+    debug_assert!(slot.is_none());
+    let raw = (slot as *mut Option<T>).cast::<MaybeUninit<Option<T>>>();
+    // SAFETY: the slot is `None`, so overwriting it drops nothing, and it's
+    // a valid `None` again once that's written back over the zeros
+    unsafe {
+        (*raw).zeroize();
+        raw.write(MaybeUninit::new(None));
+    }
+}
+
+/// Zeroes the `count` slots right past the end, which were just popped,
+/// truncated or cleared.
+#[cfg(feature = "zeroize")]
+pub(crate) fn released<T, A: Allocator>(slots: &mut Slots<T, A>, count: usize) {
+    let spare = slots.spare_capacity_mut();
+    let count = count.min(spare.len());
+    spare[..count].zeroize();
+}
+
+/// Makes room for `additional` more slots, zeroing the old buffer if that
+/// takes a new one.
+#[cfg(feature = "zeroize")]
+pub(crate) fn reserve<T, A: Allocator>(slots: &mut Slots<T, A>, additional: usize) {
+    if slots.capacity() - slots.len() >= additional {
+        return;
+    }
+    // Same amortized growth as `Vec`
+    let capacity = slots
+        .len()
+        .checked_add(additional)
+        .expect("capacity overflow")
+        .max(slots.capacity() * 2)
+        .max(4);
+    let layout = Layout::array::<Option<T>>(capacity).expect("capacity overflow");
+    // Allocated through a reference since the `Vec` owns the allocator
+    let grown = match slots.allocator().allocate(layout) {
+        Ok(grown) => grown.cast::<Option<T>>(),
+        Err(_) => handle_alloc_error(layout),
+    };
+
+    let len = slots.len();
+    let old_capacity = slots.capacity();
+    // SAFETY: the new buffer fits all `len` values and doesn't overlap the
+    // old one. Nothing between the read and the write can panic, so the
+    // `Vec` is never owned twice, and the old buffer is zeroed and handed
+    // back to the allocator it came from with the layout it was given.
+    unsafe {
+        ptr::copy_nonoverlapping(slots.as_ptr(), grown.as_ptr(), len);
+        let (old, _, _, alloc) = ptr::read(slots).into_raw_parts_with_alloc();
+        ptr::write(
+            slots,
+            Slots::from_raw_parts_in(grown.as_ptr(), len, capacity, alloc),
+        );
+        if old_capacity > 0 {
+            slice::from_raw_parts_mut(old.cast::<MaybeUninit<Option<T>>>(), old_capacity).zeroize();
+            let old_layout = Layout::array::<Option<T>>(old_capacity).unwrap();
+            slots
+                .allocator()
+                .deallocate(NonNull::new_unchecked(old).cast(), old_layout);
+        }
+    }
+}
+
+/// A collection that zeroes its slots when dropped, on top of the wiping
+/// every collection does with the `zeroize` feature. The values are dropped
+/// first, the bookkeeping is left alone so the leak tracker still sees
+/// what was never freed.
+#[cfg(feature = "zeroize")]
+pub struct WipeOnDrop<T, Tag = (), A: Allocator = Global>(Collection<T, Tag, A>);
+
+#[cfg(feature = "zeroize")]
+impl<T, Tag, A: Allocator + Default> Default for WipeOnDrop<T, Tag, A> {
+    fn default() -> Self {
+        Self(Collection::default())
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<T, Tag, A: Allocator> WipeOnDrop<T, Tag, A> {
+    pub fn new(collection: Collection<T, Tag, A>) -> Self {
+        Self(collection)
+    }
+
+    /// Hands the collection back without wiping it.
+    pub fn into_inner(self) -> Collection<T, Tag, A> {
+        let this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never used or dropped again
+        unsafe { ptr::read(&this.0) }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<T, Tag, A: Allocator> Deref for WipeOnDrop<T, Tag, A> {
+    type Target = Collection<T, Tag, A>;
+
+    fn deref(&self) -> &Collection<T, Tag, A> {
+        &self.0
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<T, Tag, A: Allocator> DerefMut for WipeOnDrop<T, Tag, A> {
+    fn deref_mut(&mut self) -> &mut Collection<T, Tag, A> {
+        &mut self.0
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<T, Tag, A: Allocator> Drop for WipeOnDrop<T, Tag, A> {
+    fn drop(&mut self) {
+        let slots = &mut self.0.inner;
+        let len = slots.len();
+        slots.clear();
+        released(slots, len);
+    }
+}
+
+#[cfg(not(feature = "zeroize"))]
+#[inline(always)]
+pub(crate) fn vacated<T>(_slot: &mut Option<T>) {}
+
+#[cfg(not(feature = "zeroize"))]
+#[inline(always)]
+pub(crate) fn released<T, A: Allocator>(_slots: &mut Slots<T, A>, _count: usize) {}
+
+#[cfg(not(feature = "zeroize"))]
+#[inline(always)]
+pub(crate) fn reserve<T, A: Allocator>(slots: &mut Slots<T, A>, additional: usize) {
+    slots.reserve(additional);
+}
+
+#[cfg(all(test, feature = "zeroize"))]
+mod tests {
+    use super::*;
+
+    // Reads the bytes of a slot past the end
+    fn bytes_at<T>(slots: *const Option<T>, index: usize) -> Vec<u8> {
+        let size = std::mem::size_of::<Option<T>>();
+        // SAFETY: only called with indices within the capacity, on slots
+        // that were just zeroed
+        let raw = unsafe { slots.add(index).cast::<std::mem::MaybeUninit<u8>>() };
+        (0..size)
+            .map(|i| unsafe { raw.add(i).read().assume_init() })
+            .collect()
+    }
+
+    #[test]
+    fn taken_and_truncated_slots_are_zeroed() {
+        let secret = [0xAB_u8; 16];
+        let mut coll = Collection::new();
+        coll.reserve(4);
+        for _ in 0..3 {
+            coll.add(secret);
+        }
+        let slots = coll.inner.as_ptr();
+
+        coll.take(1);
+        coll.truncate(1);
+        assert!(bytes_at(slots, 1).iter().all(|&b| b == 0));
+        assert!(bytes_at(slots, 2).iter().all(|&b| b == 0));
+        assert_eq!(coll.get(0), Some(&secret));
+    }
+
+    #[test]
+    fn wrapper_hands_back_or_drops_the_values() {
+        let shared = std::rc::Rc::new(());
+        let mut wiped = WipeOnDrop::new(Collection::new());
+        wiped.add(std::rc::Rc::clone(&shared));
+        let coll = wiped.into_inner();
+        assert_eq!(coll.len(), 1);
+
+        let mut wiped = WipeOnDrop::new(coll);
+        wiped.add(std::rc::Rc::clone(&shared));
+        drop(wiped);
+        assert_eq!(std::rc::Rc::strong_count(&shared), 1);
+    }
+}